@@ -20,12 +20,16 @@ let options = SteganographyInjectOption {
             password: None,
             input_image_path: "testAssets/prestine.png".to_string(),
             output_image_path: "testAssets/image_with_secret_message.png".to_string(),
+            ..Default::default()
         };
 let result = steganography(SteganographyOption::InjectMessageIntoImage(options));
-assert_eq!(None, result)
+assert!(result.unwrap().is_none())
 ```
 
-You can add a password as a string to modify the message before insertion into the image.
+You can add a password as a string to modify the message before insertion into the image. The
+message is encrypted with AES-256-GCM using a key derived from the password with Argon2id and a
+random salt. A wrong password or an altered image is reported as `SteganographyError::BadPassword`.
+Images made with the AES-256-CBC encryption of the previous versions can still be extracted.
 
 ```rust
 use steganographyrs::steganography;
@@ -36,9 +40,10 @@ let options = SteganographyInjectOption {
     password: Some("Secret Password Here".to_string()),
     input_image_path: "testAssets/prestine.png".to_string(),
     output_image_path: "testAssets/image_with_secret_message.png".to_string(),
+    ..Default::default()
 };
 let result = steganography(SteganographyOption::InjectMessageIntoImage(options));
-assert_eq!(None, result)
+assert!(result.unwrap().is_none())
 ```
 
 ## Extract Text into Image
@@ -52,9 +57,10 @@ use steganographyrs::options::{SteganographyOption, SteganographyExtractOption};
 let options = SteganographyExtractOption {
     input_image_path: "testAssets/out_message_Bye_2.png".to_string(),
     password: None,
+    ..Default::default()
 };
 let recovered_message = steganography(SteganographyOption::ExtractMessageFromImage(options)).unwrap();
-assert_eq!("Test Message", recovered_message);
+assert_eq!(Some("Test Message".to_string()), recovered_message);
 ```
 
 If the message was encrypted, the same password is required to retrieve the message:
@@ -66,9 +72,491 @@ use steganographyrs::options::{SteganographyOption, SteganographyExtractOption};
 let options = SteganographyExtractOption {
     input_image_path: "testAssets/out_message_Bye_3.png".to_string(),
     password: Some("Secret Password Here".to_string()),
+    ..Default::default()
 };
 let recovered_message = steganography(SteganographyOption::ExtractMessageFromImage(options)).unwrap();
-assert_eq!("Test Message", recovered_message);
+assert_eq!(Some("Test Message".to_string()), recovered_message);
+```
+
+## Hide Any Bytes
+
+The message is not limited to text. Any binary content (PDF, zip, key) can be injected with
+`inject_bytes` and recovered byte for byte with `extract_bytes`. The payload is stored behind a
+small header with its length, so it can contain any byte value.
+
+```rust
+use steganographyrs::{extract_bytes, inject_bytes};
+use steganographyrs::options::{SteganographyExtractOption, SteganographyInjectOption};
+
+let payload = vec![0u8, 4, 255, 42];
+let options = SteganographyInjectOption {
+    message: "".to_string(), // Not used, the payload is injected instead
+    password: Some("Secret Password Here".to_string()),
+    input_image_path: "testAssets/prestine.png".to_string(),
+    output_image_path: "testAssets/image_with_secret_bytes.png".to_string(),
+    ..Default::default()
+};
+inject_bytes(&payload, options).unwrap();
+
+let options = SteganographyExtractOption {
+    input_image_path: "testAssets/image_with_secret_bytes.png".to_string(),
+    password: Some("Secret Password Here".to_string()),
+    ..Default::default()
+};
+assert_eq!(payload, extract_bytes(options).unwrap());
+```
+
+## Scatter the Message across the Image
+
+By default the message starts at the top left pixel. With a password, the `Scattered` order
+spreads the bits over the whole image in an order derived from the password: the message can
+only be located with the same password. The extraction finds the order by itself.
+
+```rust
+use steganographyrs::steganography;
+use steganographyrs::options::{
+    EmbeddingOrder, SteganographyExtractOption, SteganographyInjectOption, SteganographyOption,
+};
+
+let options = SteganographyInjectOption {
+    message: "Test Message".to_string(),
+    password: Some("Secret Password Here".to_string()),
+    input_image_path: "testAssets/prestine.png".to_string(),
+    output_image_path: "testAssets/image_with_scattered_message.png".to_string(),
+    order: EmbeddingOrder::Scattered,
+    ..Default::default()
+};
+steganography(SteganographyOption::InjectMessageIntoImage(options)).unwrap();
+
+let options = SteganographyExtractOption {
+    input_image_path: "testAssets/image_with_scattered_message.png".to_string(),
+    password: Some("Secret Password Here".to_string()),
+    ..Default::default()
+};
+let message = steganography(SteganographyOption::ExtractMessageFromImage(options)).unwrap();
+assert_eq!(Some("Test Message".to_string()), message);
+```
+
+## Hide the Message in the Textured Regions
+
+A change in a flat region (a clear sky, a solid background) is easier to detect than in a
+textured one. The `Adaptive` order ranks the pixels by the differences with their neighbors and
+fills the most textured first. The ranking only uses the high bits of the channels, which the
+injection never changes, so the extraction reproduces it by itself. It requires the
+`Replacement` method: increasing or decreasing a value can change its high bits.
+
+```rust
+use steganographyrs::image::{DynamicImage, RgbImage, Rgb};
+use steganographyrs::options::{
+    EmbeddingOrder, SteganographyExtractOption, SteganographyInjectOption,
+};
+use steganographyrs::{extract_bytes_from_image, inject_bytes_into_image};
+
+let img = DynamicImage::ImageRgb8(RgbImage::from_fn(32, 32, |x, y| Rgb([(x * 40 + y * 70) as u8, 0, 0])));
+let options = SteganographyInjectOption {
+    order: EmbeddingOrder::Adaptive,
+    ..Default::default()
+};
+let img = inject_bytes_into_image(&img, b"In the texture", &options).unwrap();
+let bytes = extract_bytes_from_image(&img, &SteganographyExtractOption::default()).unwrap();
+assert_eq!(bytes, b"In the texture");
+```
+
+## Match the Bits instead of Replacing them
+
+By default the least significant bits are overwritten, which only ever moves an even value up
+and an odd value down: statistical analyses (chi-square, RS) detect this asymmetry. The
+`Matching` method randomly increases or decreases the value instead (by 1 with one bit per
+channel), never going past 0 or the highest value. The extraction reads the bits the same way.
+
+```rust
+use steganographyrs::image::DynamicImage;
+use steganographyrs::options::{
+    EmbeddingMethod, SteganographyExtractOption, SteganographyInjectOption,
+};
+use steganographyrs::{extract_bytes_from_image, inject_bytes_into_image};
+
+let options = SteganographyInjectOption {
+    method: EmbeddingMethod::Matching,
+    ..Default::default()
+};
+let img = inject_bytes_into_image(&DynamicImage::new_rgba8(64, 64), b"Matched", &options).unwrap();
+let bytes = extract_bytes_from_image(&img, &SteganographyExtractOption::default()).unwrap();
+assert_eq!(bytes, b"Matched");
+```
+
+## Change Fewer Channels with a Hamming Code
+
+With `matrix_embedding`, each group of `2^k - 1` channels holds `k` bits of the payload in the
+syndrome of their least significant bits: at most one channel of the group changes. The library
+chooses the largest `k` the payload allows, so the shorter the payload compared to the capacity,
+the fewer channels change. `k` is written in the header and the extraction finds it by itself.
+It uses one bit per channel and combines with the `Matching` method.
+
+```rust
+use steganographyrs::image::DynamicImage;
+use steganographyrs::options::{SteganographyExtractOption, SteganographyInjectOption};
+use steganographyrs::{extract_bytes_from_image, inject_bytes_into_image};
+
+let options = SteganographyInjectOption {
+    matrix_embedding: true,
+    ..Default::default()
+};
+let img = inject_bytes_into_image(&DynamicImage::new_rgba8(64, 64), b"Few changes", &options).unwrap();
+let bytes = extract_bytes_from_image(&img, &SteganographyExtractOption::default()).unwrap();
+assert_eq!(bytes, b"Few changes");
+```
+
+## Use More Bits of Each Color
+
+By default, only the least significant bit of each color channel carries the message. Up to 4
+bits per channel can be used to hide a longer message: the capacity grows with each bit but the
+colors are altered more. The number of bits is written in the image, the extraction finds it by
+itself. The distortion, measured as the PSNR of an image where every channel carries the
+message, is:
+
+| Bits per channel | Capacity (bits per pixel) | PSNR    |
+|------------------|---------------------------|---------|
+| 1                | 4                         | 51.1 dB |
+| 2                | 8                         | 44.2 dB |
+| 3                | 12                        | 37.9 dB |
+| 4                | 16                        | 31.9 dB |
+
+Above 40 dB, the difference is not visible. A message that uses only part of the image alters
+it less.
+
+```rust
+use steganographyrs::steganography;
+use steganographyrs::options::{SteganographyOption, SteganographyInjectOption};
+
+let options = SteganographyInjectOption {
+    message: "Test Message".to_string(),
+    input_image_path: "testAssets/prestine.png".to_string(),
+    output_image_path: "testAssets/image_with_deep_message.png".to_string(),
+    bits_per_channel: 2,
+    ..Default::default()
+};
+assert!(steganography(SteganographyOption::InjectMessageIntoImage(options)).unwrap().is_none())
+```
+
+## Choose the Color Channels
+
+The red, green, blue and alpha channels of each pixel carry the message by default. Changing
+the alpha of an opaque image is easy to spot: the `channels` option restricts the message to
+some of the channels. The channels are written in the image, the extraction finds them by itself.
+
+```rust
+use steganographyrs::steganography;
+use steganographyrs::options::{ChannelMask, SteganographyOption, SteganographyInjectOption};
+
+let options = SteganographyInjectOption {
+    message: "Test Message".to_string(),
+    input_image_path: "testAssets/prestine.png".to_string(),
+    output_image_path: "testAssets/image_with_opaque_message.png".to_string(),
+    channels: ChannelMask::RGB, // or "gb".parse().unwrap()
+    ..Default::default()
+};
+assert!(steganography(SteganographyOption::InjectMessageIntoImage(options)).unwrap().is_none())
+```
+
+## Compress the Message
+
+A text or a file often takes less room once compressed. The `compression` option compresses the
+payload with Deflate, Zstandard or Brotli before it is encrypted. The codec is written in the
+image, the extraction decompresses by itself.
+
+```rust
+use steganographyrs::image::DynamicImage;
+use steganographyrs::options::{
+    Compression, SteganographyExtractOption, SteganographyInjectOption,
+};
+use steganographyrs::{extract_bytes_from_image, inject_bytes_into_image};
+
+// 64x64 pixels hold 2037 bytes, this text is 4000 bytes long
+let text = "Compressed payload. ".repeat(200);
+let options = SteganographyInjectOption {
+    compression: Compression::Zstd,
+    ..Default::default()
+};
+let img = inject_bytes_into_image(&DynamicImage::new_rgba8(64, 64), text.as_bytes(), &options).unwrap();
+let bytes = extract_bytes_from_image(&img, &SteganographyExtractOption::default()).unwrap();
+assert_eq!(bytes, text.into_bytes());
+```
+
+## Hide a File
+
+`inject_file_into_image` hides a `FileEnvelope`: the content of a file with its name, its last
+modification and a SHA-256 hash. `extract_file_from_image` returns it back after verifying the
+hash, `write_to_dir` recreates the file under its original name.
+
+```rust
+use steganographyrs::image::DynamicImage;
+use steganographyrs::options::{SteganographyExtractOption, SteganographyInjectOption};
+use steganographyrs::{extract_file_from_image, inject_file_into_image, FileEnvelope};
+
+let envelope = FileEnvelope::from_path("testAssets/message1.txt").unwrap();
+let options = SteganographyInjectOption::default();
+let img = inject_file_into_image(&DynamicImage::new_rgba8(64, 64), &envelope, &options).unwrap();
+let file = extract_file_from_image(&img, &SteganographyExtractOption::default()).unwrap();
+assert_eq!(file.name, "message1.txt");
+assert_eq!(file.content, envelope.content);
+```
+
+## Hide Several Entries
+
+A `Container` keeps several named entries in a single image, each one optionally encrypted with
+its own password. `extract_container_from_image` returns all the entries, `Container::get`
+decrypts one of them.
+
+```rust
+use steganographyrs::image::DynamicImage;
+use steganographyrs::options::{SteganographyExtractOption, SteganographyInjectOption};
+use steganographyrs::{extract_container_from_image, inject_container_into_image, Container};
+
+let mut container = Container::new();
+container.add("note", b"Meet at noon", &None).unwrap();
+container.add("key", b"0123456789", &Some("secret".to_string())).unwrap();
+let options = SteganographyInjectOption::default();
+let img = inject_container_into_image(&DynamicImage::new_rgba8(64, 64), &container, &options).unwrap();
+let container = extract_container_from_image(&img, &SteganographyExtractOption::default()).unwrap();
+assert_eq!(container.names(), vec!["note", "key"]);
+assert_eq!(container.get("key", &Some("secret".to_string())).unwrap(), b"0123456789");
+```
+
+## Split across Several Images
+
+`inject_bytes_into_images` splits bytes too long for a single image across several images.
+`extract_bytes_from_images` joins them back from the images in any order, or reports the
+missing ones with `SteganographyError::MissingShards`.
+
+```rust
+use steganographyrs::image::DynamicImage;
+use steganographyrs::options::{SteganographyExtractOption, SteganographyInjectOption};
+use steganographyrs::{extract_bytes_from_images, inject_bytes_into_images, SteganographyError};
+
+let payload = vec![7u8; 3000]; // More than the 2037 bytes of one 64x64 image
+let images = vec![DynamicImage::new_rgba8(64, 64), DynamicImage::new_rgba8(64, 64)];
+let mut images = inject_bytes_into_images(&images, &payload, &SteganographyInjectOption::default()).unwrap();
+images.reverse();
+let options = SteganographyExtractOption::default();
+assert_eq!(extract_bytes_from_images(&images, &options).unwrap(), payload);
+assert!(matches!(
+    extract_bytes_from_images(&images[..1], &options),
+    Err(SteganographyError::MissingShards { .. })
+));
+```
+
+## Share across Several Images
+
+`share_bytes_into_images` shares bytes across several images with Shamir's secret sharing: any
+`threshold` of the images recover the bytes with `recover_bytes_from_images`, fewer images
+reveal nothing about them. Each image carries a share as long as the whole payload.
+
+```rust
+use steganographyrs::image::DynamicImage;
+use steganographyrs::options::{SteganographyExtractOption, SteganographyInjectOption};
+use steganographyrs::{recover_bytes_from_images, share_bytes_into_images, SteganographyError};
+
+let images = vec![DynamicImage::new_rgba8(64, 64); 5];
+let images = share_bytes_into_images(&images, b"Launch code", 3, &SteganographyInjectOption::default()).unwrap();
+let options = SteganographyExtractOption::default();
+let any_three = [images[4].clone(), images[0].clone(), images[2].clone()];
+assert_eq!(recover_bytes_from_images(&any_three, &options).unwrap(), b"Launch code");
+assert!(matches!(
+    recover_bytes_from_images(&images[..2], &options),
+    Err(SteganographyError::NotEnoughShares { required: 3, available: 2 })
+));
+```
+
+## Supported Images
+
+The 8 and 16 bits grayscale, grayscale with alpha, RGB and RGBA images carry the message in
+their own channels and are saved with the same color type. A grayscale image has one channel
+per pixel (two with the alpha) so it holds less message than a RGB image of the same size.
+Other color types are converted to 8 bits RGBA.
+
+The output image is saved in the format of its extension, which must keep the channels exactly:
+PNG, BMP, TIFF, TGA, PNM, Farbfeld, QOI or ICO. A format that would lose the message (E.g. WebP,
+or GIF for an image without a palette) returns a `SteganographyError::UnsupportedFormat` error and no file is written. The
+saved file is read back, a format that changes the channels of the image (E.g. a grayscale BMP
+read back as RGB) returns the same error. A JPEG output carries the message in its coefficients,
+see below. With the `verify` option, the saved image is reopened and the message extracted
+from it must have the same SHA-256 hash as the injected one, otherwise a
+`SteganographyError::Verification` error is returned.
+
+## Hide the Message in a JPEG Image
+
+Saving the pixels to JPEG would compress them and lose the message. A baseline JPEG image
+carries the message in its quantized DCT coefficients instead: one bit in each AC coefficient of
+magnitude 2 or more. The coefficients are written back with the same quantization and Huffman
+tables, the image is not compressed a second time. An output path ending with `.jpg` or `.jpeg`
+uses the coefficients, an input image of another format is first encoded to JPEG. The
+extraction and the capacity detect the JPEG images from their content. A JPEG image carries one
+bit per coefficient with the replacement method, in the sequential or scattered order.
+
+```rust
+use steganographyrs::options::{
+    SteganographyCapacityOption, SteganographyExtractOption, SteganographyInjectOption,
+};
+use steganographyrs::{extract_bytes_from_jpeg, inject_bytes_into_encoded_image, jpeg_capacity};
+use steganographyrs::image::ImageFormat;
+
+let png = std::fs::read("testAssets/prestine.png").unwrap();
+let options = SteganographyInjectOption::default();
+let jpeg = inject_bytes_into_encoded_image(&png, b"In the coefficients", &options, ImageFormat::Jpeg).unwrap();
+assert!(jpeg_capacity(&jpeg, &SteganographyCapacityOption::default()).unwrap() > 1000);
+let payload = extract_bytes_from_jpeg(&jpeg, &SteganographyExtractOption::default()).unwrap();
+assert_eq!(b"In the coefficients".to_vec(), payload);
+```
+
+## Hide the Message in an Image with a Palette
+
+A PNG image of the indexed color type and a GIF image store the index of a color of their
+palette for each pixel, changing that index changes the color arbitrarily. The palette is
+sorted by luminance and its colors are paired two by two in that order (as EzStego does): the
+parity of the rank of the color of a pixel carries one bit, and writing it swaps the color for
+the other one of its pair, which looks alike. A transparent color is only paired with another
+transparent color. An input image with a palette saved to its own format keeps its palette and
+its color type, the extraction and the capacity detect these images from their content. An image
+with a palette carries one bit per pixel with the replacement method, in the sequential or
+scattered order.
+
+An animated GIF or APNG (of the indexed color type) carries the message in all its frames: the
+sequential order fills the frames one after the other and the scattered order spreads the
+message over all of them with the password. The position, delay and disposal of each frame and
+the number of loops are kept. The extraction walks the frames in the same order.
+
+```rust
+use steganographyrs::options::{
+    SteganographyCapacityOption, SteganographyExtractOption, SteganographyInjectOption,
+};
+use steganographyrs::{
+    extract_bytes_from_palette_image, inject_bytes_into_palette_image, palette_capacity,
+};
+
+let gif = std::fs::read("testAssets/prestine.gif").unwrap();
+let options = SteganographyInjectOption::default();
+let output = inject_bytes_into_palette_image(&gif, b"In the palette", &options).unwrap();
+assert!(palette_capacity(&output, &SteganographyCapacityOption::default()).unwrap() > 1000);
+let payload = extract_bytes_from_palette_image(&output, &SteganographyExtractOption::default()).unwrap();
+assert_eq!(b"In the palette".to_vec(), payload);
+```
+
+## Work in Memory
+
+The images do not have to be files. `inject_bytes_into_image` and `extract_bytes_from_image`
+work on a `DynamicImage`, `inject_bytes_into_encoded_image` and `extract_bytes_from_encoded_image`
+on the bytes of an image file (E.g. an upload received by a server). The paths of the options
+are not used.
+
+```rust
+use steganographyrs::image::{DynamicImage, ImageFormat, RgbaImage};
+use steganographyrs::options::{SteganographyExtractOption, SteganographyInjectOption};
+use steganographyrs::{
+    extract_bytes_from_encoded_image, extract_bytes_from_image, inject_bytes_into_encoded_image,
+    inject_bytes_into_image,
+};
+
+let image = DynamicImage::from(RgbaImage::new(64, 64));
+let options = SteganographyInjectOption {
+    password: Some("Secret Password Here".to_string()),
+    ..Default::default()
+};
+let extract_options = SteganographyExtractOption {
+    input_image_path: "".to_string(),
+    password: Some("Secret Password Here".to_string()),
+    ..Default::default()
+};
+let stego_image = inject_bytes_into_image(&image, b"In memory", &options).unwrap();
+assert_eq!(b"In memory".to_vec(), extract_bytes_from_image(&stego_image, &extract_options).unwrap());
+
+let png = std::fs::read("testAssets/prestine.png").unwrap();
+let stego_png = inject_bytes_into_encoded_image(&png, b"Uploaded", &options, ImageFormat::Png).unwrap();
+assert_eq!(b"Uploaded".to_vec(), extract_bytes_from_encoded_image(&stego_png, &extract_options).unwrap());
+```
+
+## Stream a Payload
+
+`PayloadWriter` implements `std::io::Write` and `PayloadReader` implements `std::io::Read`: a
+payload can be copied from a file or a socket into an image and read back in chunks.
+
+```rust
+use std::io::{Read, Write};
+use steganographyrs::image::DynamicImage;
+use steganographyrs::options::{SteganographyExtractOption, SteganographyInjectOption};
+use steganographyrs::{PayloadReader, PayloadWriter};
+
+let mut writer = PayloadWriter::new(DynamicImage::new_rgb8(64, 64), SteganographyInjectOption::default());
+writer.write_all(b"First chunk, ").unwrap();
+writer.write_all(b"second chunk").unwrap();
+let image = writer.finish().unwrap();
+
+let mut reader = PayloadReader::new(&image, &SteganographyExtractOption::default()).unwrap();
+let mut payload = String::new();
+reader.read_to_string(&mut payload).unwrap();
+assert_eq!("First chunk, second chunk", payload);
+```
+
+## Know How Much an Image Can Hide
+
+Before injecting, you can ask how many bytes of message an image can carry with a given encoding.
+Injecting a longer message returns a `SteganographyError::Capacity` error.
+
+```rust
+use steganographyrs::capacity;
+use steganographyrs::image::DynamicImage;
+use steganographyrs::options::SteganographyCapacityOption;
+
+let image = DynamicImage::new_rgba8(64, 64);
+let options = SteganographyCapacityOption {
+    input_image_path: "".to_string(),
+    password: None,
+    ..Default::default()
+};
+assert_eq!(2037, capacity(&image, &options));
+```
+
+## Encrypt a Text without an Image
+
+The same cipher used before injecting a message with a password is available on its own. It
+is handy to pre-encrypt a message or to verify that a password is the right one.
+
+```rust
+use steganographyrs::steganography;
+use steganographyrs::options::{
+    SteganographyDecryptOption, SteganographyEncryptOption, SteganographyOption,
+};
+
+let options = SteganographyEncryptOption {
+    message: "Test Message".to_string(),
+    password: "Secret Password Here".to_string(),
+};
+let encrypted = steganography(SteganographyOption::EncryptMessage(options)).unwrap().unwrap();
+
+let options = SteganographyDecryptOption {
+    message: encrypted,
+    password: "Secret Password Here".to_string(),
+};
+let decrypted = steganography(SteganographyOption::DecryptMessage(options)).unwrap();
+assert_eq!(Some("Test Message".to_string()), decrypted);
+```
+
+## Handle Failures
+
+Every operation returns a `Result`. A missing file, an image that cannot be decoded, a wrong
+password or an image without a hidden message are reported as a `SteganographyError`.
+
+```rust
+use steganographyrs::{steganography, SteganographyError};
+use steganographyrs::options::{SteganographyOption, SteganographyExtractOption};
+
+let options = SteganographyExtractOption {
+    input_image_path: "testAssets/out_message_Bye_3.png".to_string(),
+    password: Some("Wrong Password".to_string()),
+    ..Default::default()
+};
+let result = steganography(SteganographyOption::ExtractMessageFromImage(options));
+assert!(matches!(result, Err(SteganographyError::BadPassword)));
 ```
 
 # How to Use the CLI?
@@ -87,6 +575,116 @@ steganographyrs -e inject -m "My Secret Message" -i testAssets/prestine.png -o o
 steganographyrs -e inject -p secret -m "My Secret Message" -i testAssets/prestine.png -o out.png
 ```
 
+## Scatter an Encrypted String across an Image
+
+```sh
+steganographyrs -e inject -p secret --order scattered -m "My Secret Message" -i testAssets/prestine.png -o out.png
+```
+
+## Hide a String in the Textured Regions of an Image
+
+```sh
+steganographyrs -e inject --order adaptive -m "My Secret Message" -i testAssets/prestine.png -o out.png
+```
+
+## Match the Bits to Resist Statistical Analyses
+
+```sh
+steganographyrs -e inject --method matching -m "My Secret Message" -i testAssets/prestine.png -o out.png
+```
+
+## Change Fewer Channels with a Hamming Code
+
+```sh
+steganographyrs -e inject --matrix -m "My Secret Message" -i testAssets/prestine.png -o out.png
+```
+
+## Verify that the Saved Image Gives Back the Message
+
+```sh
+steganographyrs -e inject --verify -p secret -m "My Secret Message" -i testAssets/prestine.png -o out.png
+```
+
+## Hide a String in a JPEG Image
+
+```sh
+steganographyrs -e inject -m "My Secret Message" -i photo.jpg -o out.jpg
+steganographyrs -e extract -i out.jpg
+steganographyrs -e capacity -i photo.jpg
+```
+
+## Hide a String in an Image with a Palette
+
+```sh
+steganographyrs -e inject -m "My Secret Message" -i testAssets/prestine.gif -o out.gif
+steganographyrs -e extract -i out.gif
+steganographyrs -e capacity -i testAssets/prestine.gif
+```
+
+## Hide a Longer String with More Bits of Each Color
+
+```sh
+steganographyrs -e inject -b 2 -m "My Secret Message" -i testAssets/prestine.png -o out.png
+steganographyrs -e capacity -b 2 -i testAssets/prestine.png
+```
+
+## Hide a String without Altering the Transparency
+
+```sh
+steganographyrs -e inject -c rgb -m "My Secret Message" -i testAssets/prestine.png -o out.png
+```
+
+## Hide a File in an Image
+
+```sh
+steganographyrs -e inject -p secret --input-file secret.zip -i testAssets/prestine.png -o out.png
+steganographyrs -e extract -p secret --output-file secret.zip -i out.png
+steganographyrs -e extract -p secret --to-dir documents -i out.png
+```
+
+## Compress a String before Hiding it
+
+```sh
+steganographyrs -e inject --compress zstd -m "My Secret Message" -i testAssets/prestine.png -o out.png
+```
+
+## Hide Several Entries in One Image
+
+```sh
+steganographyrs -e inject --entry note -m "Meet at noon" -i testAssets/prestine.png -o out.png
+steganographyrs -e inject --entry key --entry-password secret -m "0123456789" -i out.png -o out.png
+steganographyrs -e list -i out.png
+steganographyrs -e extract --entry key --entry-password secret -i out.png
+steganographyrs -e remove --entry note -i out.png -o out.png
+```
+
+## Split a Long Message across Several Images
+
+```sh
+steganographyrs -e inject --shards holidays/ --input-file secret.zip -o shared/
+steganographyrs -e extract --shards shared/ --output-file secret.zip
+```
+
+## Share a Message so that Any 2 of 3 Images Recover it
+
+```sh
+steganographyrs -e share -k 2 -m "My Secret Message" --shards a.png b.png c.png -o shared/
+steganographyrs -e recover --shards shared/c.png shared/a.png
+```
+
+## Encrypt and Decrypt a String without an Image
+
+```sh
+steganographyrs -e encrypt -p secret -m "My Secret Message"
+echo "My Secret Message" | steganographyrs -e encrypt -p secret | steganographyrs -e decrypt -p secret
+```
+
+## Print the Capacity of an Image
+
+```sh
+steganographyrs -e capacity -i testAssets/prestine.png
+```
+
 # Additional Resource
 
 - [Blog Post about using the least significant bits](https://patrickdesjardins.com/blog/what-is-steganography-how-to-hide-text-in-image)
@@ -95,32 +693,90 @@ steganographyrs -e inject -p secret -m "My Secret Message" -i testAssets/prestin
 mod utils;
 
 // Imports
-use crate::utils::function::{add_message_to_image, get_message_from_image};
+use crate::utils::container::{
+    add_entry, extract_entry_to_file, get_entry_message, list_entries, remove_entry,
+};
+use crate::utils::encryption::{decrypt_if_needed, encrypt_if_needed};
+use crate::utils::function::{add_message_to_image, get_capacity_of_image, get_message_from_image};
+use crate::utils::shard::{extract_shards_to_file, get_message_from_shards, inject_shards};
+use crate::utils::sharing::{get_message_from_shares, recover_message_to_file, share_message};
+use crate::utils::stream::{extract_file, extract_file_to_dir, inject_file};
 use crate::utils::options::SteganographyOption;
 
 // Re-export for external access
+pub use crate::utils::error::SteganographyError;
+pub use crate::utils::container::{Container, ContainerEntry};
+pub use crate::utils::envelope::FileEnvelope;
+pub use crate::utils::function::{
+    capacity, extract_bytes, extract_bytes_from_encoded_image, extract_bytes_from_image,
+    extract_bytes_from_jpeg, extract_bytes_from_palette_image, extract_container_from_image,
+    extract_file_from_image, get_message_from_buffer, inject_bytes, inject_bytes_into_encoded_image,
+    inject_bytes_into_image, inject_bytes_into_jpeg, inject_bytes_into_palette_image,
+    inject_container_into_image, inject_file_into_image, jpeg_capacity, palette_capacity,
+};
 pub use crate::utils::options;
-
-pub fn steganography(options: SteganographyOption) -> Option<String> {
+pub use crate::utils::shard::{extract_bytes_from_images, inject_bytes_into_images};
+pub use crate::utils::sharing::{recover_bytes_from_images, share_bytes_into_images};
+pub use crate::utils::stream::{PayloadReader, PayloadWriter};
+pub use image;
+
+/// Entry point of the library that executes the operation described by the option
+///
+/// # Returns
+/// The extracted message when extracting, the number of bytes the image can hide when asking for
+/// the capacity, the transformed message when encrypting or decrypting, `None` when injecting,
+/// or the reason the operation failed
+pub fn steganography(options: SteganographyOption) -> Result<Option<String>, SteganographyError> {
     match options {
         SteganographyOption::InjectMessageIntoImage(n) => {
-            add_message_to_image(n);
-            None
+            let shards = !n.shard_image_paths.is_empty();
+            match (shards, &n.entry, &n.input_file_path) {
+                (true, _, _) => inject_shards(n)?,
+                (false, Some(_), _) => add_entry(n)?,
+                (false, None, Some(_)) => inject_file(n)?,
+                (false, None, None) => add_message_to_image(n)?,
+            }
+            Ok(None)
         }
         SteganographyOption::ExtractMessageFromImage(n) => {
-            let result = get_message_from_image(n);
-            match result {
-                Ok(s) => Some(s),
-                Err(_e) => None,
+            let shards = !n.shard_image_paths.is_empty();
+            match (shards, &n.entry, &n.output_dir_path, &n.output_file_path) {
+                (true, _, _, Some(_)) => extract_shards_to_file(n).map(|_| None),
+                (true, _, _, None) => get_message_from_shards(n).map(Some),
+                (false, Some(_), _, Some(_)) => extract_entry_to_file(n).map(|_| None),
+                (false, Some(_), _, None) => get_entry_message(n).map(Some),
+                (false, None, Some(_), _) => extract_file_to_dir(n).map(|_| None),
+                (false, None, None, Some(_)) => extract_file(n).map(|_| None),
+                (false, None, None, None) => get_message_from_image(n).map(Some),
             }
         }
+        SteganographyOption::ListEntriesOfImage(n) => list_entries(n)
+            .map(|names| Some(names.iter().map(|name| format!("{}\n", name)).collect())),
+        SteganographyOption::RemoveEntryFromImage(n) => remove_entry(n).map(|_| None),
+        SteganographyOption::ShareMessageIntoImages(n) => share_message(n).map(|_| None),
+        SteganographyOption::RecoverMessageFromImages(n) => match n.output_file_path {
+            Some(_) => recover_message_to_file(n).map(|_| None),
+            None => get_message_from_shares(n).map(Some),
+        },
+        SteganographyOption::CapacityOfImage(n) => {
+            get_capacity_of_image(n).map(|bytes| Some(bytes.to_string()))
+        }
+        SteganographyOption::EncryptMessage(n) => {
+            encrypt_if_needed(n.message, Some(n.password)).map(Some)
+        }
+        SteganographyOption::DecryptMessage(n) => {
+            decrypt_if_needed(n.message, Some(n.password)).map(Some)
+        }
     }
 }
 
 #[cfg(test)]
 mod steganography {
+    use clap::Parser;
+
     use crate::utils::options::{
-        SteganographyExtractOption, SteganographyInjectOption,
+        extract_options, CliData, SteganographyCapacityOption, SteganographyDecryptOption,
+        SteganographyEncryptOption, SteganographyExtractOption, SteganographyInjectOption,
     };
 
     use super::*;
@@ -132,9 +788,10 @@ mod steganography {
             password: None,
             input_image_path: "testAssets/prestine.png".to_string(),
             output_image_path: "testAssets/delete_me.png".to_string(),
+            ..Default::default()
         };
         let result = steganography(SteganographyOption::InjectMessageIntoImage(options));
-        assert_eq!(None, result)
+        assert!(result.unwrap().is_none())
     }
 
     #[test]
@@ -144,9 +801,10 @@ mod steganography {
             password: Some("Secret Password Here".to_string()),
             input_image_path: "testAssets/prestine.png".to_string(),
             output_image_path: "testAssets/delete_me.png".to_string(),
+            ..Default::default()
         };
         let result = steganography(SteganographyOption::InjectMessageIntoImage(options));
-        assert_eq!(None, result)
+        assert!(result.unwrap().is_none())
     }
 
     #[test]
@@ -154,9 +812,10 @@ mod steganography {
         let options = SteganographyExtractOption {
             input_image_path: "testAssets/out_message_Bye_2.png".to_string(),
             password: None,
+            ..Default::default()
         };
         let recovered_message = steganography(SteganographyOption::ExtractMessageFromImage(options)).unwrap();
-        assert_eq!("Test Message", recovered_message);
+        assert_eq!(Some("Test Message".to_string()), recovered_message);
     }
 
     #[test]
@@ -164,9 +823,10 @@ mod steganography {
         let options = SteganographyExtractOption {
             input_image_path: "testAssets/out_message_Bye_3.png".to_string(),
             password: Some("Secret Password Here".to_string()),
+            ..Default::default()
         };
         let recovered_message = steganography(SteganographyOption::ExtractMessageFromImage(options)).unwrap();
-        assert_eq!("Test Message", recovered_message);
+        assert_eq!(Some("Test Message".to_string()), recovered_message);
     }
 
     #[test]
@@ -174,8 +834,111 @@ mod steganography {
         let options = SteganographyExtractOption {
             input_image_path: "testAssets/out_message_Bye_3.png".to_string(),
             password: Some("Wrong Secret Password Here".to_string()),
+            ..Default::default()
         };
         let recovered_message = steganography(SteganographyOption::ExtractMessageFromImage(options));
-        assert_eq!(None, recovered_message);
+        assert!(matches!(recovered_message, Err(SteganographyError::BadPassword)));
+    }
+
+    #[test]
+    fn test_steganography_capacity() {
+        let options = SteganographyCapacityOption {
+            input_image_path: "testAssets/prestine.png".to_string(),
+            password: None,
+            ..Default::default()
+        };
+        let result = steganography(SteganographyOption::CapacityOfImage(options)).unwrap();
+        assert_eq!(Some("24565".to_string()), result);
+    }
+
+    #[test]
+    fn test_steganography_encrypt_decrypt_message() {
+        let options = SteganographyEncryptOption {
+            message: "Test Message".to_string(),
+            password: "Secret Password Here".to_string(),
+        };
+        let encrypted = steganography(SteganographyOption::EncryptMessage(options))
+            .unwrap()
+            .unwrap();
+        assert_ne!("Test Message", encrypted);
+        let options = SteganographyDecryptOption {
+            message: encrypted,
+            password: "Secret Password Here".to_string(),
+        };
+        let decrypted = steganography(SteganographyOption::DecryptMessage(options)).unwrap();
+        assert_eq!(Some("Test Message".to_string()), decrypted);
+    }
+
+    #[test]
+    fn test_steganography_decrypt_message_with_wrong_password() {
+        let options = SteganographyEncryptOption {
+            message: "Test Message".to_string(),
+            password: "Secret Password Here".to_string(),
+        };
+        let encrypted = steganography(SteganographyOption::EncryptMessage(options))
+            .unwrap()
+            .unwrap();
+        let options = SteganographyDecryptOption {
+            message: encrypted,
+            password: "Wrong Password".to_string(),
+        };
+        let result = steganography(SteganographyOption::DecryptMessage(options));
+        assert!(matches!(result, Err(SteganographyError::BadPassword)));
+    }
+
+    #[test]
+    fn test_steganography_missing_image() {
+        let options = SteganographyExtractOption {
+            input_image_path: "testAssets/does_not_exist.png".to_string(),
+            password: None,
+            ..Default::default()
+        };
+        let result = steganography(SteganographyOption::ExtractMessageFromImage(options));
+        assert!(matches!(result, Err(SteganographyError::Io(_))));
+    }
+
+    #[test]
+    fn test_steganography_input_file_into_jpeg() {
+        let args = CliData::parse_from([
+            "steganographyrs",
+            "-e",
+            "inject",
+            "-i",
+            "testAssets/prestine.png",
+            "-o",
+            "target/delete_me_cli_file.jpg",
+            "--input-file",
+            "testAssets/message1.txt",
+        ]);
+        let result = steganography(extract_options(args, None).unwrap());
+        assert!(result.unwrap().is_none());
+        let args = CliData::parse_from([
+            "steganographyrs",
+            "-e",
+            "extract",
+            "-i",
+            "target/delete_me_cli_file.jpg",
+            "--output-file",
+            "target/delete_me_cli_message1.txt",
+        ]);
+        steganography(extract_options(args, None).unwrap()).unwrap();
+        assert_eq!(
+            std::fs::read("target/delete_me_cli_message1.txt").unwrap(),
+            std::fs::read("testAssets/message1.txt").unwrap()
+        );
+        let args = CliData::parse_from([
+            "steganographyrs",
+            "-e",
+            "extract",
+            "-i",
+            "target/delete_me_cli_file.jpg",
+            "--to-dir",
+            "target/delete_me_cli_to_dir",
+        ]);
+        steganography(extract_options(args, None).unwrap()).unwrap();
+        assert_eq!(
+            std::fs::read("target/delete_me_cli_to_dir/message1.txt").unwrap(),
+            std::fs::read("testAssets/message1.txt").unwrap()
+        );
     }
 }
@@ -6,28 +6,36 @@ use steganographyrs::options::{extract_options, CliData};
 use steganographyrs::steganography;
 /// CLI access to the steganography library
 fn main() {
+    let args = CliData::parse();
     let mut message_from_pipe: Option<String> = None;
     // Piping, the message content is coming from the std in instead of the args.message
-    if std::io::stdin().is_terminal() {
+    if args.needs_piped_message() && !std::io::stdin().is_terminal() {
         let mut input_message = io::stdin()
             .lock()
             .lines()
             .fold("".to_string(), |acc, line| acc + &line.unwrap() + "\n");
-        input_message.truncate(input_message.len() - 1);
-        message_from_pipe = Some(input_message);
+        if input_message.pop().is_some() {
+            message_from_pipe = Some(input_message);
+        }
     }
 
-    let args = CliData::parse();
     let options = extract_options(args, message_from_pipe);
 
     match options {
         Ok(steganography_option) => match steganography(steganography_option) {
-            Some(s) => {
+            Ok(Some(s)) => {
                 print!("{}", s);
             }
-            None => { /*Nothing to print. Happen if encrypt or if an error occured*/ }
+            Ok(None) => { /*Nothing to print. Happen if inject*/ }
+            Err(error) => {
+                eprintln!("{}", error);
+                process::exit(1);
+            }
         },
-        Err(error) => panic!("{:?}", error),
+        Err(error) => {
+            eprintln!("{}", error);
+            process::exit(1);
+        }
     };
 
     process::exit(0);
@@ -1,4 +1,16 @@
 pub mod function;
 pub mod options;
 pub mod binary;
-pub mod encryption;
\ No newline at end of file
+pub mod bitstream;
+pub mod compression;
+pub mod container;
+pub mod encryption;
+pub mod envelope;
+pub mod error;
+pub mod header;
+pub mod jpeg;
+pub mod matrix;
+pub mod palette;
+pub mod shard;
+pub mod sharing;
+pub mod stream;
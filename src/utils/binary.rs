@@ -15,20 +15,70 @@ pub fn unpack_bit(buffer_item: u8) -> u8 {
     u8::from(last_digit != 0)
 }
 
-/// Sets the least significant bit to 1 or 0 (depending on the bit to set)
+/// Sets the `depth` least significant bits of the buffer item, the first bit going into the
+/// highest of them. When fewer bits than `depth` are given, the lowest bits are kept as is
 ///
 /// # Arguments
 ///  * buffer_item - 8 bits number (E.g. 0b000011u8)
-///  * bit - 0 or 1
+///  * bits - Up to `depth` values of 0 or 1
+///  * depth - Number of least significant bits that carry data (1 to 8)
 ///
 /// # Returns
-/// Modified buffer_item with the last bit set to the bit passed by parameter
-pub fn pack_bit(buffer_item: u8, bit: u8) -> u8 {
-    if bit == 0 {
-        return buffer_item & !(1 << 0);
-    }
-    // Else 1
-    buffer_item | (1 << 0)
+/// Modified buffer_item with its low bits set to the bits passed by parameter
+pub fn pack_bits(buffer_item: u8, bits: &[u8], depth: u8) -> u8 {
+    bits.iter().enumerate().fold(buffer_item, |item, (i, bit)| {
+        let index = depth - 1 - i as u8;
+        if *bit == 0 {
+            item & !(1 << index)
+        } else {
+            item | (1 << index)
+        }
+    })
+}
+
+/// Sets the `depth` least significant bits of the buffer item like `pack_bits`, but reaches them
+/// with the closest value instead of overwriting them: when the bits must change, the value is
+/// increased or decreased (by 1 for a single bit). Replacing the bits only ever moves an even
+/// value up and an odd value down, which statistical analyses detect
+///
+/// # Arguments
+///  * buffer_item - Value of the channel, 8 or 16 bits
+///  * max - Highest value of the channel (E.g. 255), never exceeded
+///  * bits - Up to `depth` values of 0 or 1
+///  * depth - Number of least significant bits that carry data (1 to 8)
+///  * increase - When the value can move up or down by the same amount, whether it moves up.
+///    Must be random for the changes to be balanced
+///
+/// # Returns
+/// Modified buffer_item with the same low bits as `pack_bits` would set
+pub fn match_bits(buffer_item: u16, max: u16, bits: &[u8], depth: u8, increase: bool) -> u16 {
+    let replaced = (buffer_item & 0xFF00) | pack_bits(buffer_item as u8, bits, depth) as u16;
+    let item = i32::from(buffer_item);
+    let step = 1i32 << depth;
+    [
+        i32::from(replaced) - step,
+        i32::from(replaced),
+        i32::from(replaced) + step,
+    ]
+    .into_iter()
+    .filter(|candidate| (0..=i32::from(max)).contains(candidate))
+    .min_by_key(|candidate| ((candidate - item).abs(), (*candidate > item) != increase))
+    .map_or(replaced, |candidate| candidate as u16)
+}
+
+/// Reads the `depth` least significant bits of the buffer item, the highest of them first
+///
+/// # Arguments
+///  * buffer_item - 8 bits number (E.g. 0b000011u8)
+///  * depth - Number of least significant bits that carry data (1 to 8)
+///
+/// # Returns
+/// `depth` values of 0 or 1
+pub fn unpack_bits(buffer_item: u8, depth: u8) -> Vec<u8> {
+    (0..depth)
+        .rev()
+        .map(|index| (buffer_item >> index) & 1)
+        .collect()
 }
 
 /// Takes a char like 65 and convert it to "1001111";
@@ -61,6 +111,17 @@ pub fn binary_string_to_char(input: String) -> char {
     }
 }
 
+/// Assembles bits into a byte, the most significant bit first
+///
+/// # Arguments
+///  * bits - 8 values of 0 or 1
+///
+/// # Returns
+/// The byte formed by the bits (E.g. [0, 1, 0, 0, 0, 0, 0, 1] gives 65)
+pub fn bits_to_byte(bits: &[u8]) -> u8 {
+    bits.iter().fold(0u8, |byte, bit| (byte << 1) | (bit & 1))
+}
+
 #[cfg(test)]
 mod test_get_string {
     use super::*;
@@ -97,6 +158,58 @@ mod test_get_string {
         assert_eq!(result, 209);
     }
 
+    #[test]
+    fn test_bits_to_byte() {
+        let result = bits_to_byte(&[1, 1, 0, 1, 0, 0, 0, 1]);
+        assert_eq!(result, 209)
+    }
+    #[test]
+    fn test_pack_bits_depth_two() {
+        let result = pack_bits(0b1111_0000, &[1, 0], 2);
+        assert_eq!(result, 0b1111_0010)
+    }
+    #[test]
+    fn test_pack_bits_partial_keeps_lowest_bits() {
+        let result = pack_bits(0b0000_0011, &[0], 3);
+        assert_eq!(result, 0b0000_0011)
+    }
+    #[test]
+    fn test_match_bits_unchanged() {
+        assert_eq!(match_bits(91, 255, &[1], 1, true), 91);
+        assert_eq!(match_bits(91, 255, &[1], 1, false), 91);
+    }
+
+    #[test]
+    fn test_match_bits_moves_by_one() {
+        assert_eq!(match_bits(90, 255, &[1], 1, true), 91);
+        assert_eq!(match_bits(90, 255, &[1], 1, false), 89);
+        assert_eq!(match_bits(91, 255, &[0], 1, true), 92);
+        assert_eq!(match_bits(91, 255, &[0], 1, false), 90);
+    }
+
+    #[test]
+    fn test_match_bits_edges() {
+        assert_eq!(match_bits(0, 255, &[1], 1, false), 1);
+        assert_eq!(match_bits(255, 255, &[0], 1, true), 254);
+        assert_eq!(match_bits(65535, 65535, &[0], 1, true), 65534);
+    }
+
+    #[test]
+    fn test_match_bits_carries_into_high_byte() {
+        assert_eq!(match_bits(0x01FF, 65535, &[0], 1, true), 0x0200);
+    }
+
+    #[test]
+    fn test_match_bits_depth_two_closest() {
+        // 0b1000 to 0b..11: 0b0111 is 1 away, 0b1011 is 3 away
+        assert_eq!(match_bits(0b1000, 255, &[1, 1], 2, true), 0b0111);
+    }
+
+    #[test]
+    fn test_unpack_bits_depth_three() {
+        let result = unpack_bits(0b1010_1101, 3);
+        assert_eq!(result, vec![1, 0, 1])
+    }
     #[test]
     fn test_unpack_number_finish_with_one() {
         let result = unpack_bit(0b000011u8);
@@ -109,22 +222,22 @@ mod test_get_string {
     }
     #[test]
     fn test_pack_number_finish_with_zero_set_one() {
-        let result = pack_bit(90, 1);
+        let result = pack_bits(90, &[1], 1);
         assert_eq!(result, 91)
     }
     #[test]
     fn test_pack_number_finish_with_one_set_one() {
-        let result = pack_bit(91, 1);
+        let result = pack_bits(91, &[1], 1);
         assert_eq!(result, 91)
     }
     #[test]
     fn test_pack_number_finish_with_zero_set_zero() {
-        let result = pack_bit(90, 0);
+        let result = pack_bits(90, &[0], 1);
         assert_eq!(result, 90)
     }
     #[test]
     fn test_pack_number_finish_with_one_set_zero() {
-        let result = pack_bit(91, 0);
+        let result = pack_bits(91, &[0], 1);
         assert_eq!(result, 90)
     }
 }
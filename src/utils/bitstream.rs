@@ -0,0 +1,813 @@
+use std::collections::{HashMap, VecDeque};
+use std::io::Read;
+use std::rc::Rc;
+
+use image::{ImageBuffer, Pixel};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+
+use super::binary::{bits_to_byte, char_to_binary_string, match_bits, pack_bits, unpack_bits};
+use super::error::SteganographyError;
+use super::matrix::{carrier_to_flip, group_length, syndrome};
+use super::options::{ChannelMask, EmbeddingMethod};
+
+const NUMBER_BIT_PER_BYTE: u8 = 8;
+
+/// Each pixel of a RGBA buffer carries bits in each of its Red, Green, Blue and Alpha channels
+pub const NUMBER_CHANNEL_PER_PIXEL: u8 = 4;
+
+/// Highest number of least significant bits of a channel that can carry the message. More bits
+/// hold more message but alter the colors more
+pub const MAX_BITS_PER_CHANNEL: u8 = 4;
+
+/// From a 1d position, returns a 2d position using the width of the image
+///
+/// # Arguments
+///
+/// * `position` - The index position of the character in the string. Value varies from 0 to the length-1 of the text
+/// * `width` - The width of the image to determine when to change line (height)
+///
+fn get_coordinate(position: u32, width: u32) -> (u32, u32) {
+    let y = position / width; // No decimal
+    let x = position - (width * y);
+
+    (x, y)
+}
+
+/// Visits every channel position once in a pseudo-random order that only the seed can reproduce.
+///
+/// It is a Fisher-Yates shuffle computed lazily: only the swaps of the positions already visited
+/// are kept, so reading a short message does not shuffle the whole image.
+pub struct ScatteredPositions {
+    rng: ChaCha20Rng,
+    swaps: HashMap<usize, usize>,
+    next: usize,
+    count: usize,
+}
+
+impl ScatteredPositions {
+    /// # Arguments
+    ///
+    /// * `count` - Number of channel positions of the image
+    /// * `seed` - Secret that decides the order, derived from the password
+    pub fn new(count: usize, seed: [u8; 32]) -> Self {
+        ScatteredPositions {
+            rng: ChaCha20Rng::from_seed(seed),
+            swaps: HashMap::new(),
+            next: 0,
+            count,
+        }
+    }
+}
+
+impl Iterator for ScatteredPositions {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        if self.next >= self.count {
+            return None;
+        }
+        // Sampled as u64 so that the order is the same on 32 and 64 bits platforms
+        let chosen = self.rng.gen_range(self.next as u64..self.count as u64) as usize;
+        let chosen_value = *self.swaps.get(&chosen).unwrap_or(&chosen);
+        let next_value = self.swaps.remove(&self.next).unwrap_or(self.next);
+        if chosen != self.next {
+            self.swaps.insert(chosen, next_value);
+        }
+        self.next += 1;
+        Some(chosen_value)
+    }
+}
+
+/// A channel value of an image (8 or 16 bits) whose least significant bits can carry the message
+pub trait Sample: Copy {
+    /// Sets the `depth` least significant bits, see `binary::pack_bits`
+    fn pack_bits(self, bits: &[u8], depth: u8) -> Self;
+
+    /// Sets the `depth` least significant bits with the closest value, see `binary::match_bits`
+    fn match_bits(self, bits: &[u8], depth: u8, increase: bool) -> Self;
+
+    /// Reads the `depth` least significant bits, see `binary::unpack_bits`
+    fn unpack_bits(self, depth: u8) -> Vec<u8>;
+
+    /// The bits above the `MAX_BITS_PER_CHANNEL` least significant bits, never changed by
+    /// `pack_bits`
+    fn high_bits(self) -> u16;
+}
+
+impl Sample for u8 {
+    fn pack_bits(self, bits: &[u8], depth: u8) -> Self {
+        pack_bits(self, bits, depth)
+    }
+
+    fn match_bits(self, bits: &[u8], depth: u8, increase: bool) -> Self {
+        match_bits(self as u16, u8::MAX as u16, bits, depth, increase) as u8
+    }
+
+    fn unpack_bits(self, depth: u8) -> Vec<u8> {
+        unpack_bits(self, depth)
+    }
+
+    fn high_bits(self) -> u16 {
+        (self >> MAX_BITS_PER_CHANNEL) as u16
+    }
+}
+
+impl Sample for u16 {
+    fn pack_bits(self, bits: &[u8], depth: u8) -> Self {
+        // The bits carrying the message are all in the low byte
+        (self & 0xFF00) | pack_bits(self as u8, bits, depth) as u16
+    }
+
+    fn match_bits(self, bits: &[u8], depth: u8, increase: bool) -> Self {
+        // Moving the value can carry into the high byte
+        match_bits(self, u16::MAX, bits, depth, increase)
+    }
+
+    fn unpack_bits(self, depth: u8) -> Vec<u8> {
+        unpack_bits(self as u8, depth)
+    }
+
+    fn high_bits(self) -> u16 {
+        self >> MAX_BITS_PER_CHANNEL
+    }
+}
+
+/// Order in which the channels of an image are visited to write or read the bits
+///
+/// # Arguments
+///
+/// * `count` - Number of channel positions of the image (width x height x channels)
+/// * `seed` - When provided, the positions are scattered across the whole image. Otherwise
+///   they go from the top left pixel, channel after channel
+/// * `channels` - Only the positions of these channels are visited
+/// * `channel_count` - Number of channels of each pixel of the image (E.g. 3 for RGB)
+///
+pub fn channel_positions(
+    count: usize,
+    seed: Option<[u8; 32]>,
+    channels: ChannelMask,
+    channel_count: u8,
+) -> Box<dyn Iterator<Item = usize>> {
+    let positions: Box<dyn Iterator<Item = usize>> = match seed {
+        Some(s) => Box::new(ScatteredPositions::new(count, s)),
+        None => Box::new(0..count),
+    };
+    if channels.sample_count(channel_count) == channel_count {
+        return positions;
+    }
+    Box::new(positions.filter(move |p| channels.selects(p % channel_count as usize, channel_count)))
+}
+
+/// Order in which the channels of an image are visited, see `EmbeddingOrder`
+#[derive(Clone)]
+pub enum ChannelOrder {
+    /// From the top left pixel, channel after channel
+    Sequential,
+    /// Scattered across the whole image by the seed
+    Scattered([u8; 32]),
+    /// Pixel after pixel from the most textured, see `texture_order`
+    Adaptive(Rc<[usize]>),
+}
+
+impl ChannelOrder {
+    /// The channel positions to visit in this order, see `channel_positions`
+    pub fn positions(
+        &self,
+        count: usize,
+        channels: ChannelMask,
+        channel_count: u8,
+    ) -> Box<dyn Iterator<Item = usize>> {
+        match self {
+            ChannelOrder::Sequential => channel_positions(count, None, channels, channel_count),
+            ChannelOrder::Scattered(seed) => {
+                channel_positions(count, Some(*seed), channels, channel_count)
+            }
+            ChannelOrder::Adaptive(pixels) => {
+                let pixels = Rc::clone(pixels);
+                let channel_count = channel_count as usize;
+                Box::new(
+                    (0..pixels.len())
+                        .flat_map(move |i| {
+                            let first = pixels[i] * channel_count;
+                            first..first + channel_count
+                        })
+                        .filter(move |p| channels.selects(p % channel_count, channel_count as u8)),
+                )
+            }
+        }
+    }
+}
+
+/// Rank the pixels of an image from the most textured to the flattest. The texture of a pixel is
+/// the sum of the differences with its four neighbors, computed from the high bits of the
+/// channels only (see `Sample::high_bits`): the ranking is the same before and after the
+/// injection. Pixels of the same texture keep their order from the top left pixel
+///
+/// # Arguments
+///
+/// * `samples` - The channels of the whole image (E.g. [R, G, B, A, R, G, B, A, ...])
+/// * `width` - The width of the image in pixel
+/// * `channel_count` - Number of channels of each pixel of the image (E.g. 3 for RGB)
+///
+/// # Returns
+/// The indexes of the pixels, the most textured first
+pub fn texture_order<S: Sample>(samples: &[S], width: u32, channel_count: u8) -> Rc<[usize]> {
+    let channel_count = channel_count as usize;
+    let width = width as usize;
+    let count = samples.len() / channel_count;
+    let difference = |a: usize, b: usize| -> u32 {
+        (0..channel_count)
+            .map(|c| {
+                let first = samples[a * channel_count + c].high_bits();
+                let second = samples[b * channel_count + c].high_bits();
+                u32::from(first.abs_diff(second))
+            })
+            .sum()
+    };
+    let texture: Vec<u32> = (0..count)
+        .map(|pixel| {
+            let x = pixel % width;
+            let mut neighbors = Vec::with_capacity(4);
+            if x > 0 {
+                neighbors.push(pixel - 1);
+            }
+            if x + 1 < width {
+                neighbors.push(pixel + 1);
+            }
+            if pixel >= width {
+                neighbors.push(pixel - width);
+            }
+            if pixel + width < count {
+                neighbors.push(pixel + width);
+            }
+            neighbors.into_iter().map(|n| difference(pixel, n)).sum()
+        })
+        .collect();
+    let mut pixels: Vec<usize> = (0..count).collect();
+    pixels.sort_by_key(|p| std::cmp::Reverse(texture[*p]));
+    pixels.into()
+}
+
+/// The channels of a carrier that a `ChannelWriter` can modify, by channel position
+pub trait SampleBuffer {
+    type Sample: Sample;
+
+    /// The channel at a channel position (E.g. 6 is the Blue of the second RGBA pixel)
+    fn sample_mut(&mut self, position: usize) -> &mut Self::Sample;
+}
+
+impl<P> SampleBuffer for ImageBuffer<P, Vec<P::Subpixel>>
+where
+    P: Pixel,
+    P::Subpixel: Sample,
+{
+    type Sample = P::Subpixel;
+
+    fn sample_mut(&mut self, position: usize) -> &mut P::Subpixel {
+        let width = self.width();
+        sample_mut(self, width, position)
+    }
+}
+
+impl<S: Sample> SampleBuffer for [S] {
+    type Sample = S;
+
+    fn sample_mut(&mut self, position: usize) -> &mut S {
+        &mut self[position]
+    }
+}
+
+/// Writes bytes into the least significant bits of the channels of an image, `depth` bits per
+/// channel in the order of the positions
+pub struct ChannelWriter<'a, B: SampleBuffer + ?Sized> {
+    img: &'a mut B,
+    positions: Box<dyn Iterator<Item = usize>>,
+    depth: u8,
+    method: EmbeddingMethod,
+}
+
+impl<'a, B: SampleBuffer + ?Sized> ChannelWriter<'a, B> {
+    /// # Arguments
+    ///
+    /// * `img` - The image to modify, in any of its pixel layouts (E.g. `RgbaImage`), or the
+    ///   channels of another carrier (E.g. the coefficients of a JPEG image)
+    /// * `positions` - The channel positions to use, see `channel_positions`
+    /// * `depth` - Number of least significant bits used in each channel (1 to 4)
+    /// * `method` - How the bits of a channel are changed
+    pub fn new(
+        img: &'a mut B,
+        positions: Box<dyn Iterator<Item = usize>>,
+        depth: u8,
+        method: EmbeddingMethod,
+    ) -> Self {
+        ChannelWriter {
+            img,
+            positions,
+            depth,
+            method,
+        }
+    }
+
+    /// Write the bytes after the ones already written. Every call starts on a new channel
+    ///
+    /// # Errors
+    ///
+    /// Returns `Capacity` if the image runs out of channels
+    pub fn write_bytes(&mut self, data_bytes: &[u8]) -> Result<(), SteganographyError> {
+        let mut rng = rand::thread_rng();
+        let bits: Vec<u8> = data_bytes
+            .iter()
+            .flat_map(|b| char_to_binary_string(b).into_bytes())
+            .map(|c| u8::from(c != b'0'))
+            .collect();
+        for (index, chunk) in bits.chunks(self.depth as usize).enumerate() {
+            let position = self.positions.next().ok_or(SteganographyError::Capacity {
+                required: data_bytes.len(),
+                available: index * self.depth as usize / NUMBER_BIT_PER_BYTE as usize,
+            })?;
+            let sample = self.img.sample_mut(position);
+            *sample = match self.method {
+                EmbeddingMethod::Replacement => sample.pack_bits(chunk, self.depth),
+                EmbeddingMethod::Matching => sample.match_bits(chunk, self.depth, rng.gen()),
+            };
+        }
+        Ok(())
+    }
+
+    /// Write the bytes after the ones already written with a Hamming code: each group of
+    /// `2^matrix_bits - 1` channels holds `matrix_bits` bits in the syndrome of their least
+    /// significant bits, and at most one channel of the group changes. Only the least significant
+    /// bit of each channel is used, whatever the depth
+    ///
+    /// # Errors
+    ///
+    /// Returns `Capacity` if the image runs out of channels
+    pub fn write_matrix_bytes(
+        &mut self,
+        data_bytes: &[u8],
+        matrix_bits: u8,
+    ) -> Result<(), SteganographyError> {
+        let mut rng = rand::thread_rng();
+        let bits: Vec<u8> = data_bytes
+            .iter()
+            .flat_map(|b| char_to_binary_string(b).into_bytes())
+            .map(|c| u8::from(c != b'0'))
+            .collect();
+        let length = group_length(matrix_bits);
+        for (index, chunk) in bits.chunks(matrix_bits as usize).enumerate() {
+            let positions: Vec<usize> = self.positions.by_ref().take(length).collect();
+            if positions.len() < length {
+                return Err(SteganographyError::Capacity {
+                    required: data_bytes.len(),
+                    available: index * matrix_bits as usize / NUMBER_BIT_PER_BYTE as usize,
+                });
+            }
+            // The last chunk is completed with zeros
+            let value = (0..matrix_bits as usize).fold(0, |acc, i| {
+                (acc << 1) | *chunk.get(i).unwrap_or(&0) as usize
+            });
+            let lsbs: Vec<u8> = positions
+                .iter()
+                .map(|p| self.img.sample_mut(*p).unpack_bits(1)[0])
+                .collect();
+            if let Some(index) = carrier_to_flip(&lsbs, value) {
+                let sample = self.img.sample_mut(positions[index]);
+                let bit = [lsbs[index] ^ 1];
+                *sample = match self.method {
+                    EmbeddingMethod::Replacement => sample.pack_bits(&bit, 1),
+                    EmbeddingMethod::Matching => sample.match_bits(&bit, 1, rng.gen()),
+                };
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The channel of an image at a channel position (E.g. 6 is the Blue of the second RGBA pixel)
+fn sample_mut<P: Pixel>(
+    img: &mut ImageBuffer<P, Vec<P::Subpixel>>,
+    width: u32,
+    position: usize,
+) -> &mut P::Subpixel {
+    let pixel_position = position / P::CHANNEL_COUNT as usize;
+    let coordinate = get_coordinate(pixel_position as u32, width);
+    let pixel = img.get_pixel_mut(coordinate.0, coordinate.1);
+    &mut pixel.channels_mut()[position % P::CHANNEL_COUNT as usize]
+}
+
+/// Reads bytes from the least significant bits of the channels of an image, `depth` bits per
+/// channel in the order of the positions. The reading stops when there is not enough channels
+/// left for a full byte
+pub struct ChannelReader<'a, S: Sample> {
+    buffer: &'a [S],
+    positions: Box<dyn Iterator<Item = usize>>,
+    depth: u8,
+    /// Number of bits of each group of channels once the header is read, see
+    /// `ChannelWriter::write_matrix_bytes`
+    matrix_bits: Option<u8>,
+    /// Bits read from a channel but not yet assembled into a byte
+    pending: VecDeque<u8>,
+}
+
+impl<'a, S: Sample> ChannelReader<'a, S> {
+    /// # Arguments
+    ///
+    /// * `buffer` - The channels of the whole image (E.g. [R, G, B, A, R, G, B, A, ...])
+    /// * `positions` - The channel positions to use, see `channel_positions`
+    /// * `depth` - Number of least significant bits used in each channel (1 to 4)
+    pub fn new(buffer: &'a [S], positions: Box<dyn Iterator<Item = usize>>, depth: u8) -> Self {
+        ChannelReader {
+            buffer,
+            positions,
+            depth,
+            matrix_bits: None,
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Read the next bytes from the syndromes of groups of channels, the way
+    /// `ChannelWriter::write_matrix_bytes` wrote them
+    pub fn use_matrix_bits(&mut self, matrix_bits: u8) {
+        self.matrix_bits = Some(matrix_bits);
+    }
+
+    fn read_byte(&mut self) -> Option<u8> {
+        while self.pending.len() < NUMBER_BIT_PER_BYTE as usize {
+            match self.matrix_bits {
+                None => {
+                    let position = self.positions.next()?;
+                    self.pending
+                        .extend(self.buffer[position].unpack_bits(self.depth));
+                }
+                Some(matrix_bits) => {
+                    let length = group_length(matrix_bits);
+                    let lsbs: Vec<u8> = self
+                        .positions
+                        .by_ref()
+                        .take(length)
+                        .map(|p| self.buffer[p].unpack_bits(1)[0])
+                        .collect();
+                    if lsbs.len() < length {
+                        return None;
+                    }
+                    let value = syndrome(&lsbs);
+                    self.pending
+                        .extend((0..matrix_bits).rev().map(|i| (value >> i) as u8 & 1));
+                }
+            }
+        }
+        let bits: Vec<u8> = self.pending.drain(..NUMBER_BIT_PER_BYTE as usize).collect();
+        Some(bits_to_byte(&bits))
+    }
+}
+
+impl<S: Sample> Read for ChannelReader<'_, S> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let mut count = 0;
+        for byte in buf.iter_mut() {
+            match self.read_byte() {
+                Some(b) => *byte = b,
+                None => break,
+            }
+            count += 1;
+        }
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod test_bitstream {
+    use super::*;
+    use image::RgbaImage;
+
+    #[test]
+    fn test_get_coordinate_first_row() {
+        let result = get_coordinate(2, 10);
+        assert_eq!(result.0, 2);
+        assert_eq!(result.1, 0);
+    }
+
+    #[test]
+    fn test_get_coordinate_second_row() {
+        let result = get_coordinate(10, 10);
+        assert_eq!(result.0, 0);
+        assert_eq!(result.1, 1);
+    }
+
+    #[test]
+    fn test_get_coordinate_third_row() {
+        let result = get_coordinate(22, 10);
+        assert_eq!(result.0, 2);
+        assert_eq!(result.1, 2);
+    }
+
+    #[test]
+    fn test_scattered_positions_visit_every_position_once() {
+        let mut positions: Vec<usize> = ScatteredPositions::new(1000, [7u8; 32]).collect();
+        assert_ne!(positions, (0..1000).collect::<Vec<usize>>());
+        positions.sort();
+        assert_eq!(positions, (0..1000).collect::<Vec<usize>>());
+    }
+
+    #[test]
+    fn test_scattered_positions_depend_on_seed() {
+        let first: Vec<usize> = ScatteredPositions::new(100, [1u8; 32]).collect();
+        let same: Vec<usize> = ScatteredPositions::new(100, [1u8; 32]).collect();
+        let other: Vec<usize> = ScatteredPositions::new(100, [2u8; 32]).collect();
+        assert_eq!(first, same);
+        assert_ne!(first, other);
+    }
+
+    #[test]
+    fn test_scattered_positions_pinned() {
+        // The order must never change, the images scattered before would not be read back
+        let first: Vec<usize> = ScatteredPositions::new(1000, [7u8; 32]).take(8).collect();
+        assert_eq!(first, vec![257, 439, 343, 897, 138, 465, 671, 425]);
+    }
+
+    #[test]
+    fn test_write_read_scattered() {
+        let mut img = RgbaImage::new(8, 8);
+        let seed = Some([3u8; 32]);
+        let count = img.as_raw().len();
+        ChannelWriter::new(
+            &mut img,
+            channel_positions(count, seed, ChannelMask::RGBA, 4),
+            1,
+            EmbeddingMethod::Replacement,
+        )
+        .write_bytes(b"Hi!")
+        .unwrap();
+        let mut reader = ChannelReader::new(
+            img.as_raw(),
+            channel_positions(count, seed, ChannelMask::RGBA, 4),
+            1,
+        );
+        let mut bytes = [0u8; 3];
+        reader.read_exact(&mut bytes).unwrap();
+        assert_eq!(&bytes, b"Hi!");
+    }
+
+    #[test]
+    fn test_write_read_every_depth() {
+        for depth in 1..=MAX_BITS_PER_CHANNEL {
+            let original = RgbaImage::from_pixel(4, 4, image::Rgba([255, 0, 170, 85]));
+            let mut img = original.clone();
+            let count = img.as_raw().len();
+            ChannelWriter::new(
+                &mut img,
+                channel_positions(count, None, ChannelMask::RGBA, 4),
+                depth,
+                EmbeddingMethod::Replacement,
+            )
+            .write_bytes(b"Depth")
+            .unwrap();
+            // Only the low bits of the channels changed
+            for (before, after) in original.as_raw().iter().zip(img.as_raw()) {
+                assert_eq!(before >> depth, after >> depth);
+            }
+            let mut reader = ChannelReader::new(
+                img.as_raw(),
+                channel_positions(count, None, ChannelMask::RGBA, 4),
+                depth,
+            );
+            let mut bytes = [0u8; 5];
+            reader.read_exact(&mut bytes).unwrap();
+            assert_eq!(&bytes, b"Depth");
+        }
+    }
+
+    #[test]
+    fn test_write_read_matrix() {
+        let original = RgbaImage::from_pixel(16, 16, image::Rgba([10, 11, 12, 13]));
+        let mut img = original.clone();
+        let count = img.as_raw().len();
+        let mut writer = ChannelWriter::new(
+            &mut img,
+            channel_positions(count, None, ChannelMask::RGBA, 4),
+            1,
+            EmbeddingMethod::Replacement,
+        );
+        writer.write_bytes(b"H").unwrap();
+        writer.write_matrix_bytes(b"Matrix", 3).unwrap();
+        // 8 channels for "H", then 16 groups of 7 channels with at most one change each
+        let changes = original
+            .as_raw()
+            .iter()
+            .zip(img.as_raw())
+            .skip(8)
+            .filter(|(before, after)| before != after)
+            .count();
+        assert!(changes <= 16);
+        let mut reader = ChannelReader::new(
+            img.as_raw(),
+            channel_positions(count, None, ChannelMask::RGBA, 4),
+            1,
+        );
+        let mut bytes = [0u8; 1];
+        reader.read_exact(&mut bytes).unwrap();
+        assert_eq!(&bytes, b"H");
+        reader.use_matrix_bits(3);
+        let mut bytes = [0u8; 6];
+        reader.read_exact(&mut bytes).unwrap();
+        assert_eq!(&bytes, b"Matrix");
+    }
+
+    #[test]
+    fn test_write_matrix_capacity() {
+        let mut img = RgbaImage::new(2, 2);
+        let count = img.as_raw().len();
+        let result = ChannelWriter::new(
+            &mut img,
+            channel_positions(count, None, ChannelMask::RGBA, 4),
+            1,
+            EmbeddingMethod::Replacement,
+        )
+        .write_matrix_bytes(b"A", 3);
+        assert!(matches!(result, Err(SteganographyError::Capacity { .. })));
+    }
+
+    /// Flat on the left half, a checkerboard of black and white on the right half
+    fn half_textured_image() -> RgbaImage {
+        RgbaImage::from_fn(8, 8, |x, y| {
+            if x < 4 || (x + y) % 2 == 0 {
+                image::Rgba([0, 0, 0, 255])
+            } else {
+                image::Rgba([255, 255, 255, 255])
+            }
+        })
+    }
+
+    #[test]
+    fn test_texture_order_textured_first() {
+        let img = half_textured_image();
+        let pixels = texture_order(img.as_raw(), 8, 4);
+        assert_eq!(pixels.len(), 64);
+        // The 32 pixels of the right half (and the column next to it) come before the flat ones
+        assert!(pixels[..32].iter().all(|p| p % 8 >= 3));
+        // The flat pixels keep their order, the last one is at the bottom of the third column
+        assert_eq!(pixels[63], 7 * 8 + 2);
+    }
+
+    #[test]
+    fn test_texture_order_ignores_low_bits() {
+        let original = half_textured_image();
+        let mut img = original.clone();
+        let order = ChannelOrder::Adaptive(texture_order(original.as_raw(), 8, 4));
+        let count = img.as_raw().len();
+        ChannelWriter::new(
+            &mut img,
+            order.positions(count, ChannelMask::RGBA, 4),
+            MAX_BITS_PER_CHANNEL,
+            EmbeddingMethod::Replacement,
+        )
+        .write_bytes(b"Adaptive")
+        .unwrap();
+        assert_ne!(img, original);
+        assert_eq!(
+            texture_order(img.as_raw(), 8, 4),
+            texture_order(original.as_raw(), 8, 4)
+        );
+        let mut reader = ChannelReader::new(
+            img.as_raw(),
+            order.positions(count, ChannelMask::RGBA, 4),
+            4,
+        );
+        let mut bytes = [0u8; 8];
+        reader.read_exact(&mut bytes).unwrap();
+        assert_eq!(&bytes, b"Adaptive");
+    }
+
+    #[test]
+    fn test_write_read_matching() {
+        // The edges of the channels can only move in one direction
+        let original = RgbaImage::from_pixel(8, 8, image::Rgba([255, 0, 170, 85]));
+        let mut img = original.clone();
+        let count = img.as_raw().len();
+        ChannelWriter::new(
+            &mut img,
+            channel_positions(count, None, ChannelMask::RGBA, 4),
+            1,
+            EmbeddingMethod::Matching,
+        )
+        .write_bytes(b"Matching")
+        .unwrap();
+        for (before, after) in original.as_raw().iter().zip(img.as_raw()) {
+            assert!(before.abs_diff(*after) <= 1);
+        }
+        let mut reader = ChannelReader::new(
+            img.as_raw(),
+            channel_positions(count, None, ChannelMask::RGBA, 4),
+            1,
+        );
+        let mut bytes = [0u8; 8];
+        reader.read_exact(&mut bytes).unwrap();
+        assert_eq!(&bytes, b"Matching");
+    }
+
+    #[test]
+    fn test_channel_positions_skip_alpha() {
+        let positions: Vec<usize> = channel_positions(8, None, ChannelMask::RGB, 4).collect();
+        assert_eq!(positions, vec![0, 1, 2, 4, 5, 6]);
+        let mut positions: Vec<usize> = channel_positions(
+            400,
+            Some([5u8; 32]),
+            ChannelMask::from_bits(0b0100).unwrap(),
+            4,
+        )
+        .collect();
+        positions.sort();
+        assert_eq!(
+            positions,
+            (0..100).map(|p| p * 4 + 2).collect::<Vec<usize>>()
+        );
+    }
+
+    #[test]
+    fn test_write_read_rgb16() {
+        let mut img: ImageBuffer<image::Rgb<u16>, Vec<u16>> =
+            ImageBuffer::from_pixel(4, 4, image::Rgb([0xABCD, 0, 0xFFFF]));
+        let count = img.as_raw().len();
+        ChannelWriter::new(
+            &mut img,
+            channel_positions(count, None, ChannelMask::RGB, 3),
+            4,
+            EmbeddingMethod::Replacement,
+        )
+        .write_bytes(b"16 bits")
+        .unwrap();
+        assert!(img.as_raw().iter().step_by(3).all(|c| c >> 8 == 0xAB));
+        let mut reader = ChannelReader::new(
+            img.as_raw(),
+            channel_positions(count, None, ChannelMask::RGB, 3),
+            4,
+        );
+        let mut bytes = [0u8; 7];
+        reader.read_exact(&mut bytes).unwrap();
+        assert_eq!(&bytes, b"16 bits");
+    }
+
+    #[test]
+    fn test_write_read_luma_alpha_skip_alpha() {
+        let mut img = image::GrayAlphaImage::from_pixel(4, 4, image::LumaA([100, 255]));
+        let count = img.as_raw().len();
+        ChannelWriter::new(
+            &mut img,
+            channel_positions(count, None, ChannelMask::RGB, 2),
+            1,
+            EmbeddingMethod::Replacement,
+        )
+        .write_bytes(b"L")
+        .unwrap();
+        assert!(img.pixels().all(|p| p[1] == 255));
+        let mut reader = ChannelReader::new(
+            img.as_raw(),
+            channel_positions(count, None, ChannelMask::RGB, 2),
+            1,
+        );
+        let mut bytes = [0u8; 1];
+        reader.read_exact(&mut bytes).unwrap();
+        assert_eq!(&bytes, b"L");
+    }
+
+    #[test]
+    fn test_write_too_many_bytes_with_depth() {
+        let mut img = RgbaImage::new(2, 2);
+        let count = img.as_raw().len();
+        let result = ChannelWriter::new(
+            &mut img,
+            channel_positions(count, None, ChannelMask::RGBA, 4),
+            2,
+            EmbeddingMethod::Replacement,
+        )
+        .write_bytes(b"abcde");
+        assert!(matches!(
+            result,
+            Err(SteganographyError::Capacity {
+                required: 5,
+                available: 4
+            })
+        ));
+    }
+
+    #[test]
+    fn test_write_too_many_bytes() {
+        let mut img = RgbaImage::new(2, 2);
+        let count = img.as_raw().len();
+        let result = ChannelWriter::new(
+            &mut img,
+            channel_positions(count, None, ChannelMask::RGBA, 4),
+            1,
+            EmbeddingMethod::Replacement,
+        )
+        .write_bytes(b"abc");
+        assert!(matches!(
+            result,
+            Err(SteganographyError::Capacity {
+                required: 3,
+                available: 2
+            })
+        ));
+    }
+}
@@ -0,0 +1,269 @@
+use std::io::{self, Read, Write};
+
+use super::error::SteganographyError;
+use super::options::Compression;
+
+/// Quality of the Brotli compression, from 0 to 11
+const BROTLI_QUALITY: u32 = 11;
+
+/// Size of the Brotli window as a power of 2, from 10 to 24
+const BROTLI_WINDOW: u32 = 22;
+
+/// Level of the Zstandard compression, from 1 to 22. The payloads are small enough (they fit
+/// into an image) for a slow level
+const ZSTD_LEVEL: i32 = 19;
+
+/// Size of the buffers of the Brotli encoder and decoder
+const BUFFER_SIZE: usize = 4096;
+
+/// Largest ratio between the length of a decompressed payload and the length of the payload
+/// read from the image. A small payload of an untrusted image must not inflate to gigabytes
+const MAX_COMPRESSION_RATIO: u64 = 1024;
+
+/// Length of a decompressed payload always accepted, whatever its compressed length
+const MIN_DECOMPRESSED_LIMIT: u64 = 16 * 1024 * 1024;
+
+/// Largest length a payload decompresses to, see `MAX_COMPRESSION_RATIO`
+///
+/// # Arguments
+/// compressed_length - Number of bytes of the payload read from the image
+pub fn decompressed_limit(compressed_length: usize) -> u64 {
+    (compressed_length as u64)
+        .saturating_mul(MAX_COMPRESSION_RATIO)
+        .max(MIN_DECOMPRESSED_LIMIT)
+}
+
+/// Identifier of the compression written in the header
+///
+/// # Arguments
+/// compression - The compression of the payload
+pub fn compression_id(compression: Compression) -> u8 {
+    match compression {
+        Compression::None => 0,
+        Compression::Deflate => 1,
+        Compression::Zstd => 2,
+        Compression::Brotli => 3,
+    }
+}
+
+/// Compression from the identifier written in the header
+///
+/// # Arguments
+/// id - The value returned by `compression_id`
+///
+/// # Returns
+/// `None` when the identifier is unknown
+pub fn compression_from_id(id: u8) -> Option<Compression> {
+    match id {
+        0 => Some(Compression::None),
+        1 => Some(Compression::Deflate),
+        2 => Some(Compression::Zstd),
+        3 => Some(Compression::Brotli),
+        _ => None,
+    }
+}
+
+/// Compress the payload before it is encrypted
+///
+/// # Arguments
+/// * `payload` - The bytes to compress
+/// * `compression` - The codec to use, `Compression::None` returns the bytes as is
+///
+/// # Errors
+/// Returns an `Io` error if the codec fails, `InvalidOption` if the payload compresses so well
+/// that the extraction would refuse to decompress it (see `decompressed_limit`)
+pub fn compress(payload: &[u8], compression: Compression) -> Result<Vec<u8>, SteganographyError> {
+    let compressed = compress_with(payload, compression)?;
+    if payload.len() as u64 > decompressed_limit(compressed.len()) {
+        return Err(SteganographyError::InvalidOption(format!(
+            "The payload compresses more than {} times, it cannot be extracted back compressed",
+            MAX_COMPRESSION_RATIO
+        )));
+    }
+    Ok(compressed)
+}
+
+fn compress_with(payload: &[u8], compression: Compression) -> Result<Vec<u8>, SteganographyError> {
+    Ok(match compression {
+        Compression::None => payload.to_vec(),
+        Compression::Deflate => {
+            let mut encoder =
+                flate2::write::DeflateEncoder::new(Vec::new(), flate2::Compression::best());
+            encoder.write_all(payload)?;
+            encoder.finish()?
+        }
+        Compression::Zstd => zstd::encode_all(payload, ZSTD_LEVEL)?,
+        Compression::Brotli => {
+            let mut encoder = brotli::CompressorWriter::new(
+                Vec::new(),
+                BUFFER_SIZE,
+                BROTLI_QUALITY,
+                BROTLI_WINDOW,
+            );
+            encoder.write_all(payload)?;
+            encoder.into_inner()
+        }
+    })
+}
+
+/// Wrap the reader of a compressed payload into a reader of the original bytes. The bytes are
+/// decompressed as they are read
+///
+/// # Arguments
+/// * `reader` - The reader of the compressed payload, once decrypted
+/// * `compression` - The codec used by `compress`
+/// * `compressed_length` - Number of bytes of the payload read from the image, it limits the
+///   number of bytes decompressed (see `decompressed_limit`)
+///
+/// # Errors
+/// Returns `CorruptPayload` if the decoder cannot start. Reading returns `CorruptPayload` once
+/// the payload decompresses to more than the limit
+pub fn decompress_reader<'a>(
+    reader: Box<dyn Read + 'a>,
+    compression: Compression,
+    compressed_length: usize,
+) -> Result<Box<dyn Read + 'a>, SteganographyError> {
+    let decompressor: Box<dyn Read + 'a> = match compression {
+        Compression::None => return Ok(reader),
+        Compression::Deflate => Box::new(Decompressor(flate2::read::DeflateDecoder::new(reader))),
+        Compression::Zstd => Box::new(Decompressor(
+            zstd::stream::read::Decoder::new(reader).map_err(corrupt_payload)?,
+        )),
+        Compression::Brotli => {
+            Box::new(Decompressor(brotli::Decompressor::new(reader, BUFFER_SIZE)))
+        }
+    };
+    Ok(Box::new(LimitedReader {
+        reader: decompressor,
+        remaining: decompressed_limit(compressed_length),
+    }))
+}
+
+/// Fails once more than `remaining` bytes are read
+struct LimitedReader<R: Read> {
+    reader: R,
+    remaining: u64,
+}
+
+impl<R: Read> Read for LimitedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // One byte more than the limit tells whether the payload goes past it
+        let length = buf.len().min(self.remaining.saturating_add(1) as usize);
+        let read = self.reader.read(&mut buf[..length])?;
+        if read as u64 > self.remaining {
+            return Err(io::Error::other(SteganographyError::CorruptPayload(
+                "The message decompresses to more bytes than the image can justify".to_string(),
+            )));
+        }
+        self.remaining -= read as u64;
+        Ok(read)
+    }
+}
+
+/// Reports the failures of a decoder as a corrupt payload
+struct Decompressor<R: Read>(R);
+
+impl<R: Read> Read for Decompressor<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf).map_err(|e| {
+            // The failures of the library below the decoder are kept as is
+            if e.get_ref()
+                .is_some_and(|inner| inner.is::<SteganographyError>())
+            {
+                e
+            } else {
+                corrupt_payload(e).into()
+            }
+        })
+    }
+}
+
+fn corrupt_payload(error: io::Error) -> SteganographyError {
+    SteganographyError::CorruptPayload(format!("The message cannot be decompressed: {}", error))
+}
+
+#[cfg(test)]
+mod test_compression {
+    use super::*;
+
+    fn round_trip(compression: Compression) {
+        let payload = "The quick brown fox jumps over the lazy dog. ".repeat(100);
+        let compressed = compress(payload.as_bytes(), compression).unwrap();
+        assert!(compressed.len() < payload.len() / 10);
+        let mut result = String::new();
+        decompress_reader(Box::new(&compressed[..]), compression, compressed.len())
+            .unwrap()
+            .read_to_string(&mut result)
+            .unwrap();
+        assert_eq!(result, payload);
+    }
+
+    #[test]
+    fn test_deflate_round_trip() {
+        round_trip(Compression::Deflate);
+    }
+
+    #[test]
+    fn test_zstd_round_trip() {
+        round_trip(Compression::Zstd);
+    }
+
+    #[test]
+    fn test_brotli_round_trip() {
+        round_trip(Compression::Brotli);
+    }
+
+    #[test]
+    fn test_compression_id_round_trip() {
+        for compression in [
+            Compression::None,
+            Compression::Deflate,
+            Compression::Zstd,
+            Compression::Brotli,
+        ] {
+            assert_eq!(
+                compression_from_id(compression_id(compression)),
+                Some(compression)
+            );
+        }
+    }
+
+    #[test]
+    fn test_decompress_invalid_data() {
+        let mut result = Vec::new();
+        let error: SteganographyError =
+            decompress_reader(Box::new(&[0xFFu8; 16][..]), Compression::Deflate, 16)
+                .unwrap()
+                .read_to_end(&mut result)
+                .unwrap_err()
+                .into();
+        assert!(matches!(error, SteganographyError::CorruptPayload(_)));
+    }
+
+    #[test]
+    fn test_decompress_above_limit() {
+        let payload = vec![0u8; MIN_DECOMPRESSED_LIMIT as usize + 1];
+        let compressed = zstd::encode_all(&payload[..], 1).unwrap();
+        let mut result = Vec::new();
+        let error: SteganographyError = decompress_reader(
+            Box::new(&compressed[..]),
+            Compression::Zstd,
+            compressed.len(),
+        )
+        .unwrap()
+        .read_to_end(&mut result)
+        .unwrap_err()
+        .into();
+        assert!(matches!(error, SteganographyError::CorruptPayload(_)));
+        assert!(result.len() as u64 <= MIN_DECOMPRESSED_LIMIT);
+    }
+
+    #[test]
+    fn test_compress_above_limit() {
+        let payload = vec![0u8; MIN_DECOMPRESSED_LIMIT as usize + 1];
+        assert!(matches!(
+            compress(&payload, Compression::Deflate),
+            Err(SteganographyError::InvalidOption(_))
+        ));
+    }
+}
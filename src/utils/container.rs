@@ -0,0 +1,467 @@
+use std::fs;
+use std::io::{self, Read};
+
+use super::encryption::{decrypt_bytes, encrypt_bytes, Cipher};
+use super::error::SteganographyError;
+use super::function::{
+    bytes_to_message, extract_container_from_image, find_container, inject_container_into_image,
+    save_image, saved_extract_options, verify_payload,
+};
+use super::options::{SteganographyExtractOption, SteganographyInjectOption};
+
+/// Flag of an entry whose content is encrypted with its own password. The cipher follows
+const ENTRY_ENCRYPTED: u8 = 1;
+
+/// An item of a `Container`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ContainerEntry {
+    /// Name used to find the entry, unique in the container
+    pub name: String,
+    /// How the content was encrypted with the password of the entry, `None` when it was not
+    pub cipher: Option<Cipher>,
+    /// Bytes of the entry as stored, encrypted when `cipher` is set
+    pub data: Vec<u8>,
+}
+
+/// Several independent items hidden into a single image, each found back by its name. Each
+/// entry can be encrypted with its own password, on top of the password of the image
+///
+/// The container is serialized as a directory followed by the content of the entries:
+/// the number of entries (2) then for each entry the length of its name (2), its name, its
+/// encryption flag (1), its cipher when encrypted, its offset (4) and its length (4). The
+/// offsets start after the directory, the numbers are stored big endian
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Container {
+    entries: Vec<ContainerEntry>,
+}
+
+impl Container {
+    /// Create a container without any entry
+    pub fn new() -> Self {
+        Container::default()
+    }
+
+    /// The entries in the order they were added
+    pub fn entries(&self) -> &[ContainerEntry] {
+        &self.entries
+    }
+
+    /// The names of the entries in the order they were added
+    pub fn names(&self) -> Vec<&str> {
+        self.entries.iter().map(|e| e.name.as_str()).collect()
+    }
+
+    /// Add an entry
+    ///
+    /// # Arguments
+    /// * `name` - Name to find the entry back
+    /// * `content` - Bytes of the entry
+    /// * `password` - Secret to encrypt the entry (optional), independent of the password of the
+    ///   image
+    ///
+    /// # Errors
+    /// Returns `InvalidOption` if an entry already has the name
+    pub fn add(
+        &mut self,
+        name: &str,
+        content: &[u8],
+        password: &Option<String>,
+    ) -> Result<(), SteganographyError> {
+        if self.entries.iter().any(|e| e.name == name) {
+            return Err(SteganographyError::InvalidOption(format!(
+                "The entry \"{}\" already exists",
+                name
+            )));
+        }
+        let (cipher, data) = match password {
+            Some(password) => {
+                let cipher = Cipher::generate();
+                // The name is authenticated with the content so entries cannot be swapped
+                let data = encrypt_bytes(content, password, cipher, name.as_bytes())?;
+                (Some(cipher), data)
+            }
+            None => (None, content.to_vec()),
+        };
+        self.entries.push(ContainerEntry {
+            name: name.to_string(),
+            cipher,
+            data,
+        });
+        Ok(())
+    }
+
+    /// Get the content of an entry
+    ///
+    /// # Arguments
+    /// * `name` - Name given to `add`
+    /// * `password` - Secret given to `add` (optional)
+    ///
+    /// # Errors
+    /// Returns `InvalidOption` if no entry has the name, `BadPassword` if the password cannot
+    /// decrypt the entry
+    pub fn get(
+        &self,
+        name: &str,
+        password: &Option<String>,
+    ) -> Result<Vec<u8>, SteganographyError> {
+        let entry = self.entry(name)?;
+        match (entry.cipher, password) {
+            (None, _) => Ok(entry.data.clone()),
+            (Some(_), None) => Err(SteganographyError::BadPassword),
+            (Some(cipher), Some(password)) => {
+                decrypt_bytes(&entry.data, password, cipher, entry.name.as_bytes())
+            }
+        }
+    }
+
+    /// Remove an entry
+    ///
+    /// # Errors
+    /// Returns `InvalidOption` if no entry has the name
+    pub fn remove(&mut self, name: &str) -> Result<(), SteganographyError> {
+        self.entry(name)?;
+        self.entries.retain(|e| e.name != name);
+        Ok(())
+    }
+
+    fn entry(&self, name: &str) -> Result<&ContainerEntry, SteganographyError> {
+        self.entries.iter().find(|e| e.name == name).ok_or_else(|| {
+            SteganographyError::InvalidOption(format!("The entry \"{}\" does not exist", name))
+        })
+    }
+
+    /// Serialize the container into the payload injected into the image
+    ///
+    /// # Errors
+    /// Returns `InvalidOption` if there are more than 65535 entries or if a name is longer than
+    /// 65535 bytes
+    pub fn to_bytes(&self) -> Result<Vec<u8>, SteganographyError> {
+        let too_large =
+            |what: &str| SteganographyError::InvalidOption(format!("Too many {}", what));
+        let mut bytes = Vec::new();
+        bytes.extend(
+            u16::try_from(self.entries.len())
+                .map_err(|_| too_large("entries"))?
+                .to_be_bytes(),
+        );
+        let mut offset = 0usize;
+        for entry in &self.entries {
+            bytes.extend(
+                u16::try_from(entry.name.len())
+                    .map_err(|_| too_large("bytes in the name of an entry"))?
+                    .to_be_bytes(),
+            );
+            bytes.extend(entry.name.as_bytes());
+            match entry.cipher {
+                Some(cipher) => {
+                    bytes.push(ENTRY_ENCRYPTED);
+                    bytes.extend(cipher.to_bytes());
+                }
+                None => bytes.push(0),
+            }
+            bytes.extend((offset as u32).to_be_bytes());
+            bytes.extend((entry.data.len() as u32).to_be_bytes());
+            offset += entry.data.len();
+        }
+        for entry in &self.entries {
+            bytes.extend(&entry.data);
+        }
+        Ok(bytes)
+    }
+
+    /// Read the container from the payload extracted from an image
+    ///
+    /// # Errors
+    /// Returns `CorruptPayload` if the directory is truncated or points outside of the payload
+    pub fn read_from<R: Read>(reader: &mut R) -> Result<Self, SteganographyError> {
+        let count = u16::from_be_bytes(read_array(reader)?);
+        let mut directory = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let mut name = vec![0u8; u16::from_be_bytes(read_array(reader)?) as usize];
+            read_exact(reader, &mut name)?;
+            let name = String::from_utf8(name).map_err(|_| {
+                SteganographyError::CorruptPayload(
+                    "The name of an entry is not valid UTF-8".to_string(),
+                )
+            })?;
+            let [flags] = read_array(reader)?;
+            let cipher = if flags & ENTRY_ENCRYPTED != 0 {
+                Some(Cipher::read_from(reader)?)
+            } else {
+                None
+            };
+            let offset = u32::from_be_bytes(read_array(reader)?) as usize;
+            let length = u32::from_be_bytes(read_array(reader)?) as usize;
+            directory.push((name, cipher, offset, length));
+        }
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        let entries = directory
+            .into_iter()
+            .map(|(name, cipher, offset, length)| {
+                let content = offset
+                    .checked_add(length)
+                    .and_then(|end| data.get(offset..end))
+                    .ok_or_else(truncated)?;
+                Ok(ContainerEntry {
+                    name,
+                    cipher,
+                    data: content.to_vec(),
+                })
+            })
+            .collect::<Result<Vec<_>, SteganographyError>>()?;
+        Ok(Container { entries })
+    }
+}
+
+fn read_array<R: Read, const N: usize>(reader: &mut R) -> Result<[u8; N], SteganographyError> {
+    let mut bytes = [0u8; N];
+    read_exact(reader, &mut bytes)?;
+    Ok(bytes)
+}
+
+/// Fill the buffer, a directory that ends early is reported as a corrupt payload
+fn read_exact<R: Read>(reader: &mut R, buffer: &mut [u8]) -> Result<(), SteganographyError> {
+    reader.read_exact(buffer).map_err(|e| match e.kind() {
+        io::ErrorKind::UnexpectedEof => truncated(),
+        _ => e.into(),
+    })
+}
+
+fn truncated() -> SteganographyError {
+    SteganographyError::CorruptPayload("The directory of the entries is truncated".to_string())
+}
+
+/// Name of the entry given in the options
+fn entry_name(entry: &Option<String>) -> Result<&str, SteganographyError> {
+    entry.as_deref().ok_or_else(|| {
+        SteganographyError::InvalidOption("The name of the entry is required".to_string())
+    })
+}
+
+/// Add an entry to the container of the image referenced by `input_image_path` and save the
+/// result at `output_image_path`. A new container is created when the image does not have one
+///
+/// # Arguments
+///
+/// * `options` - Structure with the name of the entry, its content (the `message` or the file
+///   referenced by `input_file_path`), its password (optional) and the encoding of the image
+///
+/// # Errors
+///
+/// Returns an error if the image carries a message instead of a container, if the name is
+/// already used or if the container does not fit into the image
+pub fn add_entry(options: SteganographyInjectOption) -> Result<(), SteganographyError> {
+    let name = entry_name(&options.entry)?;
+    let content = match &options.input_file_path {
+        Some(path) => fs::read(path)?,
+        None => options.message.clone().into_bytes(),
+    };
+    let img = image::open(&options.input_image_path)?;
+    let mut container = find_container(&img, &options.password)?.unwrap_or_default();
+    container.add(name, &content, &options.entry_password)?;
+    let new_img = inject_container_into_image(&img, &container, &options)?;
+    save_image(&new_img, &options.output_image_path)?;
+    if options.verify {
+        let extracted = image::open(&options.output_image_path)
+            .map_err(SteganographyError::from)
+            .and_then(|img| extract_container_from_image(&img, &saved_extract_options(&options)))
+            .and_then(|container| container.get(name, &options.entry_password));
+        verify_payload(&content, extracted)?;
+    }
+    Ok(())
+}
+
+/// Remove an entry from the container of the image referenced by `input_image_path` and save
+/// the result at `output_image_path`
+///
+/// # Arguments
+///
+/// * `options` - Structure with the name of the entry and the encoding of the image
+///
+/// # Errors
+///
+/// Returns an error if the image does not carry a container or if no entry has the name
+pub fn remove_entry(options: SteganographyInjectOption) -> Result<(), SteganographyError> {
+    let name = entry_name(&options.entry)?;
+    let img = image::open(&options.input_image_path)?;
+    let mut container = extract_container_from_image(
+        &img,
+        &SteganographyExtractOption {
+            password: options.password.clone(),
+            ..Default::default()
+        },
+    )?;
+    container.remove(name)?;
+    let new_img = inject_container_into_image(&img, &container, &options)?;
+    save_image(&new_img, &options.output_image_path)
+}
+
+/// List the names of the entries of the container of the image referenced by
+/// `input_image_path`
+///
+/// # Errors
+///
+/// Returns an error if the image does not carry a container
+pub fn list_entries(
+    options: SteganographyExtractOption,
+) -> Result<Vec<String>, SteganographyError> {
+    let img = image::open(&options.input_image_path)?;
+    let container = extract_container_from_image(&img, &options)?;
+    Ok(container.names().into_iter().map(String::from).collect())
+}
+
+/// Get the content of an entry of the container of the image referenced by `input_image_path`
+///
+/// # Arguments
+///
+/// * `options` - Structure with the image, its password (optional), the name of the entry and
+///   its password (optional)
+///
+/// # Errors
+///
+/// Returns an error if the image does not carry a container, if no entry has the name or if
+/// the password of the entry cannot decrypt it
+pub fn extract_entry(options: SteganographyExtractOption) -> Result<Vec<u8>, SteganographyError> {
+    let name = entry_name(&options.entry)?;
+    let img = image::open(&options.input_image_path)?;
+    extract_container_from_image(&img, &options)?.get(name, &options.entry_password)
+}
+
+/// Get the content of an entry of the container of the image referenced by `input_image_path`
+/// as a UTF-8 message
+///
+/// # Errors
+///
+/// Returns the errors of `extract_entry` and `CorruptPayload` if the entry is not valid UTF-8
+pub fn get_entry_message(
+    options: SteganographyExtractOption,
+) -> Result<String, SteganographyError> {
+    bytes_to_message(extract_entry(options)?)
+}
+
+/// Write the content of an entry of the container of the image referenced by
+/// `input_image_path` into the file referenced by `output_file_path`
+///
+/// # Errors
+///
+/// Returns the errors of `extract_entry` and an error if the file cannot be written
+pub fn extract_entry_to_file(
+    options: SteganographyExtractOption,
+) -> Result<(), SteganographyError> {
+    let output_file_path = options.output_file_path.clone().ok_or_else(|| {
+        SteganographyError::InvalidOption("The file to create is required".to_string())
+    })?;
+    fs::write(output_file_path, extract_entry(options)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test_container {
+    use super::*;
+
+    #[test]
+    fn test_container_round_trip() {
+        let mut container = Container::new();
+        container.add("note", b"Meet at noon", &None).unwrap();
+        container
+            .add("key", b"0123456789", &Some("secret".to_string()))
+            .unwrap();
+        let bytes = container.to_bytes().unwrap();
+        let result = Container::read_from(&mut &bytes[..]).unwrap();
+        assert_eq!(result, container);
+        assert_eq!(result.names(), vec!["note", "key"]);
+        assert_eq!(result.get("note", &None).unwrap(), b"Meet at noon");
+        assert_eq!(
+            result.get("key", &Some("secret".to_string())).unwrap(),
+            b"0123456789"
+        );
+    }
+
+    #[test]
+    fn test_container_entry_password() {
+        let mut container = Container::new();
+        container
+            .add("key", b"0123456789", &Some("secret".to_string()))
+            .unwrap();
+        assert!(matches!(
+            container.get("key", &None),
+            Err(SteganographyError::BadPassword)
+        ));
+        assert!(matches!(
+            container.get("key", &Some("wrong".to_string())),
+            Err(SteganographyError::BadPassword)
+        ));
+    }
+
+    #[test]
+    fn test_container_duplicate_and_missing() {
+        let mut container = Container::new();
+        container.add("note", b"1", &None).unwrap();
+        assert!(matches!(
+            container.add("note", b"2", &None),
+            Err(SteganographyError::InvalidOption(_))
+        ));
+        assert!(matches!(
+            container.get("other", &None),
+            Err(SteganographyError::InvalidOption(_))
+        ));
+        container.remove("note").unwrap();
+        assert!(container.entries().is_empty());
+        assert!(matches!(
+            container.remove("note"),
+            Err(SteganographyError::InvalidOption(_))
+        ));
+    }
+
+    #[test]
+    fn test_container_truncated() {
+        let mut container = Container::new();
+        container.add("note", b"Meet at noon", &None).unwrap();
+        let bytes = container.to_bytes().unwrap();
+        let result = Container::read_from(&mut &bytes[..bytes.len() - 1]);
+        assert!(matches!(result, Err(SteganographyError::CorruptPayload(_))));
+    }
+
+    #[test]
+    fn test_add_list_extract_remove_entries() {
+        let inject = |entry: &str, message: &str, input: &str, password: Option<String>| {
+            add_entry(SteganographyInjectOption {
+                message: message.to_string(),
+                input_image_path: input.to_string(),
+                output_image_path: "testAssets/delete_me_container.png".to_string(),
+                entry: Some(entry.to_string()),
+                entry_password: password,
+                ..Default::default()
+            })
+            .unwrap();
+        };
+        inject("note", "Meet at noon", "testAssets/prestine.png", None);
+        inject(
+            "key",
+            "0123456789",
+            "testAssets/delete_me_container.png",
+            Some("secret".to_string()),
+        );
+        let options = SteganographyExtractOption {
+            input_image_path: "testAssets/delete_me_container.png".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(list_entries(options.clone()).unwrap(), vec!["note", "key"]);
+        let key = extract_entry(SteganographyExtractOption {
+            entry: Some("key".to_string()),
+            entry_password: Some("secret".to_string()),
+            ..options.clone()
+        })
+        .unwrap();
+        assert_eq!(key, b"0123456789");
+        remove_entry(SteganographyInjectOption {
+            input_image_path: "testAssets/delete_me_container.png".to_string(),
+            output_image_path: "testAssets/delete_me_container.png".to_string(),
+            entry: Some("note".to_string()),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(list_entries(options).unwrap(), vec!["key"]);
+    }
+}
@@ -1,4 +1,175 @@
-use magic_crypt::{new_magic_crypt, MagicCryptError, MagicCryptTrait};
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::{Aead, KeyInit, OsRng, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
+use argon2::{Algorithm, Argon2, Params, Version};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use magic_crypt::{new_magic_crypt, MagicCryptTrait};
+use std::io::Read;
+
+use super::error::SteganographyError;
+
+/// Number of random bytes mixed with the password to derive the key
+pub const SALT_LENGTH: usize = 16;
+
+/// Number of bytes of the AES-GCM nonce
+pub const NONCE_LENGTH: usize = 12;
+
+/// Number of bytes the authentication tag adds to the encrypted payload
+pub const TAG_LENGTH: usize = 16;
+
+/// Number of bytes `Cipher::to_bytes` produces for the Argon2id and AES-256-GCM cipher:
+/// identifier (1), memory cost (4), time cost (4), parallelism (1), salt and nonce
+pub const CIPHER_LENGTH: usize = 10 + SALT_LENGTH + NONCE_LENGTH;
+
+/// Memory used by Argon2id in KiB (19 MiB, OWASP recommendation)
+const ARGON2_MEMORY_COST: u32 = 19 * 1024;
+
+/// Number of passes of Argon2id over the memory
+const ARGON2_TIME_COST: u32 = 2;
+
+/// Number of lanes of Argon2id
+const ARGON2_PARALLELISM: u8 = 1;
+
+/// Largest Argon2id memory in KiB accepted from an image (256 MiB). The parameters are read
+/// before the authentication tag can be checked, a crafted image must not exhaust the memory
+const MAX_ARGON2_MEMORY_COST: u32 = 256 * 1024;
+
+/// Largest number of Argon2id passes accepted from an image
+const MAX_ARGON2_TIME_COST: u32 = 8;
+
+/// Largest number of Argon2id lanes accepted from an image
+const MAX_ARGON2_PARALLELISM: u8 = 16;
+
+/// Salt to derive the seed of the scattered order. It is constant because the seed must be
+/// found back from the password alone, before reading the header
+const SCATTER_SALT: &[u8] = b"steganographyrs scatter";
+
+const CIPHER_ID_MAGIC_CRYPT: u8 = 1;
+const CIPHER_ID_ARGON2ID_AES256GCM: u8 = 2;
+
+/// Parameters to derive the key from the password. They are stored next to the encrypted
+/// payload because they are required to decrypt it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KeyDerivation {
+    /// Argon2id memory in KiB
+    pub memory_cost: u32,
+    /// Argon2id number of passes
+    pub time_cost: u32,
+    /// Argon2id number of lanes
+    pub parallelism: u8,
+    /// Random bytes mixed with the password
+    pub salt: [u8; SALT_LENGTH],
+    /// Random AES-GCM nonce
+    pub nonce: [u8; NONCE_LENGTH],
+}
+
+/// Algorithm used to encrypt a payload
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Cipher {
+    /// AES-256-CBC from magic-crypt with the password used directly as key. Not authenticated,
+    /// only kept to read images made by previous versions of the library
+    MagicCrypt,
+    /// Key derived from the password with Argon2id then AES-256-GCM. A wrong password or an
+    /// altered image fails the authentication tag
+    Argon2idAes256Gcm(KeyDerivation),
+}
+
+impl Cipher {
+    /// Create the cipher used to encrypt a new payload with a random salt and nonce
+    pub fn generate() -> Self {
+        let mut salt = [0u8; SALT_LENGTH];
+        let mut nonce = [0u8; NONCE_LENGTH];
+        OsRng.fill_bytes(&mut salt);
+        OsRng.fill_bytes(&mut nonce);
+        Cipher::Argon2idAes256Gcm(KeyDerivation {
+            memory_cost: ARGON2_MEMORY_COST,
+            time_cost: ARGON2_TIME_COST,
+            parallelism: ARGON2_PARALLELISM,
+            salt,
+            nonce,
+        })
+    }
+
+    /// Serialize the cipher and its parameters, numbers are big endian
+    pub fn to_bytes(self) -> Vec<u8> {
+        match self {
+            Cipher::MagicCrypt => vec![CIPHER_ID_MAGIC_CRYPT],
+            Cipher::Argon2idAes256Gcm(k) => {
+                let mut bytes = Vec::with_capacity(CIPHER_LENGTH);
+                bytes.push(CIPHER_ID_ARGON2ID_AES256GCM);
+                bytes.extend(k.memory_cost.to_be_bytes());
+                bytes.extend(k.time_cost.to_be_bytes());
+                bytes.push(k.parallelism);
+                bytes.extend(k.salt);
+                bytes.extend(k.nonce);
+                bytes
+            }
+        }
+    }
+
+    /// Read a cipher written by `to_bytes`
+    ///
+    /// # Arguments
+    /// bytes - Bytes starting with the cipher identifier. Extra bytes are ignored
+    ///
+    /// # Errors
+    /// `CorruptPayload` if the bytes are too short or the identifier is unknown
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SteganographyError> {
+        Cipher::read_from(&mut &bytes[..])
+    }
+
+    /// Read a cipher written by `to_bytes`, consuming only its bytes from the reader
+    ///
+    /// # Errors
+    /// `CorruptPayload` if the reader ends too early, the identifier is unknown or the Argon2id
+    /// parameters are above the limits (E.g. `MAX_ARGON2_MEMORY_COST`)
+    pub fn read_from<R: Read>(reader: &mut R) -> Result<Self, SteganographyError> {
+        let truncated = |_e| {
+            SteganographyError::CorruptPayload("The encryption header is truncated".to_string())
+        };
+        let mut id = [0u8; 1];
+        reader.read_exact(&mut id).map_err(truncated)?;
+        match id[0] {
+            CIPHER_ID_MAGIC_CRYPT => Ok(Cipher::MagicCrypt),
+            CIPHER_ID_ARGON2ID_AES256GCM => {
+                let mut bytes = [0u8; CIPHER_LENGTH - 1];
+                reader.read_exact(&mut bytes).map_err(truncated)?;
+                let mut salt = [0u8; SALT_LENGTH];
+                let mut nonce = [0u8; NONCE_LENGTH];
+                salt.copy_from_slice(&bytes[9..9 + SALT_LENGTH]);
+                nonce.copy_from_slice(&bytes[9 + SALT_LENGTH..]);
+                let key_derivation = KeyDerivation {
+                    memory_cost: u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+                    time_cost: u32::from_be_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]),
+                    parallelism: bytes[8],
+                    salt,
+                    nonce,
+                };
+                if key_derivation.memory_cost > MAX_ARGON2_MEMORY_COST
+                    || !(1..=MAX_ARGON2_TIME_COST).contains(&key_derivation.time_cost)
+                    || !(1..=MAX_ARGON2_PARALLELISM).contains(&key_derivation.parallelism)
+                {
+                    return Err(SteganographyError::CorruptPayload(
+                        "The key derivation parameters of the message are out of range".to_string(),
+                    ));
+                }
+                Ok(Cipher::Argon2idAes256Gcm(key_derivation))
+            }
+            _ => Err(SteganographyError::CorruptPayload(
+                "Unknown encryption of the message".to_string(),
+            )),
+        }
+    }
+
+    /// Number of bytes `to_bytes` produces
+    pub fn length(self) -> usize {
+        match self {
+            Cipher::MagicCrypt => 1,
+            Cipher::Argon2idAes256Gcm(_) => CIPHER_LENGTH,
+        }
+    }
+}
 
 /// Encrypt a message using a password if provided. When not provided, the function
 /// returns the message without alteration
@@ -8,15 +179,26 @@ use magic_crypt::{new_magic_crypt, MagicCryptError, MagicCryptTrait};
 /// password - The secret used to modify the message before insertion into the image
 ///
 /// # Returns
-/// The message that is encrypted if a password is provided
-pub fn encrypt_if_needed(message: String, password: Option<String>) -> String {
+/// The message that is encrypted if a password is provided. The cipher parameters, the
+/// encrypted message and its authentication tag are wrapped in base64
+pub fn encrypt_if_needed(
+    message: String,
+    password: Option<String>,
+) -> Result<String, SteganographyError> {
     match password {
-        Some(p) => encrypt(message, p),
-        None => message,
+        Some(p) => {
+            let cipher = Cipher::generate();
+            let mut bytes = cipher.to_bytes();
+            let encrypted = encrypt_bytes(message.as_bytes(), &p, cipher, &bytes)?;
+            bytes.extend(encrypted);
+            Ok(STANDARD.encode(bytes))
+        }
+        None => Ok(message),
     }
 }
 
-/// Decrypt a message using the provided password.
+/// Decrypt a message using the provided password. Messages encrypted with the magic-crypt
+/// AES-256-CBC of the previous versions are still decrypted
 ///
 /// # Arguments
 /// message - Message that may or not be encrypted
@@ -24,31 +206,174 @@ pub fn encrypt_if_needed(message: String, password: Option<String>) -> String {
 /// is returned without alteration
 ///
 /// #Returns
-/// The message decrypted, readable by a human
+/// The message decrypted, readable by a human or `BadPassword` if the password cannot decrypt it
 pub fn decrypt_if_needed(
     message: String,
     password: Option<String>,
-) -> Result<String, MagicCryptError> {
+) -> Result<String, SteganographyError> {
     match password {
-        Some(p) => decrypt(message, p),
+        Some(p) => {
+            let bytes = STANDARD
+                .decode(&message)
+                .map_err(|_e| SteganographyError::BadPassword)?;
+            match Cipher::from_bytes(&bytes) {
+                Ok(cipher @ Cipher::Argon2idAes256Gcm(_)) => {
+                    // A magic-crypt message can start like the new format by chance, it is
+                    // tried when the authentication fails
+                    let (parameters, encrypted) = bytes.split_at(cipher.length());
+                    decrypt_bytes(encrypted, &p, cipher, parameters)
+                        .ok()
+                        .and_then(|decrypted| String::from_utf8(decrypted).ok())
+                        .map_or_else(|| decrypt(message, p), Ok)
+                }
+                _ => decrypt(message, p),
+            }
+        }
         None => Ok(message),
     }
 }
 
-/// Encrypt the message with the password using AES256. The result is wrapped in base64.
+/// Encrypt bytes with the password
 ///
 /// # Arguments
-/// message - The message to encrypt
-/// password - Secret to encrypt the message
+/// payload - The bytes to encrypt
+/// password - Secret to derive the key from
+/// cipher - The algorithm and its parameters (salt, nonce), see `Cipher::generate`
+/// associated_data - Bytes that are not encrypted but authenticated with the payload (E.g. the header)
 ///
 /// # Returns
-/// Encrypted message
-fn encrypt(message: String, password: String) -> String {
-    let mc = new_magic_crypt!(password, 256);
-    mc.encrypt_str_to_base64(message)
+/// The encrypted bytes followed by the authentication tag
+pub fn encrypt_bytes(
+    payload: &[u8],
+    password: &str,
+    cipher: Cipher,
+    associated_data: &[u8],
+) -> Result<Vec<u8>, SteganographyError> {
+    match cipher {
+        Cipher::MagicCrypt => Ok(new_magic_crypt!(password, 256).encrypt_bytes_to_bytes(payload)),
+        Cipher::Argon2idAes256Gcm(k) => {
+            let aes = Aes256Gcm::new_from_slice(&derive_key(password, &k)?)
+                .map_err(|e| SteganographyError::Encryption(e.to_string()))?;
+            aes.encrypt(
+                Nonce::from_slice(&k.nonce),
+                Payload {
+                    msg: payload,
+                    aad: associated_data,
+                },
+            )
+            .map_err(|e| SteganographyError::Encryption(e.to_string()))
+        }
+    }
+}
+
+/// Decrypt bytes with the password
+///
+/// # Arguments
+/// payload - The encrypted bytes followed by the authentication tag
+/// password - Secret to derive the key from
+/// cipher - The algorithm and its parameters read from the image
+/// associated_data - The same bytes given to `encrypt_bytes`
+///
+/// # Errors
+/// `BadPassword` when the password is wrong or when the payload or associated data were altered
+pub fn decrypt_bytes(
+    payload: &[u8],
+    password: &str,
+    cipher: Cipher,
+    associated_data: &[u8],
+) -> Result<Vec<u8>, SteganographyError> {
+    match cipher {
+        Cipher::MagicCrypt => new_magic_crypt!(password, 256)
+            .decrypt_bytes_to_bytes(payload)
+            .map_err(|_e| SteganographyError::BadPassword),
+        Cipher::Argon2idAes256Gcm(k) => {
+            let key = derive_key(password, &k)
+                .map_err(|e| SteganographyError::CorruptPayload(e.to_string()))?;
+            let aes = Aes256Gcm::new_from_slice(&key)
+                .map_err(|e| SteganographyError::CorruptPayload(e.to_string()))?;
+            aes.decrypt(
+                Nonce::from_slice(&k.nonce),
+                Payload {
+                    msg: payload,
+                    aad: associated_data,
+                },
+            )
+            .map_err(|_e| SteganographyError::BadPassword)
+        }
+    }
+}
+
+/// Compute the length of the bytes once they go through `encrypt_bytes` with a new cipher
+///
+/// # Arguments
+/// length - Number of bytes before encryption
+/// password - The secret that will be used to encrypt the bytes, if any
+///
+/// # Returns
+/// The number of bytes once encrypted
+pub fn encrypted_length(length: usize, password: &Option<String>) -> usize {
+    match password {
+        Some(_) => length + TAG_LENGTH,
+        None => length,
+    }
+}
+
+/// Derive the seed of the pseudo-random order used to scatter the bits across the image
+///
+/// # Arguments
+/// password - The secret provided by the user
+///
+/// # Returns
+/// The same 256 bits seed for the same password
+pub fn derive_seed(password: &str) -> Result<[u8; 32], SteganographyError> {
+    argon2id(
+        password,
+        SCATTER_SALT,
+        ARGON2_MEMORY_COST,
+        ARGON2_TIME_COST,
+        ARGON2_PARALLELISM,
+    )
+}
+
+/// Derive a 256 bits key from the password with Argon2id
+///
+/// # Arguments
+/// password - The secret provided by the user
+/// key_derivation - Salt and cost parameters
+///
+/// # Errors
+/// `Encryption` if the parameters are not accepted by Argon2
+fn derive_key(
+    password: &str,
+    key_derivation: &KeyDerivation,
+) -> Result<[u8; 32], SteganographyError> {
+    argon2id(
+        password,
+        &key_derivation.salt,
+        key_derivation.memory_cost,
+        key_derivation.time_cost,
+        key_derivation.parallelism,
+    )
+}
+
+/// Hash the password with Argon2id into 256 bits
+fn argon2id(
+    password: &str,
+    salt: &[u8],
+    memory_cost: u32,
+    time_cost: u32,
+    parallelism: u8,
+) -> Result<[u8; 32], SteganographyError> {
+    let params = Params::new(memory_cost, time_cost, parallelism as u32, Some(32))
+        .map_err(|e| SteganographyError::Encryption(e.to_string()))?;
+    let mut key = [0u8; 32];
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(password.as_bytes(), salt, &mut key)
+        .map_err(|e| SteganographyError::Encryption(e.to_string()))?;
+    Ok(key)
 }
 
-/// Decrypt the message using the password.
+/// Decrypt a base64 message encrypted by magic-crypt (previous versions of the library)
 ///
 /// # Arguments
 /// message - Encrypted message
@@ -56,7 +381,128 @@ fn encrypt(message: String, password: String) -> String {
 ///
 /// #Returns
 /// Decrypted message or failure result if something is wrong
-fn decrypt(message: String, password: String) -> Result<String, MagicCryptError> {
+fn decrypt(message: String, password: String) -> Result<String, SteganographyError> {
     let mc = new_magic_crypt!(password, 256);
     mc.decrypt_base64_to_string(message)
+        .map_err(|_e| SteganographyError::BadPassword)
+}
+
+#[cfg(test)]
+mod test_encryption {
+    use super::*;
+
+    #[test]
+    fn test_encrypted_length_without_password() {
+        assert_eq!(encrypted_length(12, &None), 12);
+    }
+
+    #[test]
+    fn test_encrypted_length_matches_encryption() {
+        let password = Some("Secret Password Here".to_string());
+        let cipher = Cipher::generate();
+        for length in [0, 1, 16, 100] {
+            let encrypted = encrypt_bytes(&vec![7u8; length], "pw", cipher, &[]).unwrap();
+            assert_eq!(encrypted_length(length, &password), encrypted.len());
+        }
+    }
+
+    #[test]
+    fn test_encrypt_bytes_round_trip() {
+        let payload = vec![0u8, 4, 255, 4, 10];
+        let cipher = Cipher::generate();
+        let encrypted = encrypt_bytes(&payload, "secret", cipher, b"header").unwrap();
+        assert_ne!(encrypted, payload);
+        assert_eq!(
+            decrypt_bytes(&encrypted, "secret", cipher, b"header").unwrap(),
+            payload
+        );
+    }
+
+    #[test]
+    fn test_decrypt_bytes_tampered() {
+        let cipher = Cipher::generate();
+        let mut encrypted = encrypt_bytes(b"payload", "secret", cipher, b"header").unwrap();
+        encrypted[0] ^= 1;
+        let result = decrypt_bytes(&encrypted, "secret", cipher, b"header");
+        assert!(matches!(result, Err(SteganographyError::BadPassword)));
+    }
+
+    #[test]
+    fn test_decrypt_bytes_tampered_associated_data() {
+        let cipher = Cipher::generate();
+        let encrypted = encrypt_bytes(b"payload", "secret", cipher, b"header").unwrap();
+        let result = decrypt_bytes(&encrypted, "secret", cipher, b"HEADER");
+        assert!(matches!(result, Err(SteganographyError::BadPassword)));
+    }
+
+    #[test]
+    fn test_cipher_round_trip() {
+        let cipher = Cipher::generate();
+        let bytes = cipher.to_bytes();
+        assert_eq!(bytes.len(), CIPHER_LENGTH);
+        assert_eq!(Cipher::from_bytes(&bytes).unwrap(), cipher);
+        assert_eq!(
+            Cipher::from_bytes(&Cipher::MagicCrypt.to_bytes()).unwrap(),
+            Cipher::MagicCrypt
+        );
+    }
+
+    #[test]
+    fn test_cipher_parameters_out_of_range() {
+        let Cipher::Argon2idAes256Gcm(key_derivation) = Cipher::generate() else {
+            panic!("Expected the Argon2id cipher");
+        };
+        for key_derivation in [
+            KeyDerivation {
+                memory_cost: u32::MAX,
+                ..key_derivation
+            },
+            KeyDerivation {
+                time_cost: u32::MAX,
+                ..key_derivation
+            },
+            KeyDerivation {
+                parallelism: 0,
+                ..key_derivation
+            },
+        ] {
+            let bytes = Cipher::Argon2idAes256Gcm(key_derivation).to_bytes();
+            assert!(matches!(
+                Cipher::from_bytes(&bytes),
+                Err(SteganographyError::CorruptPayload(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_derive_seed() {
+        assert_eq!(derive_seed("secret").unwrap(), derive_seed("secret").unwrap());
+        assert_ne!(derive_seed("secret").unwrap(), derive_seed("Secret").unwrap());
+    }
+
+    #[test]
+    fn test_cipher_generate_is_random() {
+        assert_ne!(Cipher::generate(), Cipher::generate());
+    }
+
+    #[test]
+    fn test_encrypt_decrypt_message() {
+        let password = Some("secret".to_string());
+        let encrypted = encrypt_if_needed("Bonjour".to_string(), password.clone()).unwrap();
+        assert_eq!(decrypt_if_needed(encrypted, password).unwrap(), "Bonjour");
+    }
+
+    #[test]
+    fn test_decrypt_magic_crypt_message() {
+        let encrypted = new_magic_crypt!("secret", 256).encrypt_str_to_base64("Bonjour");
+        let result = decrypt_if_needed(encrypted, Some("secret".to_string()));
+        assert_eq!(result.unwrap(), "Bonjour");
+    }
+
+    #[test]
+    fn test_decrypt_with_wrong_password() {
+        let encrypted = encrypt_if_needed("Test".to_string(), Some("right".to_string())).unwrap();
+        let result = decrypt_if_needed(encrypted, Some("wrong".to_string()));
+        assert!(matches!(result, Err(SteganographyError::BadPassword)));
+    }
 }
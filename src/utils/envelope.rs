@@ -0,0 +1,261 @@
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+
+use sha2::{Digest, Sha256};
+
+use super::error::SteganographyError;
+
+/// Number of bytes of the SHA-256 hash of the content
+pub const HASH_LENGTH: usize = 32;
+
+/// A file hidden into an image with what is needed to recreate it: its name, its size, its
+/// last modification and a hash to verify its content once extracted
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FileEnvelope {
+    /// Name of the file, without any directory
+    pub name: String,
+    /// Last modification in seconds since the Unix epoch, 0 when unknown
+    pub modified: u64,
+    /// SHA-256 hash of the content
+    pub hash: [u8; HASH_LENGTH],
+    /// Bytes of the file
+    pub content: Vec<u8>,
+}
+
+impl FileEnvelope {
+    /// # Arguments
+    ///
+    /// * `name` - Name of the file, without any directory
+    /// * `content` - Bytes of the file
+    /// * `modified` - Last modification in seconds since the Unix epoch, 0 when unknown
+    pub fn new(name: String, content: Vec<u8>, modified: u64) -> Self {
+        FileEnvelope {
+            name,
+            modified,
+            hash: Sha256::digest(&content).into(),
+            content,
+        }
+    }
+
+    /// Read a file with its name and its last modification
+    ///
+    /// # Errors
+    /// Returns an error if the file cannot be read or if its name is not valid UTF-8
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self, SteganographyError> {
+        let path = path.as_ref();
+        let (name, modified) = file_metadata(path)?;
+        Ok(FileEnvelope::new(name, fs::read(path)?, modified))
+    }
+
+    /// Number of bytes of the content
+    pub fn size(&self) -> u64 {
+        self.content.len() as u64
+    }
+
+    /// Serialize the envelope into the payload injected into the image: the length of the
+    /// name (2), the name, the size (8), the last modification (8), the hash (32) and the
+    /// content. The numbers are stored big endian
+    ///
+    /// # Errors
+    /// Returns `InvalidOption` if the name is longer than 65535 bytes
+    pub fn to_bytes(&self) -> Result<Vec<u8>, SteganographyError> {
+        envelope_bytes(&self.name, &self.content, self.modified, &self.hash)
+    }
+
+    /// Read the envelope from the payload extracted from an image
+    ///
+    /// # Errors
+    /// Returns `CorruptPayload` if the envelope is truncated, if the name is not valid UTF-8
+    /// or if the content does not match its hash
+    pub fn read_from<R: Read>(reader: &mut R) -> Result<Self, SteganographyError> {
+        let mut name_length = [0u8; 2];
+        read_exact(reader, &mut name_length)?;
+        let mut name = vec![0u8; u16::from_be_bytes(name_length) as usize];
+        read_exact(reader, &mut name)?;
+        let name = String::from_utf8(name).map_err(|_| {
+            SteganographyError::CorruptPayload(
+                "The name of the file is not valid UTF-8".to_string(),
+            )
+        })?;
+        let mut size = [0u8; 8];
+        read_exact(reader, &mut size)?;
+        let mut modified = [0u8; 8];
+        read_exact(reader, &mut modified)?;
+        let mut hash = [0u8; HASH_LENGTH];
+        read_exact(reader, &mut hash)?;
+        let size = u64::from_be_bytes(size);
+        let mut content = Vec::new();
+        reader.take(size).read_to_end(&mut content)?;
+        if content.len() as u64 != size {
+            return Err(truncated());
+        }
+        let envelope = FileEnvelope::new(name, content, u64::from_be_bytes(modified));
+        if envelope.hash != hash {
+            return Err(SteganographyError::CorruptPayload(
+                "The content of the file does not match its hash".to_string(),
+            ));
+        }
+        Ok(envelope)
+    }
+
+    /// Recreate the file with its name and its last modification into a directory, created
+    /// when missing. Only the last component of the name is used, the file cannot be written
+    /// outside of the directory
+    ///
+    /// # Returns
+    /// The path of the file
+    ///
+    /// # Errors
+    /// Returns an error if the file cannot be written or if the name is not a file name
+    /// (E.g. "..")
+    pub fn write_to_dir<P: AsRef<Path>>(&self, dir: P) -> Result<PathBuf, SteganographyError> {
+        let name = Path::new(&self.name).file_name().ok_or_else(|| {
+            SteganographyError::CorruptPayload(format!(
+                "\"{}\" is not a valid file name",
+                self.name
+            ))
+        })?;
+        fs::create_dir_all(&dir)?;
+        let path = dir.as_ref().join(name);
+        fs::write(&path, &self.content)?;
+        // The modification comes from the payload, a time the system cannot represent is skipped
+        let modified = UNIX_EPOCH.checked_add(Duration::from_secs(self.modified));
+        if let Some(modified) = modified.filter(|_| self.modified != 0) {
+            File::options()
+                .write(true)
+                .open(&path)?
+                .set_modified(modified)?;
+        }
+        Ok(path)
+    }
+}
+
+/// The name of a file, without any directory, and its last modification in seconds since the
+/// Unix epoch, 0 when unknown
+///
+/// # Errors
+/// Returns an error if the file cannot be read or if its name is not valid UTF-8
+pub(crate) fn file_metadata(path: &Path) -> Result<(String, u64), SteganographyError> {
+    let name = path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .ok_or_else(|| {
+            SteganographyError::InvalidOption(format!(
+                "The file \"{}\" has no valid name",
+                path.display()
+            ))
+        })?
+        .to_string();
+    let modified = fs::metadata(path)?
+        .modified()
+        .ok()
+        .and_then(|m| m.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |d| d.as_secs());
+    Ok((name, modified))
+}
+
+/// Serialize a file into the payload of an envelope, see `FileEnvelope::to_bytes`. The content
+/// is borrowed so that a file streamed into a `PayloadWriter` is not copied
+///
+/// # Errors
+/// Returns `InvalidOption` if the name is longer than 65535 bytes
+pub(crate) fn envelope_bytes(
+    name: &str,
+    content: &[u8],
+    modified: u64,
+    hash: &[u8; HASH_LENGTH],
+) -> Result<Vec<u8>, SteganographyError> {
+    let name_length = u16::try_from(name.len()).map_err(|_| {
+        SteganographyError::InvalidOption("The name of the file is too long".to_string())
+    })?;
+    let mut bytes = Vec::with_capacity(envelope_overhead(name) + content.len());
+    bytes.extend(name_length.to_be_bytes());
+    bytes.extend(name.as_bytes());
+    bytes.extend((content.len() as u64).to_be_bytes());
+    bytes.extend(modified.to_be_bytes());
+    bytes.extend(hash);
+    bytes.extend(content);
+    Ok(bytes)
+}
+
+/// Number of bytes an envelope adds to the content of a file
+pub(crate) fn envelope_overhead(name: &str) -> usize {
+    2 + name.len() + 16 + HASH_LENGTH
+}
+
+/// Fill the buffer, an envelope that ends early is reported as a corrupt payload
+fn read_exact<R: Read>(reader: &mut R, buffer: &mut [u8]) -> Result<(), SteganographyError> {
+    reader.read_exact(buffer).map_err(|e| match e.kind() {
+        io::ErrorKind::UnexpectedEof => truncated(),
+        _ => e.into(),
+    })
+}
+
+fn truncated() -> SteganographyError {
+    SteganographyError::CorruptPayload("The file is truncated".to_string())
+}
+
+#[cfg(test)]
+mod test_envelope {
+    use super::*;
+
+    #[test]
+    fn test_envelope_round_trip() {
+        let envelope = FileEnvelope::new("report.pdf".to_string(), vec![1, 2, 3], 1_700_000_000);
+        let bytes = envelope.to_bytes().unwrap();
+        assert_eq!(bytes.len(), 2 + 10 + 16 + HASH_LENGTH + 3);
+        let result = FileEnvelope::read_from(&mut &bytes[..]).unwrap();
+        assert_eq!(result, envelope);
+    }
+
+    #[test]
+    fn test_envelope_altered_content() {
+        let envelope = FileEnvelope::new("a.txt".to_string(), vec![1, 2, 3], 0);
+        let mut bytes = envelope.to_bytes().unwrap();
+        *bytes.last_mut().unwrap() = 4;
+        let result = FileEnvelope::read_from(&mut &bytes[..]);
+        assert!(matches!(result, Err(SteganographyError::CorruptPayload(_))));
+    }
+
+    #[test]
+    fn test_envelope_truncated() {
+        let envelope = FileEnvelope::new("a.txt".to_string(), vec![1, 2, 3], 0);
+        let bytes = envelope.to_bytes().unwrap();
+        let result = FileEnvelope::read_from(&mut &bytes[..bytes.len() - 1]);
+        assert!(matches!(result, Err(SteganographyError::CorruptPayload(_))));
+    }
+
+    #[test]
+    fn test_envelope_from_path() {
+        let envelope = FileEnvelope::from_path("testAssets/message1.txt").unwrap();
+        assert_eq!(envelope.name, "message1.txt");
+        assert_eq!(envelope.size(), 52);
+        assert_ne!(envelope.modified, 0);
+    }
+
+    #[test]
+    fn test_envelope_write_to_dir() {
+        let envelope = FileEnvelope::new("../escape.txt".to_string(), vec![1, 2, 3], 1_700_000_000);
+        let path = envelope.write_to_dir("target/delete_me_envelope").unwrap();
+        assert_eq!(path, Path::new("target/delete_me_envelope/escape.txt"));
+        assert_eq!(fs::read(&path).unwrap(), vec![1, 2, 3]);
+        let modified = fs::metadata(&path).unwrap().modified().unwrap();
+        assert_eq!(modified, UNIX_EPOCH + Duration::from_secs(1_700_000_000));
+    }
+
+    #[test]
+    fn test_envelope_write_modified_overflow() {
+        let envelope = FileEnvelope::new("overflow.txt".to_string(), vec![1], u64::MAX);
+        let path = envelope.write_to_dir("target/delete_me_envelope").unwrap();
+        assert_eq!(fs::read(&path).unwrap(), vec![1]);
+    }
+
+    #[test]
+    fn test_envelope_write_invalid_name() {
+        let envelope = FileEnvelope::new("..".to_string(), vec![1], 0);
+        let result = envelope.write_to_dir("target/delete_me_envelope");
+        assert!(matches!(result, Err(SteganographyError::CorruptPayload(_))));
+    }
+}
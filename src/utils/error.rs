@@ -0,0 +1,195 @@
+use std::fmt;
+
+/// All the failures that can happen while injecting or extracting a message
+///
+/// The library never panics on a bad input: every public function returns one of these
+/// variants so the caller can decide what to do with the failure.
+#[derive(Debug)]
+pub enum SteganographyError {
+    /// Reading or writing a file failed
+    Io(std::io::Error),
+    /// The image could not be decoded or encoded
+    Image(image::ImageError),
+    /// The message needs more bytes than the image can hold
+    Capacity { required: usize, available: usize },
+    /// The password could not decrypt the message: the password is wrong or the image was altered
+    BadPassword,
+    /// The message could not be encrypted
+    Encryption(String),
+    /// The bytes read from the image do not form a valid message
+    CorruptPayload(String),
+    /// The image format cannot be used to hide a message
+    UnsupportedFormat(String),
+    /// The options cannot be used together (E.g. a scattered order without a password)
+    InvalidOption(String),
+    /// Some images of a payload split across several images are missing. The sequence numbers
+    /// start at 1
+    MissingShards { missing: Vec<u16>, total: u16 },
+    /// Fewer images than the threshold of a payload shared across several images
+    NotEnoughShares { required: usize, available: usize },
+    /// The saved image does not give back the injected payload, see the `verify` option of the
+    /// injection
+    Verification(String),
+}
+
+impl fmt::Display for SteganographyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "I/O error: {}", e),
+            Self::Image(e) => write!(f, "Image error: {}", e),
+            Self::Capacity {
+                required,
+                available,
+            } => write!(
+                f,
+                "The message requires {} bytes but the image can only hold {} bytes",
+                required, available
+            ),
+            Self::BadPassword => write!(f, "The password cannot decrypt the message"),
+            Self::Encryption(reason) => write!(f, "Encryption error: {}", reason),
+            Self::CorruptPayload(reason) => write!(f, "Corrupt message: {}", reason),
+            Self::UnsupportedFormat(format) => write!(f, "Unsupported format: {}", format),
+            Self::InvalidOption(reason) => write!(f, "Invalid option: {}", reason),
+            Self::MissingShards { missing, total } => write!(
+                f,
+                "The message is split across {} images, the images number {} are missing",
+                total,
+                missing
+                    .iter()
+                    .map(|m| m.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Self::NotEnoughShares {
+                required,
+                available,
+            } => write!(
+                f,
+                "The message requires {} images to be recovered, only {} were given",
+                required, available
+            ),
+            Self::Verification(reason) => {
+                write!(
+                    f,
+                    "The saved image does not give back the message: {}",
+                    reason
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for SteganographyError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            Self::Image(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for SteganographyError {
+    fn from(error: std::io::Error) -> Self {
+        // A `Read` or `Write` of the library carries its own failure inside the I/O error
+        if error
+            .get_ref()
+            .is_some_and(|inner| inner.is::<SteganographyError>())
+        {
+            return *error
+                .into_inner()
+                .and_then(|inner| inner.downcast::<SteganographyError>().ok())
+                .expect("The inner error was checked to be a SteganographyError");
+        }
+        Self::Io(error)
+    }
+}
+
+impl From<SteganographyError> for std::io::Error {
+    fn from(error: SteganographyError) -> Self {
+        match error {
+            SteganographyError::Io(e) => e,
+            e => std::io::Error::other(e),
+        }
+    }
+}
+
+impl From<image::ImageError> for SteganographyError {
+    fn from(error: image::ImageError) -> Self {
+        match error {
+            image::ImageError::IoError(e) => Self::Io(e),
+            image::ImageError::Unsupported(e) => Self::UnsupportedFormat(e.to_string()),
+            e => Self::Image(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_error {
+    use super::*;
+
+    #[test]
+    fn test_from_io_error() {
+        let error: SteganographyError =
+            std::io::Error::new(std::io::ErrorKind::NotFound, "missing").into();
+        assert!(matches!(error, SteganographyError::Io(_)));
+    }
+
+    #[test]
+    fn test_from_image_unsupported_error() {
+        let error: SteganographyError = image::open("testAssets/message1.txt").unwrap_err().into();
+        assert!(matches!(error, SteganographyError::UnsupportedFormat(_)));
+    }
+
+    #[test]
+    fn test_io_error_round_trip() {
+        let error: std::io::Error = SteganographyError::BadPassword.into();
+        let error: SteganographyError = error.into();
+        assert!(matches!(error, SteganographyError::BadPassword));
+    }
+
+    #[test]
+    fn test_display_capacity() {
+        let error = SteganographyError::Capacity {
+            required: 10,
+            available: 5,
+        };
+        assert_eq!(
+            error.to_string(),
+            "The message requires 10 bytes but the image can only hold 5 bytes"
+        );
+    }
+
+    #[test]
+    fn test_display_missing_shards() {
+        let error = SteganographyError::MissingShards {
+            missing: vec![2, 4],
+            total: 5,
+        };
+        assert_eq!(
+            error.to_string(),
+            "The message is split across 5 images, the images number 2, 4 are missing"
+        );
+    }
+
+    #[test]
+    fn test_display_not_enough_shares() {
+        let error = SteganographyError::NotEnoughShares {
+            required: 3,
+            available: 2,
+        };
+        assert_eq!(
+            error.to_string(),
+            "The message requires 3 images to be recovered, only 2 were given"
+        );
+    }
+
+    #[test]
+    fn test_display_verification() {
+        let error = SteganographyError::Verification("the hashes differ".to_string());
+        assert_eq!(
+            error.to_string(),
+            "The saved image does not give back the message: the hashes differ"
+        );
+    }
+}
@@ -1,200 +1,3011 @@
-use magic_crypt::MagicCryptError;
+use std::fs::File;
+use std::io::{Cursor, Read};
+use std::path::Path;
 
-use super::binary::{binary_string_to_char, char_to_binary_string, pack_bit, unpack_bit};
-use super::encryption::{decrypt_if_needed, encrypt_if_needed};
-use super::options::{SteganographyExtractOption, SteganographyInjectOption};
+use image::{DynamicImage, ImageBuffer, ImageFormat, Pixel};
+use sha2::{Digest, Sha256};
+
+use super::binary::{binary_string_to_char, unpack_bit};
+use super::bitstream::{
+    texture_order, ChannelOrder, ChannelReader, ChannelWriter, Sample, SampleBuffer,
+    MAX_BITS_PER_CHANNEL, NUMBER_CHANNEL_PER_PIXEL,
+};
+use super::compression::{compress, decompress_reader};
+use super::container::Container;
+use super::encryption::{
+    decrypt_bytes, decrypt_if_needed, derive_seed, encrypt_bytes, encrypted_length, Cipher,
+};
+use super::envelope::FileEnvelope;
+use super::error::SteganographyError;
+use super::header::{header_length, PayloadHeader, PayloadKind};
+use super::jpeg::{encode_jpeg, is_jpeg, JpegImage, DEFAULT_JPEG_QUALITY};
+use super::matrix::choose_matrix_bits;
+use super::options::{
+    ChannelMask, EmbeddingMethod, EmbeddingOrder, SteganographyCapacityOption,
+    SteganographyExtractOption, SteganographyInjectOption,
+};
+use super::palette::{palette_format, PaletteImage};
 
 const NUMBER_BIT_PER_BYTE: u8 = 8;
 
+/// End of the message in images injected before the header existed
 const EOF_CHAR: char = 4u8 as char;
 
-/// From a 1d position, returns a 2d position using the width of the image
+/// The formats that save the channels exactly, the only ones an image that carries a payload is
+/// saved in. The others (E.g. GIF and its palette, lossy WebP) change the least significant bits
+const LOSSLESS_FORMATS: [ImageFormat; 8] = [
+    ImageFormat::Png,
+    ImageFormat::Bmp,
+    ImageFormat::Tiff,
+    ImageFormat::Tga,
+    ImageFormat::Pnm,
+    ImageFormat::Farbfeld,
+    ImageFormat::Qoi,
+    ImageFormat::Ico,
+];
+
+/// What was found in an image: a payload with its header or a message with the original
+/// format terminated by `EOF_CHAR`
+enum LocatedPayload<'a> {
+    /// The header and a reader positioned on the first byte of the payload
+    Framed {
+        header: PayloadHeader,
+        reader: Box<dyn Read + 'a>,
+    },
+    Legacy(Vec<u8>),
+}
+
+/// Reads exactly the number of bytes of the payload. Reaching the end of the image before is
+/// reported as a `CorruptPayload` error instead of a shorter payload
+struct PayloadLengthReader<R: Read> {
+    reader: R,
+    remaining: usize,
+}
+
+impl<R: Read> Read for PayloadLengthReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.remaining == 0 || buf.is_empty() {
+            return Ok(0);
+        }
+        let length = buf.len().min(self.remaining);
+        let count = self.reader.read(&mut buf[..length])?;
+        if count == 0 {
+            return Err(SteganographyError::CorruptPayload(
+                "The message is longer than the image".to_string(),
+            )
+            .into());
+        }
+        self.remaining -= count;
+        Ok(count)
+    }
+}
+
+/// Number of bytes that can be written into the channels of a carrier once the header is
+/// reserved
 ///
 /// # Arguments
 ///
-/// * `position` - The index position of the character in the string. Value varies from 0 to the length-1 of the text
-/// * `width` - The width of the image to determine when to change line (height)
+/// * `carriers` - Number of channels that carry the payload (E.g. width x height x 3 for the
+///   Red, Green and Blue channels of an image)
+/// * `password` - The header is longer when the payload is encrypted
+/// * `bits_per_channel` - Number of least significant bits of each channel that carry the payload
 ///
-fn get_coordinate(position: u32, width: u32) -> (u32, u32) {
-    let y = position / width; // No decimal
-    let x = position - (width * y);
+fn available_bytes(carriers: usize, password: &Option<String>, bits_per_channel: u8) -> usize {
+    let total_bits = carriers * bits_per_channel as usize;
+    (total_bits / NUMBER_BIT_PER_BYTE as usize).saturating_sub(header_length(password.is_some()))
+}
 
-    (x, y)
+/// Number of channels of an image that carry the payload
+///
+/// # Arguments
+///
+/// * `img` - The image that carries or would carry the payload
+/// * `samples_per_pixel` - Number of channels of each pixel that carry the payload
+fn image_carriers(img: &DynamicImage, samples_per_pixel: u8) -> usize {
+    img.width() as usize * img.height() as usize * samples_per_pixel as usize
 }
 
-/// Add a string (message) into an image that is referenced by a path in the `options` argument
+/// Verify that the number of bits per channel is one the library can write
+///
+/// # Errors
+///
+/// Returns `InvalidOption` when the number is not between 1 and `MAX_BITS_PER_CHANNEL`
+fn check_bits_per_channel(bits_per_channel: u8) -> Result<(), SteganographyError> {
+    if (1..=MAX_BITS_PER_CHANNEL).contains(&bits_per_channel) {
+        Ok(())
+    } else {
+        Err(SteganographyError::InvalidOption(format!(
+            "{} bits per channel, it must be between 1 and {}",
+            bits_per_channel, MAX_BITS_PER_CHANNEL
+        )))
+    }
+}
+
+/// Number of channels of each pixel of the image once ready to carry a message. The 8 and 16 bits
+/// grayscale, RGB and RGBA images keep their layout, the others are converted to RGBA
 ///
 /// # Arguments
 ///
-/// * `options` - Structure with the information about the message to insert and which image to use as
-///         the source and where to save the altered image that contain the secret message. The option contains
-///         the detail about if the message passed in the option must be encrypted
-///
-pub fn add_message_to_image(options: SteganographyInjectOption) {
-    let data_to_insert = encrypt_if_needed(options.message, options.password);
-    let data_to_add_with_eof = format!("{}{}", data_to_insert, EOF_CHAR);
-    let data_bytes = data_to_add_with_eof.as_bytes();
-    let img = image::open(options.input_image_path).unwrap();
-    // let exist_img_dimension = img.dimensions();
-    // println!("dimensions {:?}", exist_img_dimension);
-
-    // Modify
-    let mut new_img = img.to_rgba8();
-    let mut sliding_image_position = 0; // Position into the buffer
-
-    // The iteration order is x = 0 to width then y = 0 to height Starting from the top left.
-    // Pixel are a fixed array of 4 u8
-    // See: https://docs.rs/image/0.20.1/image/struct.Rgba.html
-    // and see : https://doc.rust-lang.org/nightly/std/primitive.array.html
-
-    for char_code in data_bytes {
-        let char_binary = char_to_binary_string(char_code);
-        let char_binary_bytes: Vec<u8> = char_binary
-            .as_bytes()
-            .iter()
-            .map(|c| u8::from(*c != 48))
-            .collect();
+/// * `img` - The image that carries or would carry the message
+fn carrier_channel_count(img: &DynamicImage) -> u8 {
+    match img {
+        DynamicImage::ImageLuma8(_)
+        | DynamicImage::ImageLumaA8(_)
+        | DynamicImage::ImageRgb8(_)
+        | DynamicImage::ImageRgba8(_)
+        | DynamicImage::ImageLuma16(_)
+        | DynamicImage::ImageLumaA16(_)
+        | DynamicImage::ImageRgb16(_)
+        | DynamicImage::ImageRgba16(_) => img.color().channel_count(),
+        _ => NUMBER_CHANNEL_PER_PIXEL,
+    }
+}
+
+/// Compute how many bytes of message an image can hide
+///
+/// The capacity accounts for the header and, when a password is provided,
+/// for the space the encryption adds to the message. Each additional bit per channel
+/// adds the capacity of one bit per channel. A number of bits per channel that is not
+/// supported gives a capacity of 0.
+///
+/// # Arguments
+///
+/// * `image` - The image that would carry the message
+/// * `options` - The encoding that would be used to inject the message
+///
+/// # Returns
+/// The maximum number of bytes of a message that fits into the image
+pub fn capacity(image: &DynamicImage, options: &SteganographyCapacityOption) -> usize {
+    if check_bits_per_channel(options.bits_per_channel).is_err() {
+        return 0;
+    }
+    let samples_per_pixel = options.channels.sample_count(carrier_channel_count(image));
+    let available = available_bytes(
+        image_carriers(image, samples_per_pixel),
+        &options.password,
+        options.bits_per_channel,
+    );
+    payload_capacity(available, &options.password)
+}
+
+/// Compute how many bytes of message a JPEG image can hide in its coefficients, one bit in each
+/// coefficient of magnitude 2 or more (see `jpeg::Coefficient`). The number of bits per channel
+/// and the channels of the options do not apply to JPEG images
+///
+/// # Arguments
+///
+/// * `jpeg` - The content of the JPEG file
+/// * `options` - The password that would encrypt the message (optional)
+///
+/// # Errors
+///
+/// Returns an error if the image is not a baseline JPEG image
+pub fn jpeg_capacity(
+    jpeg: &[u8],
+    options: &SteganographyCapacityOption,
+) -> Result<usize, SteganographyError> {
+    Ok(CarrierImage::Jpeg(JpegImage::decode(jpeg)?).capacity(options))
+}
+
+/// Compute how many bytes of message an image with a palette (a PNG image of the indexed color
+/// type or a GIF image) can hide, one bit in each pixel whose color is paired with another one
+/// (see `palette::PaletteImage`), in all the frames of an animated image. The number of bits per
+/// channel and the channels of the options do not apply to images with a palette
+///
+/// # Arguments
+///
+/// * `encoded_image` - The content of the PNG or GIF file
+/// * `options` - The password that would encrypt the message (optional)
+///
+/// # Errors
+///
+/// Returns an error if the image has no palette or cannot be decoded
+pub fn palette_capacity(
+    encoded_image: &[u8],
+    options: &SteganographyCapacityOption,
+) -> Result<usize, SteganographyError> {
+    Ok(CarrierImage::Palette(PaletteImage::decode(encoded_image)?).capacity(options))
+}
+
+/// Compute how many bytes of message carriers that are not channels can hide, one bit in each
+fn carriers_capacity(carriers: usize, options: &SteganographyCapacityOption) -> usize {
+    let available = available_bytes(carriers, &options.password, 1);
+    payload_capacity(available, &options.password)
+}
+
+/// Largest message length that still fits into the available bytes once encrypted
+fn payload_capacity(available: usize, password: &Option<String>) -> usize {
+    if encrypted_length(0, password) > available {
+        return 0;
+    }
+    let mut low = 0;
+    let mut high = available;
+    while low < high {
+        let middle = low + (high - low).div_ceil(2);
+        if encrypted_length(middle, password) <= available {
+            low = middle;
+        } else {
+            high = middle - 1;
+        }
+    }
+    low
+}
+
+/// Whether the file is a JPEG image, from its content
+///
+/// # Errors
+///
+/// Returns `Io` if the file cannot be read
+fn is_jpeg_file<P: AsRef<Path>>(path: P) -> Result<bool, SteganographyError> {
+    let mut start = Vec::new();
+    File::open(path)?.take(3).read_to_end(&mut start)?;
+    Ok(is_jpeg(&start))
+}
+
+/// The format of the file if it is an image with a palette, from its content, see
+/// `palette::palette_format`
+///
+/// # Errors
+///
+/// Returns `Io` if the file cannot be read
+fn palette_file_format<P: AsRef<Path>>(path: P) -> Result<Option<ImageFormat>, SteganographyError> {
+    let mut start = Vec::new();
+    File::open(path)?.take(32).read_to_end(&mut start)?;
+    Ok(palette_format(&start))
+}
+
+/// An image file opened with what carries its payload: the coefficients of a JPEG image, the
+/// ranks of the colors of an image with a palette or the channels of any other image. The
+/// functions that read or write image files open them with it so that every kind of image carries
+/// any payload the same way
+pub(crate) enum CarrierImage {
+    /// A baseline JPEG image, see `inject_bytes_into_jpeg`
+    Jpeg(JpegImage),
+    /// A PNG image of the indexed color type or a GIF image, see `inject_bytes_into_palette_image`
+    Palette(PaletteImage),
+    /// An image decoded to its channels, see `inject_bytes_into_image`
+    Pixels(DynamicImage),
+}
+
+impl CarrierImage {
+    /// Open an image file to extract its payload, the kind of image comes from its content
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or decoded
+    pub(crate) fn open<P: AsRef<Path>>(path: P) -> Result<Self, SteganographyError> {
+        let path = path.as_ref();
+        if is_jpeg_file(path)? {
+            let jpeg = std::fs::read(path)?;
+            return Ok(CarrierImage::Jpeg(JpegImage::decode(&jpeg)?));
+        }
+        if palette_file_format(path)?.is_some() {
+            let encoded_image = std::fs::read(path)?;
+            return Ok(CarrierImage::Palette(PaletteImage::decode(&encoded_image)?));
+        }
+        Ok(CarrierImage::Pixels(image::open(path)?))
+    }
+
+    /// Open an image file to inject a payload into, for the image to be saved at `output_path`.
+    /// An output path ending with `.jpg` or `.jpeg` gives a JPEG image, an input image of another
+    /// format is first encoded to JPEG with `DEFAULT_JPEG_QUALITY`. An input image with a palette
+    /// saved to the same format keeps its palette
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or decoded
+    pub(crate) fn open_for_output<P: AsRef<Path>, Q: AsRef<Path>>(
+        input_path: P,
+        output_path: Q,
+    ) -> Result<Self, SteganographyError> {
+        let input_path = input_path.as_ref();
+        let output_format = ImageFormat::from_path(output_path).ok();
+        if output_format == Some(ImageFormat::Jpeg) {
+            let jpeg = if is_jpeg_file(input_path)? {
+                std::fs::read(input_path)?
+            } else {
+                encode_jpeg(&image::open(input_path)?, DEFAULT_JPEG_QUALITY)?
+            };
+            return Ok(CarrierImage::Jpeg(JpegImage::decode(&jpeg)?));
+        }
+        if output_format.is_some() && palette_file_format(input_path)? == output_format {
+            let encoded_image = std::fs::read(input_path)?;
+            return Ok(CarrierImage::Palette(PaletteImage::decode(&encoded_image)?));
+        }
+        Ok(CarrierImage::Pixels(image::open(input_path)?))
+    }
+
+    /// Verify that the image can be saved at the path without losing its payload, before any
+    /// image is saved
+    ///
+    /// # Errors
+    ///
+    /// Returns `UnsupportedFormat` if the format of the path would lose the payload
+    pub(crate) fn check_output<P: AsRef<Path>>(&self, path: P) -> Result<(), SteganographyError> {
+        match self {
+            // Opened for an output path of their format, see `open_for_output`
+            CarrierImage::Jpeg(_) | CarrierImage::Palette(_) => Ok(()),
+            CarrierImage::Pixels(_) => check_output_format(ImageFormat::from_path(path)?),
+        }
+    }
+
+    /// Save the image, a JPEG image with its coefficients, an image with a palette with its
+    /// palette and any other image with `save_image`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be written or if its format would lose the payload
+    pub(crate) fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), SteganographyError> {
+        match self {
+            CarrierImage::Jpeg(image) => Ok(std::fs::write(path, image.encode()?)?),
+            CarrierImage::Palette(image) => Ok(std::fs::write(path, image.encode()?)?),
+            CarrierImage::Pixels(img) => save_image(img, path),
+        }
+    }
+
+    /// The image decoded to its channels, for the functions that return an image in memory
+    ///
+    /// # Errors
+    ///
+    /// Returns `UnsupportedFormat` for a JPEG image or an image with a palette, decoding their
+    /// carriers to pixels would lose the payload
+    pub(crate) fn into_pixels(self) -> Result<DynamicImage, SteganographyError> {
+        match self {
+            CarrierImage::Jpeg(_) => Err(SteganographyError::UnsupportedFormat(
+                "The payload of a JPEG image is in its coefficients, save it as JPEG".to_string(),
+            )),
+            CarrierImage::Palette(_) => Err(SteganographyError::UnsupportedFormat(
+                "The payload of an image with a palette is in its colors, save it in its format"
+                    .to_string(),
+            )),
+            CarrierImage::Pixels(img) => Ok(img),
+        }
+    }
+}
+
+/// An image that can carry a payload, in memory (`DynamicImage`) or opened from a file
+/// (`CarrierImage`)
+pub(crate) trait PayloadCarrier {
+    /// The maximum number of bytes of a message that fits into the image, see `capacity`
+    fn capacity(&self, options: &SteganographyCapacityOption) -> usize;
+
+    /// Write the payload into the image, see `inject_payload_into_image`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the payload does not fit into the image or if the options cannot be
+    /// used with the image
+    fn inject(
+        &mut self,
+        payload: &[u8],
+        kind: PayloadKind,
+        options: &SteganographyInjectOption,
+    ) -> Result<(), SteganographyError>;
+
+    /// Find the payload of the image, see `open_framed_payload`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no payload is found or if the password cannot decrypt the payload
+    fn open_framed_payload(
+        &self,
+        password: &Option<String>,
+    ) -> Result<(PayloadKind, Box<dyn Read + '_>), SteganographyError>;
+}
+
+impl PayloadCarrier for DynamicImage {
+    fn capacity(&self, options: &SteganographyCapacityOption) -> usize {
+        capacity(self, options)
+    }
+
+    fn inject(
+        &mut self,
+        payload: &[u8],
+        kind: PayloadKind,
+        options: &SteganographyInjectOption,
+    ) -> Result<(), SteganographyError> {
+        *self = inject_payload_into_image(self, payload, kind, options)?;
+        Ok(())
+    }
+
+    fn open_framed_payload(
+        &self,
+        password: &Option<String>,
+    ) -> Result<(PayloadKind, Box<dyn Read + '_>), SteganographyError> {
+        open_framed_payload(self, password)
+    }
+}
+
+impl PayloadCarrier for CarrierImage {
+    fn capacity(&self, options: &SteganographyCapacityOption) -> usize {
+        match self {
+            CarrierImage::Jpeg(image) => carriers_capacity(image.carriers().len(), options),
+            CarrierImage::Palette(image) => carriers_capacity(image.carriers().len(), options),
+            CarrierImage::Pixels(img) => capacity(img, options),
+        }
+    }
+
+    fn inject(
+        &mut self,
+        payload: &[u8],
+        kind: PayloadKind,
+        options: &SteganographyInjectOption,
+    ) -> Result<(), SteganographyError> {
+        match self {
+            CarrierImage::Jpeg(image) => inject_into_jpeg(image, payload, kind, options),
+            CarrierImage::Palette(image) => {
+                inject_into_palette_image(image, payload, kind, options)
+            }
+            CarrierImage::Pixels(img) => img.inject(payload, kind, options),
+        }
+    }
 
-        let mut i = 0;
-        while i < NUMBER_BIT_PER_BYTE {
-            // two loops: 0 to 3 bits, 4 to 8 bits (4 because r-g-b-a)
-            let coordinate = get_coordinate(sliding_image_position, img.width());
-            let current_color = new_img.get_pixel(coordinate.0, coordinate.1);
-
-            let mut new_rgba: [u8; 4] = [0, 0, 0, 0];
-            for irgba in 0..4 {
-                let bit = char_binary_bytes[i as usize];
-                let rgba = current_color[irgba]; // RGBA
-                new_rgba[irgba] = pack_bit(rgba, bit);
-                i += 1;
+    fn open_framed_payload(
+        &self,
+        password: &Option<String>,
+    ) -> Result<(PayloadKind, Box<dyn Read + '_>), SteganographyError> {
+        match self {
+            CarrierImage::Jpeg(image) => match read_from_carriers(&image.carriers(), password)? {
+                Some((kind, payload)) => Ok((kind, Box::new(Cursor::new(payload)))),
+                None => Err(SteganographyError::CorruptPayload(
+                    "No message found in the coefficients of the JPEG image".to_string(),
+                )),
+            },
+            CarrierImage::Palette(image) => {
+                match read_from_carriers(&image.carriers(), password)? {
+                    Some((kind, payload)) => Ok((kind, Box::new(Cursor::new(payload)))),
+                    None => Err(SteganographyError::CorruptPayload(
+                        "No message found in the pixels of the image with a palette".to_string(),
+                    )),
+                }
             }
-            sliding_image_position += 1;
-            new_img.put_pixel(coordinate.0, coordinate.1, image::Rgba(new_rgba));
+            CarrierImage::Pixels(img) => img.open_framed_payload(password),
         }
     }
-    // Out
-    new_img.save(options.output_image_path).unwrap();
 }
 
-/// Get a string (message) from an image that is referenced by a path in the `options` argument.
-/// It assumes the image was using `add_message_to_image` to find the hidden piece of information
+/// Compute how many bytes of message the image referenced by a path in the `options` argument can hide
 ///
 /// # Arguments
 ///
-/// * `options` - Structure with the where to find the image and detail about if the bytes retrieved
-///         need to be decrypted using the password provided (optional)
+/// * `options` - Structure with the image to measure and the encoding that would be used
 ///
-pub fn get_message_from_image(
-    options: SteganographyExtractOption,
-) -> Result<String, MagicCryptError> {
-    let img = image::open(options.input_image_path).unwrap();
+/// # Errors
+///
+/// Returns an error if the image cannot be read or if the number of bits per channel is not supported
+pub fn get_capacity_of_image(
+    options: SteganographyCapacityOption,
+) -> Result<usize, SteganographyError> {
+    check_bits_per_channel(options.bits_per_channel)?;
+    Ok(CarrierImage::open(&options.input_image_path)?.capacity(&options))
+}
 
-    let new_buffer = img.as_bytes();
-    let msg = get_message_from_buffer(new_buffer);
-    decrypt_if_needed(msg, options.password)
+/// Add a string (message) into an image that is referenced by a path in the `options` argument
+///
+/// # Arguments
+///
+/// * `options` - Structure with the information about the message to insert and which image to use as
+///   the source and where to save the altered image that contain the secret message. The option contains
+///   the detail about if the message passed in the option must be encrypted
+///
+/// # Errors
+///
+/// Returns an error if the input image cannot be read, if the message does not fit into the image
+/// or if the output image cannot be written. Returns `UnsupportedFormat` if the format of the
+/// output image would lose the message (E.g. GIF), see `save_image`. With the `verify` option,
+/// returns `Verification` if the saved image does not give back the message
+pub fn add_message_to_image(options: SteganographyInjectOption) -> Result<(), SteganographyError> {
+    let message = options.message.clone();
+    inject_bytes(message.as_bytes(), options)
 }
 
-/// Get an array of bytes to extract the char
+/// Add any bytes (binary file, key, compressed archive) into an image. The bytes are extracted
+/// back unaltered with `extract_bytes`
+///
+/// An output path ending with `.jpg` or `.jpeg` gives a JPEG image that carries the bytes in its
+/// coefficients, see `inject_bytes_into_jpeg`. An input image of another format is first encoded
+/// to JPEG with `DEFAULT_JPEG_QUALITY`. An input image with a palette (a PNG image of the indexed
+/// color type or a GIF image) saved to the same format keeps its palette, see
+/// `inject_bytes_into_palette_image`
 ///
 /// # Arguments
 ///
-/// * `new_buffer` - An array of bytes that represent the whole image. Each bytes are a part of
-///         the image colors.
-///    The buffer has the pattern [R, G, B, A, R, G, B, A, ...]
+/// * `payload` - The bytes to hide. It replaces the `message` of the options which is not used
+/// * `options` - Structure with the image to use as the source, where to save the altered image,
+///   the optional password to encrypt the payload, the order of the pixels and the number of bits
+///   of each channel to use
 ///
-pub fn get_message_from_buffer(new_buffer: &[u8]) -> String {
-    let mut result = String::new();
-    let mut data_position = 0;
-    let mut last_character = 0 as char;
-    let mut bit_counter = 0;
-    let mut bits = String::new();
-    while last_character != EOF_CHAR && data_position < new_buffer.len() {
-        while bit_counter < NUMBER_BIT_PER_BYTE {
-            let rgba_color = new_buffer[data_position];
-            let bit = unpack_bit(rgba_color);
-            bits.push_str(if bit == 0 { "0" } else { "1" });
-            data_position += 1;
-            bit_counter += 1;
-        }
-        last_character = binary_string_to_char(bits);
-        if last_character != EOF_CHAR {
-            result.push(last_character);
-        }
-        bit_counter = 0;
-        bits = "".to_string();
+/// # Errors
+///
+/// Returns an error if the input image cannot be read, if the payload does not fit into the image,
+/// if the options cannot be used together or if the output image cannot be written. Returns
+/// `UnsupportedFormat` if the format of the output image would lose the payload
+pub fn inject_bytes(
+    payload: &[u8],
+    options: SteganographyInjectOption,
+) -> Result<(), SteganographyError> {
+    check_inject_options(&options)?;
+    let mut carrier =
+        CarrierImage::open_for_output(&options.input_image_path, &options.output_image_path)?;
+    carrier.inject(payload, PayloadKind::Bytes, &options)?;
+    carrier.save(&options.output_image_path)?;
+    if options.verify {
+        verify_payload(payload, extract_bytes(saved_extract_options(&options)))?;
     }
+    Ok(())
+}
 
-    result
+/// The options to extract the payload back from the image saved by an injection
+pub(crate) fn saved_extract_options(
+    options: &SteganographyInjectOption,
+) -> SteganographyExtractOption {
+    SteganographyExtractOption {
+        password: options.password.clone(),
+        input_image_path: options.output_image_path.clone(),
+        ..Default::default()
+    }
 }
-#[cfg(test)]
-mod test_get_string {
-    use super::*;
 
-    #[test]
-    fn test_add_message_to_image() {
-        let options = SteganographyInjectOption {
-            input_image_path: "testAssets/prestine.png".to_string(),
-            message: "Bye".to_string(),
-            output_image_path: "testAssets/out.png".to_string(),
-            password: None,
-        };
-        add_message_to_image(options);
+/// Verify that the payload extracted back from the saved image is the injected payload, by
+/// comparing their SHA-256 hashes
+///
+/// # Arguments
+///
+/// * `injected` - The payload given to the injection
+/// * `extracted` - The result of the extraction of the saved image
+///
+/// # Errors
+///
+/// Returns `Verification` if the extraction fails or if the hashes differ
+pub(crate) fn verify_payload(
+    injected: &[u8],
+    extracted: Result<Vec<u8>, SteganographyError>,
+) -> Result<(), SteganographyError> {
+    let extracted = extracted.map_err(|e| SteganographyError::Verification(e.to_string()))?;
+    if Sha256::digest(injected) != Sha256::digest(&extracted) {
+        return Err(SteganographyError::Verification(
+            "the extracted message differs from the injected one".to_string(),
+        ));
     }
+    Ok(())
+}
 
-    #[test]
-    fn test_get_coordinate_first_row() {
-        let result = get_coordinate(2, 10);
-        assert_eq!(result.0, 2);
-        assert_eq!(result.1, 0);
+/// Verify that an image that carries a payload can be saved in the format without losing it
+///
+/// # Errors
+///
+/// Returns `UnsupportedFormat` if the format is not one of `LOSSLESS_FORMATS`
+pub(crate) fn check_output_format(format: ImageFormat) -> Result<(), SteganographyError> {
+    if LOSSLESS_FORMATS.contains(&format) {
+        Ok(())
+    } else {
+        Err(SteganographyError::UnsupportedFormat(format!(
+            "The {:?} format would lose the message, save the image as PNG",
+            format
+        )))
     }
+}
 
-    #[test]
-    fn test_get_coordinate_second_row() {
-        let result = get_coordinate(10, 10);
-        assert_eq!(result.0, 0);
-        assert_eq!(result.1, 1);
+/// Save an image that carries a payload, in the format of the extension of the path. The saved
+/// file is read back: an encoder that converts the channels (E.g. 16 bits to 8 bits) would lose
+/// the payload
+///
+/// # Errors
+///
+/// Returns `UnsupportedFormat` if the format is not lossless or if the saved image does not have
+/// the channels of the image, an error if the file cannot be written
+pub(crate) fn save_image<P: AsRef<Path>>(
+    img: &DynamicImage,
+    path: P,
+) -> Result<(), SteganographyError> {
+    let format = ImageFormat::from_path(&path)?;
+    check_output_format(format)?;
+    img.save_with_format(&path, format)?;
+    if image::open(&path)? != *img {
+        return Err(SteganographyError::UnsupportedFormat(format!(
+            "The {:?} format does not keep the channels of the image, save the image as PNG",
+            format
+        )));
     }
+    Ok(())
+}
 
-    #[test]
-    fn test_get_coordinate_third_row() {
-        let result = get_coordinate(22, 10);
-        assert_eq!(result.0, 2);
-        assert_eq!(result.1, 2);
+/// Add any bytes into an image already in memory, without reading or writing any file. The
+/// bytes are extracted back unaltered with `extract_bytes_from_image`
+///
+/// # Arguments
+///
+/// * `image` - The image to carry the bytes. It is not altered, a copy is returned. An
+///   `RgbaImage` (or any other `ImageBuffer`) can be wrapped with `DynamicImage::from`
+/// * `payload` - The bytes to hide
+/// * `options` - The password, the order of the pixels, the number of bits and the channels to
+///   use. The paths and the message of the options are not used
+///
+/// # Returns
+/// The image with the bytes, with the same color type as `image` when it is supported
+///
+/// # Errors
+///
+/// Returns an error if the payload does not fit into the image or if the options cannot be used
+/// together
+pub fn inject_bytes_into_image(
+    image: &DynamicImage,
+    payload: &[u8],
+    options: &SteganographyInjectOption,
+) -> Result<DynamicImage, SteganographyError> {
+    inject_payload_into_image(image, payload, PayloadKind::Bytes, options)
+}
+
+/// Add a file with its name and metadata into an image already in memory. The file is extracted
+/// back with `extract_file_from_image`, the other extractions return its content
+///
+/// # Arguments
+///
+/// * `image` - The image to carry the file. It is not altered, a copy is returned
+/// * `envelope` - The file to hide, see `FileEnvelope::from_path`
+/// * `options` - The encoding to use, see `inject_bytes_into_image`
+///
+/// # Errors
+///
+/// Returns an error if the file does not fit into the image or if the options cannot be used
+/// together
+pub fn inject_file_into_image(
+    image: &DynamicImage,
+    envelope: &FileEnvelope,
+    options: &SteganographyInjectOption,
+) -> Result<DynamicImage, SteganographyError> {
+    inject_payload_into_image(image, &envelope.to_bytes()?, PayloadKind::File, options)
+}
+
+/// Add several named entries into an image already in memory. The entries are extracted back
+/// with `extract_container_from_image`
+///
+/// # Arguments
+///
+/// * `image` - The image to carry the entries. It is not altered, a copy is returned
+/// * `container` - The entries to hide, each one encrypted with its own password (optional)
+/// * `options` - The encoding to use, see `inject_bytes_into_image`
+///
+/// # Errors
+///
+/// Returns an error if the entries do not fit into the image or if the options cannot be used
+/// together
+pub fn inject_container_into_image(
+    image: &DynamicImage,
+    container: &Container,
+    options: &SteganographyInjectOption,
+) -> Result<DynamicImage, SteganographyError> {
+    inject_payload_into_image(
+        image,
+        &container.to_bytes()?,
+        PayloadKind::Container,
+        options,
+    )
+}
+
+/// Add a payload of any kind into an image, the kind is written in the header
+pub(crate) fn inject_payload_into_image(
+    image: &DynamicImage,
+    payload: &[u8],
+    kind: PayloadKind,
+    options: &SteganographyInjectOption,
+) -> Result<DynamicImage, SteganographyError> {
+    check_inject_options(options)?;
+    let order = match (&options.password, options.order) {
+        (Some(password), EmbeddingOrder::Scattered) => {
+            ChannelOrder::Scattered(derive_seed(password)?)
+        }
+        (_, EmbeddingOrder::Adaptive) => adaptive_order(image),
+        _ => ChannelOrder::Sequential,
+    };
+    let samples_per_pixel = options.channels.sample_count(carrier_channel_count(image));
+    if samples_per_pixel == 0 {
+        return Err(SteganographyError::InvalidOption(format!(
+            "The image has none of the channels \"{}\"",
+            options.channels
+        )));
     }
+    let carriers = image_carriers(image, samples_per_pixel);
+    let (header, payload) = frame_payload(payload, kind, options, options.channels, carriers)?;
+    write_into_image(image.clone(), header, &payload, &order, options.method)
+}
 
-    #[test]
-    fn test_get_message_from_image() {
-        let options = SteganographyExtractOption {
-            input_image_path: "testAssets/out_message_Bye.png".to_string(),
-            password: None,
-        };
-        let message = get_message_from_image(options).unwrap();
-        assert_eq!(message, "Bye".to_string());
+/// Add any bytes into a baseline JPEG image, in the least significant bit of its quantized AC
+/// coefficients of magnitude 2 or more (see `jpeg::Coefficient`). The coefficients are written
+/// back without decoding the pixels, so the image is not compressed a second time. The bytes are
+/// extracted back with `extract_bytes_from_jpeg`
+///
+/// # Arguments
+///
+/// * `jpeg` - The content of the JPEG file
+/// * `payload` - The bytes to hide
+/// * `options` - The password, the order of the coefficients (sequential or scattered), the
+///   compression and the matrix embedding. A JPEG image carries one bit per coefficient with the
+///   replacement method, the channels of the options are not used
+///
+/// # Returns
+/// The content of the JPEG file that carries the payload
+///
+/// # Errors
+///
+/// Returns an error if the image is not a baseline JPEG image, if the payload does not fit into
+/// the image or if the options cannot be used with a JPEG image
+pub fn inject_bytes_into_jpeg(
+    jpeg: &[u8],
+    payload: &[u8],
+    options: &SteganographyInjectOption,
+) -> Result<Vec<u8>, SteganographyError> {
+    let mut image = JpegImage::decode(jpeg)?;
+    inject_into_jpeg(&mut image, payload, PayloadKind::Bytes, options)?;
+    image.encode()
+}
+
+/// Write a payload of any kind into the coefficients of a JPEG image, see `inject_bytes_into_jpeg`
+fn inject_into_jpeg(
+    image: &mut JpegImage,
+    payload: &[u8],
+    kind: PayloadKind,
+    options: &SteganographyInjectOption,
+) -> Result<(), SteganographyError> {
+    check_inject_options(options)?;
+    check_carrier_options(options, "a JPEG image", "coefficient")?;
+    let mut carriers = image.carriers();
+    write_into_carriers(&mut carriers, payload, kind, options)?;
+    image.set_carriers(&carriers);
+    Ok(())
+}
+
+/// Add any bytes into an image with a palette (a PNG image of the indexed color type or a GIF
+/// image), in the parity of the rank of the color of its pixels once the palette is sorted by
+/// luminance (see `palette::PaletteImage`). A pixel only takes the color it is paired with, which
+/// looks alike, and the image is encoded back to its format with the same palette. The bytes are
+/// extracted back with `extract_bytes_from_palette_image`
+///
+/// An animated image (GIF or APNG) carries the bytes in all its frames, one after the other with
+/// the sequential order or scattered over all of them with the scattered order. The position, the
+/// delay and the disposal of the frames are kept
+///
+/// # Arguments
+///
+/// * `encoded_image` - The content of the PNG or GIF file
+/// * `payload` - The bytes to hide
+/// * `options` - The password, the order of the pixels (sequential or scattered), the
+///   compression and the matrix embedding. An image with a palette carries one bit per pixel
+///   with the replacement method, the channels of the options are not used
+///
+/// # Returns
+/// The content of the file that carries the payload, in the format of `encoded_image`
+///
+/// # Errors
+///
+/// Returns an error if the image has no palette, if the payload does not fit into the image or if
+/// the options cannot be used with an image with a palette
+pub fn inject_bytes_into_palette_image(
+    encoded_image: &[u8],
+    payload: &[u8],
+    options: &SteganographyInjectOption,
+) -> Result<Vec<u8>, SteganographyError> {
+    let mut image = PaletteImage::decode(encoded_image)?;
+    inject_into_palette_image(&mut image, payload, PayloadKind::Bytes, options)?;
+    image.encode()
+}
+
+/// Write a payload of any kind into the pixels of an image with a palette, see
+/// `inject_bytes_into_palette_image`
+fn inject_into_palette_image(
+    image: &mut PaletteImage,
+    payload: &[u8],
+    kind: PayloadKind,
+    options: &SteganographyInjectOption,
+) -> Result<(), SteganographyError> {
+    check_inject_options(options)?;
+    check_carrier_options(options, "an image with a palette", "pixel")?;
+    let mut carriers = image.carriers();
+    write_into_carriers(&mut carriers, payload, kind, options)?;
+    image.set_carriers(&carriers);
+    Ok(())
+}
+
+/// Verify the options that an image whose carriers are not channels (E.g. a JPEG image) cannot use
+///
+/// # Arguments
+///
+/// * `options` - The options to verify
+/// * `image` - The kind of image with its article (E.g. "a JPEG image"), for the error message
+/// * `carrier` - What carries a bit of the message in that kind of image, for the error message
+///
+/// # Errors
+///
+/// Returns `InvalidOption` if the options use more than one bit per carrier, the matching
+/// method or the adaptive order
+fn check_carrier_options(
+    options: &SteganographyInjectOption,
+    image: &str,
+    carrier: &str,
+) -> Result<(), SteganographyError> {
+    if options.bits_per_channel != 1 {
+        return Err(SteganographyError::InvalidOption(format!(
+            "The {}s of {} carry one bit each",
+            carrier, image
+        )));
+    }
+    // Moving a coefficient by one can bring it to 1 and moving the rank of a color by one can
+    // leave its pair, it would not be found at the extraction
+    if options.method == EmbeddingMethod::Matching {
+        return Err(SteganographyError::InvalidOption(format!(
+            "The replacement method is required by {}",
+            image
+        )));
     }
+    if options.order == EmbeddingOrder::Adaptive {
+        return Err(SteganographyError::InvalidOption(format!(
+            "The adaptive order does not apply to {}",
+            image
+        )));
+    }
+    Ok(())
+}
 
-    #[test]
-    fn test_get_message_from_buffer() {
-        let buffer = [
-            // Letter B
-            binary_string_to_char("00000000".to_string()) as u8,
-            binary_string_to_char("00000001".to_string()) as u8,
-            binary_string_to_char("00000000".to_string()) as u8,
-            binary_string_to_char("00000000".to_string()) as u8,
-            binary_string_to_char("00000000".to_string()) as u8,
-            binary_string_to_char("00000000".to_string()) as u8,
-            binary_string_to_char("00000001".to_string()) as u8,
-            binary_string_to_char("00000000".to_string()) as u8,
-            // End of file Character
-            binary_string_to_char("00000000".to_string()) as u8,
-            binary_string_to_char("00000000".to_string()) as u8,
-            binary_string_to_char("00000000".to_string()) as u8,
-            binary_string_to_char("00000000".to_string()) as u8,
-            binary_string_to_char("00000000".to_string()) as u8,
-            binary_string_to_char("00000001".to_string()) as u8,
-            binary_string_to_char("00000000".to_string()) as u8,
-            binary_string_to_char("00000000".to_string()) as u8,
-        ];
-        let message = get_message_from_buffer(&buffer);
-        assert_eq!(message, "B".to_string());
+/// Write the header and the payload into carriers that are not the channels of an image (E.g.
+/// the coefficients of a JPEG image), one bit per carrier
+///
+/// # Errors
+///
+/// Returns `Capacity` if the payload does not fit into the carriers
+fn write_into_carriers<S: Sample>(
+    carriers: &mut [S],
+    payload: &[u8],
+    kind: PayloadKind,
+    options: &SteganographyInjectOption,
+) -> Result<(), SteganographyError> {
+    let order = match (&options.password, options.order) {
+        (Some(password), EmbeddingOrder::Scattered) => {
+            ChannelOrder::Scattered(derive_seed(password)?)
+        }
+        _ => ChannelOrder::Sequential,
+    };
+    let (header, payload) =
+        frame_payload(payload, kind, options, ChannelMask::RGBA, carriers.len())?;
+    let count = carriers.len();
+    let mut writer = ChannelWriter::new(
+        carriers,
+        order.positions(count, header.channels, 1),
+        header.bits_per_channel,
+        options.method,
+    );
+    write_framed(&mut writer, header, &payload)
+}
+
+/// Compress and encrypt a payload and build the header that precedes it
+///
+/// # Arguments
+///
+/// * `payload` - The bytes to hide
+/// * `kind` - The kind of payload, written in the header
+/// * `options` - The password, the compression, the number of bits per channel and the matrix
+///   embedding to use
+/// * `channels` - The channels written in the header
+/// * `carriers` - Number of channels of the carrier that receive the header and the payload
+///
+/// # Returns
+/// The header and the payload once compressed and encrypted
+///
+/// # Errors
+///
+/// Returns `Capacity` if the payload does not fit into the carriers or is longer than the header
+/// can record (4 GiB)
+fn frame_payload(
+    payload: &[u8],
+    kind: PayloadKind,
+    options: &SteganographyInjectOption,
+    channels: ChannelMask,
+    carriers: usize,
+) -> Result<(PayloadHeader, Vec<u8>), SteganographyError> {
+    let available = available_bytes(carriers, &options.password, options.bits_per_channel);
+    // The compression comes first, encrypted bytes do not compress
+    let payload = compress(payload, options.compression)?;
+    let required = encrypted_length(payload.len(), &options.password);
+    if required > available {
+        return Err(SteganographyError::Capacity {
+            required,
+            available,
+        });
+    }
+    // The header records the length on 4 bytes, whatever the image can hold
+    let payload_length = u32::try_from(required).map_err(|_| SteganographyError::Capacity {
+        required,
+        available: u32::MAX as usize,
+    })?;
+    let mut header = PayloadHeader {
+        cipher: options.password.as_ref().map(|_| Cipher::generate()),
+        bits_per_channel: options.bits_per_channel,
+        channels,
+        compression: options.compression,
+        kind,
+        payload_length,
+        matrix_bits: None,
+    };
+    if options.matrix_embedding {
+        // The byte of the matrix embedding lengthens the header
+        let header_channels = (header.length() + 1) * NUMBER_BIT_PER_BYTE as usize;
+        let carriers = carriers.saturating_sub(header_channels);
+        header.matrix_bits = Some(choose_matrix_bits(required, carriers).ok_or(
+            SteganographyError::Capacity {
+                required,
+                available: carriers / NUMBER_BIT_PER_BYTE as usize,
+            },
+        )?);
+    }
+    // The header is authenticated with the payload to detect any alteration
+    let payload = match (&options.password, header.cipher) {
+        (Some(password), Some(cipher)) => {
+            encrypt_bytes(&payload, password, cipher, &header.to_bytes())?
+        }
+        _ => payload,
+    };
+    Ok((header, payload))
+}
+
+/// Add any bytes into an encoded image (E.g. the content of a PNG file received by a server)
+/// and encode the result, without reading or writing any file
+///
+/// # Arguments
+///
+/// * `encoded_image` - The bytes of the image file, the format is detected from its content
+/// * `payload` - The bytes to hide
+/// * `options` - The encoding to use, see `inject_bytes_into_image`
+/// * `format` - The format of the returned image. It must be lossless (E.g. `ImageFormat::Png`)
+///   or `ImageFormat::Jpeg` for the payload to go into the coefficients, see
+///   `inject_bytes_into_jpeg`. An image with a palette encoded to its own format keeps its
+///   palette, see `inject_bytes_into_palette_image`
+///
+/// # Returns
+/// The bytes of the image file that carries the payload
+///
+/// # Errors
+///
+/// Returns an error if the image cannot be decoded or encoded, if the payload does not fit into
+/// the image or if the options cannot be used together. Returns `UnsupportedFormat` if the format
+/// would lose the payload
+pub fn inject_bytes_into_encoded_image(
+    encoded_image: &[u8],
+    payload: &[u8],
+    options: &SteganographyInjectOption,
+    format: ImageFormat,
+) -> Result<Vec<u8>, SteganographyError> {
+    check_inject_options(options)?;
+    if format == ImageFormat::Jpeg {
+        if is_jpeg(encoded_image) {
+            return inject_bytes_into_jpeg(encoded_image, payload, options);
+        }
+        let img = image::load_from_memory(encoded_image)?;
+        return inject_bytes_into_jpeg(&encode_jpeg(&img, DEFAULT_JPEG_QUALITY)?, payload, options);
+    }
+    if palette_format(encoded_image) == Some(format) {
+        return inject_bytes_into_palette_image(encoded_image, payload, options);
+    }
+    check_output_format(format)?;
+    let img = image::load_from_memory(encoded_image)?;
+    let new_img = inject_bytes_into_image(&img, payload, options)?;
+    let mut output = Cursor::new(Vec::new());
+    new_img.write_to(&mut output, format)?;
+    Ok(output.into_inner())
+}
+
+/// Verify the options that do not depend on the image, before any image is read
+///
+/// # Errors
+///
+/// Returns `InvalidOption` if the number of bits per channel is not supported, if the scattered
+/// order has no password, if the matrix embedding uses more than one bit per channel or if the
+/// adaptive order is used with the matching method
+fn check_inject_options(options: &SteganographyInjectOption) -> Result<(), SteganographyError> {
+    check_bits_per_channel(options.bits_per_channel)?;
+    if options.matrix_embedding && options.bits_per_channel != 1 {
+        return Err(SteganographyError::InvalidOption(
+            "The matrix embedding uses one bit per channel".to_string(),
+        ));
+    }
+    if options.order == EmbeddingOrder::Scattered && options.password.is_none() {
+        return Err(SteganographyError::InvalidOption(
+            "The scattered order requires a password".to_string(),
+        ));
+    }
+    // Increasing or decreasing a value can change its high bits and the ranking of the pixels
+    if options.order == EmbeddingOrder::Adaptive && options.method == EmbeddingMethod::Matching {
+        return Err(SteganographyError::InvalidOption(
+            "The adaptive order requires the replacement method".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Rank the pixels of the image from the most textured, in the layout the image carries the
+/// payload with (see `carrier_channel_count`)
+fn adaptive_order(img: &DynamicImage) -> ChannelOrder {
+    let channel_count = carrier_channel_count(img);
+    let width = img.width();
+    ChannelOrder::Adaptive(match img {
+        DynamicImage::ImageLuma8(buffer) => texture_order(buffer.as_raw(), width, channel_count),
+        DynamicImage::ImageLumaA8(buffer) => texture_order(buffer.as_raw(), width, channel_count),
+        DynamicImage::ImageRgb8(buffer) => texture_order(buffer.as_raw(), width, channel_count),
+        DynamicImage::ImageRgba8(buffer) => texture_order(buffer.as_raw(), width, channel_count),
+        DynamicImage::ImageLuma16(buffer) => texture_order(buffer.as_raw(), width, channel_count),
+        DynamicImage::ImageLumaA16(buffer) => texture_order(buffer.as_raw(), width, channel_count),
+        DynamicImage::ImageRgb16(buffer) => texture_order(buffer.as_raw(), width, channel_count),
+        DynamicImage::ImageRgba16(buffer) => texture_order(buffer.as_raw(), width, channel_count),
+        other => texture_order(other.to_rgba8().as_raw(), width, channel_count),
+    })
+}
+
+/// Write the bytes into the image, keeping its color type when it is supported (see
+/// `carrier_channel_count`) so the saved image has the same layout as the source
+///
+/// # Arguments
+///
+/// * `img` - The image that carries the bytes
+/// * `header` - The header written before the payload, it tells the number of bits per channel,
+///   the channels and the matrix embedding to use
+/// * `payload` - The payload, once compressed and encrypted
+/// * `order` - The order in which the channels receive the bytes
+/// * `method` - How the bits of each channel are changed
+fn write_into_image(
+    img: DynamicImage,
+    header: PayloadHeader,
+    payload: &[u8],
+    order: &ChannelOrder,
+    method: EmbeddingMethod,
+) -> Result<DynamicImage, SteganographyError> {
+    Ok(match img {
+        DynamicImage::ImageLuma8(mut buffer) => {
+            write_into_buffer(&mut buffer, header, payload, order, method)?;
+            DynamicImage::ImageLuma8(buffer)
+        }
+        DynamicImage::ImageLumaA8(mut buffer) => {
+            write_into_buffer(&mut buffer, header, payload, order, method)?;
+            DynamicImage::ImageLumaA8(buffer)
+        }
+        DynamicImage::ImageRgb8(mut buffer) => {
+            write_into_buffer(&mut buffer, header, payload, order, method)?;
+            DynamicImage::ImageRgb8(buffer)
+        }
+        DynamicImage::ImageLuma16(mut buffer) => {
+            write_into_buffer(&mut buffer, header, payload, order, method)?;
+            DynamicImage::ImageLuma16(buffer)
+        }
+        DynamicImage::ImageLumaA16(mut buffer) => {
+            write_into_buffer(&mut buffer, header, payload, order, method)?;
+            DynamicImage::ImageLumaA16(buffer)
+        }
+        DynamicImage::ImageRgb16(mut buffer) => {
+            write_into_buffer(&mut buffer, header, payload, order, method)?;
+            DynamicImage::ImageRgb16(buffer)
+        }
+        DynamicImage::ImageRgba16(mut buffer) => {
+            write_into_buffer(&mut buffer, header, payload, order, method)?;
+            DynamicImage::ImageRgba16(buffer)
+        }
+        other => {
+            let mut buffer = other.to_rgba8();
+            write_into_buffer(&mut buffer, header, payload, order, method)?;
+            DynamicImage::ImageRgba8(buffer)
+        }
+    })
+}
+
+/// Write the header and the payload into the channels of an image buffer of any pixel layout
+fn write_into_buffer<P>(
+    buffer: &mut ImageBuffer<P, Vec<P::Subpixel>>,
+    header: PayloadHeader,
+    payload: &[u8],
+    order: &ChannelOrder,
+    method: EmbeddingMethod,
+) -> Result<(), SteganographyError>
+where
+    P: Pixel,
+    P::Subpixel: Sample,
+{
+    let count = buffer.as_raw().len();
+    let mut writer = ChannelWriter::new(
+        buffer,
+        order.positions(count, header.channels, P::CHANNEL_COUNT),
+        header.bits_per_channel,
+        method,
+    );
+    write_framed(&mut writer, header, payload)
+}
+
+/// Write the header and then the payload, with the matrix embedding the header tells
+fn write_framed<B: SampleBuffer + ?Sized>(
+    writer: &mut ChannelWriter<B>,
+    header: PayloadHeader,
+    payload: &[u8],
+) -> Result<(), SteganographyError> {
+    let mut data_bytes = header.to_bytes();
+    match header.matrix_bits {
+        // The header is always read channel after channel
+        Some(matrix_bits) => {
+            writer.write_bytes(&data_bytes)?;
+            writer.write_matrix_bytes(payload, matrix_bits)
+        }
+        None => {
+            data_bytes.extend(payload);
+            writer.write_bytes(&data_bytes)
+        }
+    }
+}
+
+/// Get a string (message) from an image that is referenced by a path in the `options` argument.
+/// It assumes the image was using `add_message_to_image` to find the hidden piece of information
+///
+/// # Arguments
+///
+/// * `options` - Structure with the where to find the image and detail about if the bytes retrieved
+///   need to be decrypted using the password provided (optional)
+///
+/// # Errors
+///
+/// Returns an error if the image cannot be read, if no message is found or if the password
+/// cannot decrypt the message
+pub fn get_message_from_image(
+    options: SteganographyExtractOption,
+) -> Result<String, SteganographyError> {
+    let bytes = extract_bytes(options)?;
+    bytes_to_message(bytes)
+}
+
+/// Get the bytes hidden into an image that is referenced by a path in the `options` argument.
+/// The bytes are the exact payload given to `inject_bytes` (or the message of `add_message_to_image`)
+///
+/// # Arguments
+///
+/// * `options` - Structure with the where to find the image and detail about if the bytes retrieved
+///   need to be decrypted using the password provided (optional)
+///
+/// # Errors
+///
+/// Returns an error if the image cannot be read, if no payload is found or if the password
+/// cannot decrypt the payload
+pub fn extract_bytes(options: SteganographyExtractOption) -> Result<Vec<u8>, SteganographyError> {
+    extract_payload(
+        &CarrierImage::open(&options.input_image_path)?,
+        &options.password,
+    )
+}
+
+/// Get the bytes hidden into an image already in memory, without reading any file
+///
+/// # Arguments
+///
+/// * `image` - The image that carries the bytes
+/// * `options` - The password to decrypt the bytes (optional). The path of the options is not used
+///
+/// # Errors
+///
+/// Returns an error if no payload is found or if the password cannot decrypt the payload
+pub fn extract_bytes_from_image(
+    image: &DynamicImage,
+    options: &SteganographyExtractOption,
+) -> Result<Vec<u8>, SteganographyError> {
+    extract_payload(image, &options.password)
+}
+
+/// Read the payload of any carrier, the content of a file, see `payload_reader`
+fn extract_payload<C: PayloadCarrier>(
+    carrier: &C,
+    password: &Option<String>,
+) -> Result<Vec<u8>, SteganographyError> {
+    let mut payload = Vec::new();
+    open_payload(carrier, password)?.read_to_end(&mut payload)?;
+    Ok(payload)
+}
+
+/// Get the bytes hidden into the coefficients of a JPEG image with `inject_bytes_into_jpeg`
+///
+/// # Arguments
+///
+/// * `jpeg` - The content of the JPEG file
+/// * `options` - The password to decrypt the bytes (optional). The path of the options is not used
+///
+/// # Errors
+///
+/// Returns an error if the image is not a baseline JPEG image, if no payload is found or if the
+/// password cannot decrypt the payload
+pub fn extract_bytes_from_jpeg(
+    jpeg: &[u8],
+    options: &SteganographyExtractOption,
+) -> Result<Vec<u8>, SteganographyError> {
+    extract_payload(
+        &CarrierImage::Jpeg(JpegImage::decode(jpeg)?),
+        &options.password,
+    )
+}
+
+/// Get the bytes hidden into an image with a palette with `inject_bytes_into_palette_image`
+///
+/// # Arguments
+///
+/// * `encoded_image` - The content of the PNG or GIF file
+/// * `options` - The password to decrypt the bytes (optional). The path of the options is not used
+///
+/// # Errors
+///
+/// Returns an error if the image has no palette, if no payload is found or if the password
+/// cannot decrypt the payload
+pub fn extract_bytes_from_palette_image(
+    encoded_image: &[u8],
+    options: &SteganographyExtractOption,
+) -> Result<Vec<u8>, SteganographyError> {
+    let image = CarrierImage::Palette(PaletteImage::decode(encoded_image)?);
+    extract_payload(&image, &options.password)
+}
+
+/// Read the payload written by `write_into_carriers`. The carriers do not outlive the caller,
+/// the payload is read now
+///
+/// # Returns
+/// The kind of the payload with its bytes once decrypted and decompressed, `None` when no header
+/// is found
+fn read_from_carriers<S: Sample>(
+    carriers: &[S],
+    password: &Option<String>,
+) -> Result<Option<(PayloadKind, Vec<u8>)>, SteganographyError> {
+    let orders = password_orders(password)?;
+    let located = find_header(carriers, 1, &orders)?;
+    match located {
+        Some(LocatedPayload::Framed { header, reader }) => {
+            let kind = header.kind;
+            let mut payload = Vec::new();
+            decode_payload(header, reader, password)?.read_to_end(&mut payload)?;
+            Ok(Some((kind, payload)))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Get the file hidden into an image already in memory with `inject_file_into_image`
+///
+/// # Arguments
+///
+/// * `image` - The image that carries the file
+/// * `options` - The password to decrypt the file (optional). The paths of the options are not
+///   used
+///
+/// # Errors
+///
+/// Returns `InvalidOption` if the image carries a message instead of a file, `CorruptPayload`
+/// if the content of the file does not match its hash
+pub fn extract_file_from_image(
+    image: &DynamicImage,
+    options: &SteganographyExtractOption,
+) -> Result<FileEnvelope, SteganographyError> {
+    extract_file_from_carrier(image, &options.password)
+}
+
+/// Get the file hidden into any carrier, see `extract_file_from_image`
+pub(crate) fn extract_file_from_carrier<C: PayloadCarrier>(
+    carrier: &C,
+    password: &Option<String>,
+) -> Result<FileEnvelope, SteganographyError> {
+    match carrier.open_framed_payload(password)? {
+        (PayloadKind::File, mut reader) => FileEnvelope::read_from(&mut reader),
+        _ => Err(SteganographyError::InvalidOption(
+            "The image carries a message, not a file".to_string(),
+        )),
+    }
+}
+
+/// Get the entries hidden into an image already in memory with `inject_container_into_image`
+///
+/// # Arguments
+///
+/// * `image` - The image that carries the entries
+/// * `options` - The password of the image (optional), the passwords of the entries are given
+///   to `Container::get`. The paths of the options are not used
+///
+/// # Errors
+///
+/// Returns `InvalidOption` if the image does not carry a container
+pub fn extract_container_from_image(
+    image: &DynamicImage,
+    options: &SteganographyExtractOption,
+) -> Result<Container, SteganographyError> {
+    find_container(image, &options.password)?.ok_or_else(|| {
+        SteganographyError::InvalidOption("The image does not carry a container".to_string())
+    })
+}
+
+/// Find the container of an image
+///
+/// # Returns
+/// `None` when the image does not carry any payload (or a message with the original format)
+///
+/// # Errors
+///
+/// Returns `InvalidOption` if the image carries another payload than a container
+pub(crate) fn find_container(
+    image: &DynamicImage,
+    password: &Option<String>,
+) -> Result<Option<Container>, SteganographyError> {
+    match locate_framed_payload(image, password)? {
+        Some(LocatedPayload::Framed { header, reader })
+            if header.kind == PayloadKind::Container =>
+        {
+            let mut reader = decode_payload(header, reader, password)?;
+            Ok(Some(Container::read_from(&mut reader)?))
+        }
+        Some(_) => Err(SteganographyError::InvalidOption(
+            "The image carries a message, not a container".to_string(),
+        )),
+        None => Ok(None),
+    }
+}
+
+/// Find the payload of an image and return a reader over its bytes once decrypted. A payload
+/// that is not encrypted is read directly from the pixels, the others are decrypted in memory.
+/// The reader of a file returns its content
+///
+/// # Arguments
+///
+/// * `carrier` - The image that carries the bytes, see `PayloadCarrier`
+/// * `password` - The password to decrypt the bytes (optional)
+///
+/// # Errors
+///
+/// Returns an error if no payload is found or if the password cannot decrypt the payload
+pub(crate) fn open_payload<'a, C: PayloadCarrier>(
+    carrier: &'a C,
+    password: &Option<String>,
+) -> Result<Box<dyn Read + 'a>, SteganographyError> {
+    let (kind, reader) = carrier.open_framed_payload(password)?;
+    payload_reader(kind, reader)
+}
+
+/// A reader over the bytes of a payload of any kind, the content of a file
+///
+/// # Errors
+///
+/// Returns `InvalidOption` if the payload holds several entries or a part of a message
+fn payload_reader<'a>(
+    kind: PayloadKind,
+    reader: Box<dyn Read + 'a>,
+) -> Result<Box<dyn Read + 'a>, SteganographyError> {
+    match (kind, reader) {
+        (PayloadKind::File, mut reader) => Ok(Box::new(Cursor::new(
+            FileEnvelope::read_from(&mut reader)?.content,
+        ))),
+        (PayloadKind::Bytes, reader) => Ok(reader),
+        (PayloadKind::Container, _) => Err(SteganographyError::InvalidOption(
+            "The image carries several entries, one must be extracted by its name".to_string(),
+        )),
+        (PayloadKind::Shard, _) => Err(SteganographyError::InvalidOption(
+            "The image carries a part of a message split across several images".to_string(),
+        )),
+        (PayloadKind::Share, _) => Err(SteganographyError::InvalidOption(
+            "The image carries a share of a message shared across several images".to_string(),
+        )),
+    }
+}
+
+/// Find the payload of an image and return its kind with a reader over its bytes once
+/// decrypted and decompressed. A message with the original format is a `PayloadKind::Bytes`
+pub(crate) fn open_framed_payload<'a>(
+    image: &'a DynamicImage,
+    password: &Option<String>,
+) -> Result<(PayloadKind, Box<dyn Read + 'a>), SteganographyError> {
+    match locate_payload(image, password)? {
+        LocatedPayload::Framed { header, reader } => {
+            Ok((header.kind, decode_payload(header, reader, password)?))
+        }
+        LocatedPayload::Legacy(bytes) => {
+            let message = bytes_to_message(bytes)?;
+            let bytes = decrypt_if_needed(message, password.clone())?.into_bytes();
+            Ok((PayloadKind::Bytes, Box::new(Cursor::new(bytes))))
+        }
+    }
+}
+
+/// Decrypt and decompress the payload that follows the header
+///
+/// # Arguments
+///
+/// * `header` - The header found in the image
+/// * `reader` - The reader positioned on the first byte of the payload
+/// * `password` - The password to decrypt the payload (optional)
+fn decode_payload<'a>(
+    header: PayloadHeader,
+    reader: Box<dyn Read + 'a>,
+    password: &Option<String>,
+) -> Result<Box<dyn Read + 'a>, SteganographyError> {
+    let payload: Box<dyn Read + 'a> = match (header.cipher, password) {
+        (None, _) => Box::new(PayloadLengthReader {
+            reader,
+            remaining: header.payload_length as usize,
+        }),
+        (Some(_), None) => return Err(SteganographyError::BadPassword),
+        (Some(cipher), Some(password)) => {
+            let payload = read_payload(reader, &header)?;
+            let bytes = decrypt_bytes(&payload, password, cipher, &header.to_bytes())?;
+            Box::new(Cursor::new(bytes))
+        }
+    };
+    decompress_reader(payload, header.compression, header.payload_length as usize)
+}
+
+/// Read all the bytes of the payload that follows the header
+///
+/// # Errors
+///
+/// Returns `CorruptPayload` if the image ends before the payload
+fn read_payload<R: Read>(reader: R, header: &PayloadHeader) -> Result<Vec<u8>, SteganographyError> {
+    // The length is not authenticated yet, the buffer grows with the bytes actually read
+    let mut payload = Vec::new();
+    PayloadLengthReader {
+        reader,
+        remaining: header.payload_length as usize,
+    }
+    .read_to_end(&mut payload)?;
+    Ok(payload)
+}
+
+/// Get the bytes hidden into an encoded image (E.g. the content of a PNG file received by a
+/// server), without reading any file
+///
+/// # Arguments
+///
+/// * `encoded_image` - The bytes of the image file, the format is detected from its content
+/// * `options` - The password to decrypt the bytes (optional). The path of the options is not used
+///
+/// # Errors
+///
+/// Returns an error if the image cannot be decoded, if no payload is found or if the password
+/// cannot decrypt the payload
+pub fn extract_bytes_from_encoded_image(
+    encoded_image: &[u8],
+    options: &SteganographyExtractOption,
+) -> Result<Vec<u8>, SteganographyError> {
+    if is_jpeg(encoded_image) {
+        return extract_bytes_from_jpeg(encoded_image, options);
+    }
+    if palette_format(encoded_image).is_some() {
+        return extract_bytes_from_palette_image(encoded_image, options);
+    }
+    let img = image::load_from_memory(encoded_image)?;
+    extract_bytes_from_image(&img, options)
+}
+
+/// Decode the bytes extracted from an image as a UTF-8 message
+///
+/// # Arguments
+///
+/// * `bytes` - The payload once decrypted
+///
+/// # Errors
+///
+/// Returns `CorruptPayload` if the bytes are not valid UTF-8 (E.g. a binary payload
+/// injected with `inject_bytes`)
+pub(crate) fn bytes_to_message(bytes: Vec<u8>) -> Result<String, SteganographyError> {
+    String::from_utf8(bytes).map_err(|e| {
+        SteganographyError::CorruptPayload(format!("The message is not valid UTF-8 text: {}", e))
+    })
+}
+
+/// Get an array of bytes to extract the char
+///
+/// # Arguments
+///
+/// * `new_buffer` - An array of bytes that represent the whole image. Each bytes are a part of
+///   the image colors. The buffer has the pattern [R, G, B, A, R, G, B, A, ...]
+///
+/// # Errors
+///
+/// Returns `CorruptPayload` if the end of the buffer is reached before the end of the message
+/// or if the message is not valid UTF-8
+pub fn get_message_from_buffer(new_buffer: &[u8]) -> Result<String, SteganographyError> {
+    let bytes = match find_header(
+        new_buffer,
+        NUMBER_CHANNEL_PER_PIXEL,
+        &[ChannelOrder::Sequential],
+    )? {
+        Some(LocatedPayload::Framed { header, reader }) => {
+            let payload = read_payload(reader, &header)?;
+            let length = payload.len();
+            let mut bytes = Vec::new();
+            decompress_reader(Box::new(Cursor::new(payload)), header.compression, length)?
+                .read_to_end(&mut bytes)?;
+            bytes
+        }
+        _ => get_legacy_bytes_from_buffer(new_buffer)?,
+    };
+    bytes_to_message(bytes)
+}
+
+/// Read the header from the image in the layout it was decoded with, the same layout the
+/// injection wrote into. When no header is found, the image is read with the original format
+/// where the message ends with `EOF_CHAR`
+///
+/// # Arguments
+///
+/// * `img` - The image that carries the message
+/// * `password` - The password that may have scattered the message
+///
+fn locate_payload<'a>(
+    img: &'a DynamicImage,
+    password: &Option<String>,
+) -> Result<LocatedPayload<'a>, SteganographyError> {
+    match locate_framed_payload(img, password)? {
+        Some(located) => Ok(located),
+        None => Ok(LocatedPayload::Legacy(get_legacy_bytes_from_buffer(
+            img.to_rgba8().as_raw(),
+        )?)),
+    }
+}
+
+/// Read the header from the image in the layout it was decoded with
+///
+/// # Returns
+/// `None` when no header is found, the image carries no payload or one with the original format
+fn locate_framed_payload<'a>(
+    img: &'a DynamicImage,
+    password: &Option<String>,
+) -> Result<Option<LocatedPayload<'a>>, SteganographyError> {
+    match find_header_in_image(img, &password_orders(password)?)? {
+        Some(located) => Ok(Some(located)),
+        // The ranking of the pixels is only computed when the other orders find nothing
+        None => find_header_in_image(img, &[adaptive_order(img)]),
+    }
+}
+
+/// The orders a payload may have been written in with or without a password, the scattered
+/// order first
+fn password_orders(password: &Option<String>) -> Result<Vec<ChannelOrder>, SteganographyError> {
+    let mut orders = Vec::new();
+    if let Some(p) = password {
+        orders.push(ChannelOrder::Scattered(derive_seed(p)?));
+    }
+    orders.push(ChannelOrder::Sequential);
+    Ok(orders)
+}
+
+/// Read the header from the image in the layout it was decoded with, in each of the orders
+fn find_header_in_image<'a>(
+    img: &'a DynamicImage,
+    orders: &[ChannelOrder],
+) -> Result<Option<LocatedPayload<'a>>, SteganographyError> {
+    let channel_count = carrier_channel_count(img);
+    Ok(match img {
+        DynamicImage::ImageLuma8(buffer) => find_header(buffer.as_raw(), channel_count, orders)?,
+        DynamicImage::ImageLumaA8(buffer) => find_header(buffer.as_raw(), channel_count, orders)?,
+        DynamicImage::ImageRgb8(buffer) => find_header(buffer.as_raw(), channel_count, orders)?,
+        DynamicImage::ImageRgba8(buffer) => find_header(buffer.as_raw(), channel_count, orders)?,
+        DynamicImage::ImageLuma16(buffer) => find_header(buffer.as_raw(), channel_count, orders)?,
+        DynamicImage::ImageLumaA16(buffer) => find_header(buffer.as_raw(), channel_count, orders)?,
+        DynamicImage::ImageRgb16(buffer) => find_header(buffer.as_raw(), channel_count, orders)?,
+        DynamicImage::ImageRgba16(buffer) => find_header(buffer.as_raw(), channel_count, orders)?,
+        other => {
+            // The converted image does not outlive this function, the payload is read now
+            let buffer = other.to_rgba8();
+            let found = find_header(buffer.as_raw(), channel_count, orders)?;
+            match found {
+                Some(LocatedPayload::Framed { header, reader }) => {
+                    let payload = read_payload(reader, &header)?;
+                    Some(LocatedPayload::Framed {
+                        header,
+                        reader: Box::new(Cursor::new(payload)),
+                    })
+                }
+                _ => None,
+            }
+        }
+    })
+}
+
+/// Look for the header with every order, every combination of channels and every number of bits
+/// per channel
+///
+/// # Arguments
+///
+/// * `samples` - The channels of the whole image (E.g. [R, G, B, R, G, B, ...])
+/// * `channel_count` - Number of channels of each pixel
+/// * `orders` - The orders the channels may have received the message in
+///
+/// # Returns
+/// The framed payload with its reader positioned on the first byte of the payload, `None` when
+/// no header is found
+fn find_header<'a, S: Sample + 'a>(
+    samples: &'a [S],
+    channel_count: u8,
+    orders: &[ChannelOrder],
+) -> Result<Option<LocatedPayload<'a>>, SteganographyError> {
+    // From all channels to a single one
+    let masks = (1..=ChannelMask::RGBA.bits())
+        .rev()
+        .filter_map(ChannelMask::from_bits)
+        .filter(|mask| mask.sample_count(channel_count) > 0)
+        .collect::<Vec<ChannelMask>>();
+    for order in orders {
+        for channels in masks.iter().copied() {
+            for bits_per_channel in 1..=MAX_BITS_PER_CHANNEL {
+                let mut reader = ChannelReader::new(
+                    samples,
+                    order.positions(samples.len(), channels, channel_count),
+                    bits_per_channel,
+                );
+                match PayloadHeader::read_from(&mut reader)? {
+                    // The header tells how it was written, it must match how it was read
+                    Some(header)
+                        if header.bits_per_channel == bits_per_channel
+                            && header.channels == channels =>
+                    {
+                        // The length comes from the image, it bounds the decompressed payload
+                        let carriers = samples.len() / channel_count as usize
+                            * channels.sample_count(channel_count) as usize;
+                        let available = (carriers * bits_per_channel as usize
+                            / NUMBER_BIT_PER_BYTE as usize)
+                            .saturating_sub(header.length());
+                        if header.payload_length as usize > available {
+                            return Err(SteganographyError::CorruptPayload(
+                                "The length of the message is larger than the image".to_string(),
+                            ));
+                        }
+                        if let Some(matrix_bits) = header.matrix_bits {
+                            reader.use_matrix_bits(matrix_bits);
+                        }
+                        return Ok(Some(LocatedPayload::Framed {
+                            header,
+                            reader: Box::new(reader),
+                        }));
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// Read the bytes of an image injected before the header existed: the message is
+/// terminated by `EOF_CHAR`
+///
+/// # Arguments
+///
+/// * `new_buffer` - An array of bytes that represent the whole image
+///
+/// # Errors
+///
+/// Returns `CorruptPayload` if the end of the buffer is reached before the end of file character
+fn get_legacy_bytes_from_buffer(new_buffer: &[u8]) -> Result<Vec<u8>, SteganographyError> {
+    let mut result = Vec::new();
+    let mut data_position = 0;
+    let mut last_character = 0 as char;
+    let mut bit_counter = 0;
+    let mut bits = String::new();
+    while last_character != EOF_CHAR {
+        if data_position + NUMBER_BIT_PER_BYTE as usize > new_buffer.len() {
+            return Err(SteganographyError::CorruptPayload(
+                "No end of message found in the image".to_string(),
+            ));
+        }
+        while bit_counter < NUMBER_BIT_PER_BYTE {
+            let rgba_color = new_buffer[data_position];
+            let bit = unpack_bit(rgba_color);
+            bits.push_str(if bit == 0 { "0" } else { "1" });
+            data_position += 1;
+            bit_counter += 1;
+        }
+        last_character = binary_string_to_char(bits);
+        if last_character != EOF_CHAR {
+            result.push(last_character as u8);
+        }
+        bit_counter = 0;
+        bits = "".to_string();
+    }
+
+    Ok(result)
+}
+#[cfg(test)]
+mod test_get_string {
+    use super::*;
+    use crate::utils::binary::char_to_binary_string;
+    use crate::utils::bitstream::channel_positions;
+    use crate::utils::options::Compression;
+    use crate::utils::palette::test_palette::{
+        animated_gif, animated_png, palette_gif, palette_png,
+    };
+
+    #[test]
+    fn test_add_message_to_image() {
+        let options = SteganographyInjectOption {
+            input_image_path: "testAssets/prestine.png".to_string(),
+            message: "Bye".to_string(),
+            output_image_path: "testAssets/out.png".to_string(),
+            password: None,
+            ..Default::default()
+        };
+        add_message_to_image(options).unwrap();
+    }
+
+    #[test]
+    fn test_add_message_to_image_missing_input() {
+        let options = SteganographyInjectOption {
+            input_image_path: "testAssets/does_not_exist.png".to_string(),
+            message: "Bye".to_string(),
+            output_image_path: "testAssets/out.png".to_string(),
+            password: None,
+            ..Default::default()
+        };
+        let result = add_message_to_image(options);
+        assert!(matches!(result, Err(SteganographyError::Io(_))));
+    }
+
+    #[test]
+    fn test_add_message_to_image_too_long() {
+        let options = SteganographyInjectOption {
+            input_image_path: "testAssets/prestine.png".to_string(),
+            message: "a".repeat(100_000),
+            output_image_path: "testAssets/out.png".to_string(),
+            password: None,
+            ..Default::default()
+        };
+        let result = add_message_to_image(options);
+        assert!(matches!(
+            result,
+            Err(SteganographyError::Capacity {
+                required: 100_000,
+                available: 24_565
+            })
+        ));
+    }
+
+    #[test]
+    fn test_capacity_without_password() {
+        let img = DynamicImage::new_rgba8(8, 8);
+        let options = SteganographyCapacityOption {
+            input_image_path: "".to_string(),
+            password: None,
+            ..Default::default()
+        };
+        assert_eq!(capacity(&img, &options), 21);
+    }
+
+    #[test]
+    fn test_capacity_with_password() {
+        let img = DynamicImage::new_rgba8(16, 16);
+        let options = SteganographyCapacityOption {
+            input_image_path: "".to_string(),
+            password: Some("secret".to_string()),
+            ..Default::default()
+        };
+        // 128 bytes minus the header (11), the cipher (38) and the authentication tag (16)
+        assert_eq!(capacity(&img, &options), 63);
+    }
+
+    #[test]
+    fn test_capacity_too_small_for_password() {
+        let img = DynamicImage::new_rgba8(2, 2);
+        let options = SteganographyCapacityOption {
+            input_image_path: "".to_string(),
+            password: Some("secret".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(capacity(&img, &options), 0);
+    }
+
+    #[test]
+    fn test_get_capacity_of_image() {
+        let options = SteganographyCapacityOption {
+            input_image_path: "testAssets/prestine.png".to_string(),
+            password: None,
+            ..Default::default()
+        };
+        assert_eq!(get_capacity_of_image(options).unwrap(), 24_565);
+    }
+
+    #[test]
+    fn test_inject_extract_bytes_with_end_of_file_byte() {
+        let payload = vec![0u8, 4, 4, 255, 10, 4, 0];
+        let options = SteganographyInjectOption {
+            input_image_path: "testAssets/prestine.png".to_string(),
+            message: "".to_string(),
+            output_image_path: "testAssets/delete_me_bytes.png".to_string(),
+            password: None,
+            ..Default::default()
+        };
+        inject_bytes(&payload, options).unwrap();
+        let options = SteganographyExtractOption {
+            input_image_path: "testAssets/delete_me_bytes.png".to_string(),
+            password: None,
+            ..Default::default()
+        };
+        assert_eq!(extract_bytes(options).unwrap(), payload);
+    }
+
+    #[test]
+    fn test_inject_extract_bytes_with_password() {
+        let payload: Vec<u8> = (0..=255).collect();
+        let options = SteganographyInjectOption {
+            input_image_path: "testAssets/prestine.png".to_string(),
+            message: "".to_string(),
+            output_image_path: "testAssets/delete_me_bytes_password.png".to_string(),
+            password: Some("secret".to_string()),
+            ..Default::default()
+        };
+        inject_bytes(&payload, options).unwrap();
+        let options = SteganographyExtractOption {
+            input_image_path: "testAssets/delete_me_bytes_password.png".to_string(),
+            password: None,
+            ..Default::default()
+        };
+        assert!(matches!(
+            extract_bytes(options),
+            Err(SteganographyError::BadPassword)
+        ));
+        let options = SteganographyExtractOption {
+            input_image_path: "testAssets/delete_me_bytes_password.png".to_string(),
+            password: Some("secret".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(extract_bytes(options).unwrap(), payload);
+    }
+
+    #[test]
+    fn test_get_message_from_image() {
+        let options = SteganographyExtractOption {
+            input_image_path: "testAssets/out_message_Bye.png".to_string(),
+            password: None,
+            ..Default::default()
+        };
+        let message = get_message_from_image(options).unwrap();
+        assert_eq!(message, "Bye".to_string());
+    }
+
+    #[test]
+    fn test_get_message_from_buffer() {
+        let buffer = [
+            // Letter B
+            binary_string_to_char("00000000".to_string()) as u8,
+            binary_string_to_char("00000001".to_string()) as u8,
+            binary_string_to_char("00000000".to_string()) as u8,
+            binary_string_to_char("00000000".to_string()) as u8,
+            binary_string_to_char("00000000".to_string()) as u8,
+            binary_string_to_char("00000000".to_string()) as u8,
+            binary_string_to_char("00000001".to_string()) as u8,
+            binary_string_to_char("00000000".to_string()) as u8,
+            // End of file Character
+            binary_string_to_char("00000000".to_string()) as u8,
+            binary_string_to_char("00000000".to_string()) as u8,
+            binary_string_to_char("00000000".to_string()) as u8,
+            binary_string_to_char("00000000".to_string()) as u8,
+            binary_string_to_char("00000000".to_string()) as u8,
+            binary_string_to_char("00000001".to_string()) as u8,
+            binary_string_to_char("00000000".to_string()) as u8,
+            binary_string_to_char("00000000".to_string()) as u8,
+        ];
+        let message = get_message_from_buffer(&buffer).unwrap();
+        assert_eq!(message, "B".to_string());
+    }
+
+    #[test]
+    fn test_extract_bytes_tampered_image() {
+        let options = SteganographyInjectOption {
+            input_image_path: "testAssets/prestine.png".to_string(),
+            message: "".to_string(),
+            output_image_path: "testAssets/delete_me_tampered.png".to_string(),
+            password: Some("secret".to_string()),
+            ..Default::default()
+        };
+        inject_bytes(b"Meet at noon", options).unwrap();
+        let mut img = image::open("testAssets/delete_me_tampered.png")
+            .unwrap()
+            .to_rgba8();
+        // Flip one bit of the encrypted payload, after the 48 bytes (96 pixels) of header
+        let pixel = img.get_pixel_mut(100, 0);
+        pixel[0] ^= 1;
+        img.save("testAssets/delete_me_tampered.png").unwrap();
+        let options = SteganographyExtractOption {
+            input_image_path: "testAssets/delete_me_tampered.png".to_string(),
+            password: Some("secret".to_string()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            extract_bytes(options),
+            Err(SteganographyError::BadPassword)
+        ));
+    }
+
+    #[test]
+    fn test_inject_extract_scattered() {
+        let options = SteganographyInjectOption {
+            input_image_path: "testAssets/prestine.png".to_string(),
+            message: "Scattered across the image".to_string(),
+            output_image_path: "testAssets/delete_me_scattered.png".to_string(),
+            password: Some("secret".to_string()),
+            order: EmbeddingOrder::Scattered,
+            ..Default::default()
+        };
+        add_message_to_image(options).unwrap();
+        let img = image::open("testAssets/delete_me_scattered.png").unwrap();
+        // Nothing is at the top left of the image
+        assert!(PayloadHeader::read_from(&mut ChannelReader::new(
+            img.as_bytes(),
+            channel_positions(img.as_bytes().len(), None, ChannelMask::RGBA, 4),
+            1
+        ))
+        .unwrap()
+        .is_none());
+        let options = SteganographyExtractOption {
+            input_image_path: "testAssets/delete_me_scattered.png".to_string(),
+            password: Some("secret".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            get_message_from_image(options).unwrap(),
+            "Scattered across the image"
+        );
+    }
+
+    #[test]
+    fn test_inject_scattered_without_password() {
+        let options = SteganographyInjectOption {
+            input_image_path: "testAssets/prestine.png".to_string(),
+            message: "Bye".to_string(),
+            output_image_path: "testAssets/out.png".to_string(),
+            password: None,
+            order: EmbeddingOrder::Scattered,
+            ..Default::default()
+        };
+        assert!(matches!(
+            add_message_to_image(options),
+            Err(SteganographyError::InvalidOption(_))
+        ));
+    }
+
+    #[test]
+    fn test_inject_extract_three_bits_per_channel() {
+        let message = "A".repeat(50_000);
+        let options = SteganographyInjectOption {
+            input_image_path: "testAssets/prestine.png".to_string(),
+            message: message.clone(),
+            output_image_path: "testAssets/delete_me_three_bits.png".to_string(),
+            password: None,
+            bits_per_channel: 3,
+            ..Default::default()
+        };
+        add_message_to_image(options).unwrap();
+        let options = SteganographyExtractOption {
+            input_image_path: "testAssets/delete_me_three_bits.png".to_string(),
+            password: None,
+            ..Default::default()
+        };
+        assert_eq!(get_message_from_image(options).unwrap(), message);
+    }
+
+    #[test]
+    fn test_inject_extract_scattered_four_bits_per_channel() {
+        let options = SteganographyInjectOption {
+            input_image_path: "testAssets/prestine.png".to_string(),
+            message: "Deep".to_string(),
+            output_image_path: "testAssets/delete_me_scattered_four_bits.png".to_string(),
+            password: Some("secret".to_string()),
+            order: EmbeddingOrder::Scattered,
+            bits_per_channel: 4,
+            ..Default::default()
+        };
+        add_message_to_image(options).unwrap();
+        let options = SteganographyExtractOption {
+            input_image_path: "testAssets/delete_me_scattered_four_bits.png".to_string(),
+            password: Some("secret".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(get_message_from_image(options).unwrap(), "Deep");
+    }
+
+    #[test]
+    fn test_inject_extract_without_alpha() {
+        let options = SteganographyInjectOption {
+            input_image_path: "testAssets/prestine.png".to_string(),
+            message: "Opaque".to_string(),
+            output_image_path: "testAssets/delete_me_without_alpha.png".to_string(),
+            password: Some("secret".to_string()),
+            channels: ChannelMask::RGB,
+            bits_per_channel: 2,
+            ..Default::default()
+        };
+        add_message_to_image(options).unwrap();
+        let original = image::open("testAssets/prestine.png").unwrap().to_rgba8();
+        let img = image::open("testAssets/delete_me_without_alpha.png")
+            .unwrap()
+            .to_rgba8();
+        assert!(original
+            .pixels()
+            .zip(img.pixels())
+            .all(|(before, after)| before[3] == after[3]));
+        let options = SteganographyExtractOption {
+            input_image_path: "testAssets/delete_me_without_alpha.png".to_string(),
+            password: Some("secret".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(get_message_from_image(options).unwrap(), "Opaque");
+    }
+
+    #[test]
+    fn test_inject_extract_blue_scattered() {
+        let options = SteganographyInjectOption {
+            input_image_path: "testAssets/prestine.png".to_string(),
+            message: "Blue".to_string(),
+            output_image_path: "testAssets/delete_me_blue.png".to_string(),
+            password: Some("secret".to_string()),
+            order: EmbeddingOrder::Scattered,
+            channels: "b".parse().unwrap(),
+            ..Default::default()
+        };
+        add_message_to_image(options).unwrap();
+        let original = image::open("testAssets/prestine.png").unwrap().to_rgba8();
+        let img = image::open("testAssets/delete_me_blue.png")
+            .unwrap()
+            .to_rgba8();
+        assert!(original.pixels().zip(img.pixels()).all(|(before, after)| {
+            before[0] == after[0] && before[1] == after[1] && before[3] == after[3]
+        }));
+        let options = SteganographyExtractOption {
+            input_image_path: "testAssets/delete_me_blue.png".to_string(),
+            password: Some("secret".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(get_message_from_image(options).unwrap(), "Blue");
+    }
+
+    #[test]
+    fn test_capacity_without_alpha() {
+        let img = DynamicImage::new_rgba8(8, 8);
+        let options = SteganographyCapacityOption {
+            channels: ChannelMask::RGB,
+            ..Default::default()
+        };
+        // 64 pixels x 3 channels = 24 bytes minus the header (11)
+        assert_eq!(capacity(&img, &options), 13);
+    }
+
+    /// Inject into a copy of the prestine image saved with another color type and extract back
+    fn round_trip_color_type(source: DynamicImage, name: &str, bits_per_channel: u8) {
+        let source_path = format!("testAssets/delete_me_source_{}.png", name);
+        let output_path = format!("testAssets/delete_me_output_{}.png", name);
+        source.save(&source_path).unwrap();
+        let options = SteganographyInjectOption {
+            input_image_path: source_path,
+            message: format!("Hidden in {}", name),
+            output_image_path: output_path.clone(),
+            password: Some("secret".to_string()),
+            bits_per_channel,
+            ..Default::default()
+        };
+        add_message_to_image(options).unwrap();
+        let output = image::open(&output_path).unwrap();
+        assert_eq!(output.color(), source.color());
+        let options = SteganographyExtractOption {
+            input_image_path: output_path,
+            password: Some("secret".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            get_message_from_image(options).unwrap(),
+            format!("Hidden in {}", name)
+        );
+    }
+
+    #[test]
+    fn test_inject_extract_luma8() {
+        let img = image::open("testAssets/prestine.png").unwrap();
+        round_trip_color_type(DynamicImage::ImageLuma8(img.to_luma8()), "luma8", 1);
+    }
+
+    #[test]
+    fn test_inject_extract_luma_alpha8() {
+        let img = image::open("testAssets/prestine.png").unwrap();
+        round_trip_color_type(DynamicImage::ImageLumaA8(img.to_luma_alpha8()), "lumaa8", 2);
+    }
+
+    #[test]
+    fn test_inject_extract_rgb8() {
+        let img = image::open("testAssets/prestine.png").unwrap();
+        round_trip_color_type(DynamicImage::ImageRgb8(img.to_rgb8()), "rgb8", 1);
+    }
+
+    #[test]
+    fn test_inject_extract_rgb16() {
+        let img = image::open("testAssets/prestine.png").unwrap();
+        round_trip_color_type(DynamicImage::ImageRgb16(img.to_rgb16()), "rgb16", 3);
+    }
+
+    #[test]
+    fn test_inject_extract_rgba16() {
+        let img = image::open("testAssets/prestine.png").unwrap();
+        round_trip_color_type(DynamicImage::ImageRgba16(img.to_rgba16()), "rgba16", 1);
+    }
+
+    #[test]
+    fn test_inject_alpha_into_rgb() {
+        let img = image::open("testAssets/prestine.png").unwrap();
+        DynamicImage::ImageRgb8(img.to_rgb8())
+            .save("testAssets/delete_me_source_rgb8_alpha.png")
+            .unwrap();
+        let options = SteganographyInjectOption {
+            input_image_path: "testAssets/delete_me_source_rgb8_alpha.png".to_string(),
+            message: "Bye".to_string(),
+            output_image_path: "testAssets/out.png".to_string(),
+            channels: "a".parse().unwrap(),
+            ..Default::default()
+        };
+        assert!(matches!(
+            add_message_to_image(options),
+            Err(SteganographyError::InvalidOption(_))
+        ));
+    }
+
+    #[test]
+    fn test_capacity_grayscale() {
+        let img = DynamicImage::new_luma8(8, 8);
+        let options = SteganographyCapacityOption::default();
+        // 64 pixels x 1 channel = 8 bytes, too small for the header (11)
+        assert_eq!(capacity(&img, &options), 0);
+        let img = DynamicImage::new_rgb16(8, 8);
+        // 64 pixels x 3 channels = 24 bytes minus the header (11)
+        assert_eq!(capacity(&img, &options), 13);
+    }
+
+    #[test]
+    fn test_inject_extract_in_memory() {
+        let img = DynamicImage::ImageRgb8(image::RgbImage::new(32, 32));
+        let options = SteganographyInjectOption {
+            password: Some("secret".to_string()),
+            order: EmbeddingOrder::Scattered,
+            ..Default::default()
+        };
+        let new_img = inject_bytes_into_image(&img, &[1, 2, 3], &options).unwrap();
+        assert_eq!(new_img.color(), img.color());
+        assert_ne!(new_img, img);
+        let options = SteganographyExtractOption {
+            input_image_path: "".to_string(),
+            password: Some("secret".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            extract_bytes_from_image(&new_img, &options).unwrap(),
+            vec![1, 2, 3]
+        );
+    }
+
+    fn round_trip_compression(compression: Compression, password: Option<String>) {
+        // 64x64 RGBA holds 2037 bytes without compression
+        let img = DynamicImage::new_rgba8(64, 64);
+        let payload = "Compressed payload. ".repeat(200).into_bytes();
+        let options = SteganographyInjectOption {
+            password: password.clone(),
+            compression,
+            ..Default::default()
+        };
+        let new_img = inject_bytes_into_image(&img, &payload, &options).unwrap();
+        let options = SteganographyExtractOption {
+            password,
+            ..Default::default()
+        };
+        assert_eq!(
+            extract_bytes_from_image(&new_img, &options).unwrap(),
+            payload
+        );
+    }
+
+    #[test]
+    fn test_inject_extract_deflate() {
+        round_trip_compression(Compression::Deflate, None);
+    }
+
+    #[test]
+    fn test_inject_extract_zstd_encrypted() {
+        round_trip_compression(Compression::Zstd, Some("secret".to_string()));
+    }
+
+    #[test]
+    fn test_inject_extract_brotli() {
+        round_trip_compression(Compression::Brotli, None);
+    }
+
+    #[test]
+    fn test_inject_extract_matching() {
+        let img = image::open("testAssets/prestine.png").unwrap();
+        let options = SteganographyInjectOption {
+            password: Some("secret".to_string()),
+            method: EmbeddingMethod::Matching,
+            order: EmbeddingOrder::Scattered,
+            ..Default::default()
+        };
+        let new_img = inject_bytes_into_image(&img, b"Matched", &options).unwrap();
+        // Every channel moved by 1 at most, up or down
+        let changes: Vec<i16> = img
+            .to_rgba8()
+            .as_raw()
+            .iter()
+            .zip(new_img.to_rgba8().as_raw())
+            .map(|(before, after)| *after as i16 - *before as i16)
+            .filter(|change| *change != 0)
+            .collect();
+        assert!(changes.iter().all(|change| change.abs() == 1));
+        assert!(changes.contains(&1) && changes.contains(&-1));
+        let options = SteganographyExtractOption {
+            password: Some("secret".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            extract_bytes_from_image(&new_img, &options).unwrap(),
+            b"Matched"
+        );
+    }
+
+    /// Number of channels that differ between two images
+    fn changed_channels(before: &DynamicImage, after: &DynamicImage) -> usize {
+        before
+            .to_rgba8()
+            .as_raw()
+            .iter()
+            .zip(after.to_rgba8().as_raw())
+            .filter(|(b, a)| b != a)
+            .count()
+    }
+
+    #[test]
+    fn test_inject_extract_matrix() {
+        let img = image::open("testAssets/prestine.png").unwrap();
+        let payload = "Few changes. ".repeat(20).into_bytes();
+        let options = SteganographyInjectOption {
+            password: Some("secret".to_string()),
+            order: EmbeddingOrder::Scattered,
+            ..Default::default()
+        };
+        let direct = inject_bytes_into_image(&img, &payload, &options).unwrap();
+        let options = SteganographyInjectOption {
+            matrix_embedding: true,
+            ..options
+        };
+        let matrix = inject_bytes_into_image(&img, &payload, &options).unwrap();
+        assert!(changed_channels(&img, &matrix) * 2 < changed_channels(&img, &direct));
+        let options = SteganographyExtractOption {
+            password: Some("secret".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            extract_bytes_from_image(&matrix, &options).unwrap(),
+            payload
+        );
+    }
+
+    #[test]
+    fn test_inject_extract_matrix_full_image() {
+        // 64x64 RGBA holds 2037 bytes, 2036 once the matrix byte is in the header: one bit
+        // per group of one channel
+        let img = DynamicImage::new_rgba8(64, 64);
+        let options = SteganographyInjectOption {
+            matrix_embedding: true,
+            method: EmbeddingMethod::Matching,
+            ..Default::default()
+        };
+        let payload = vec![0xA5u8; 2036];
+        let new_img = inject_bytes_into_image(&img, &payload, &options).unwrap();
+        assert_eq!(
+            extract_bytes_from_image(&new_img, &SteganographyExtractOption::default()).unwrap(),
+            payload
+        );
+        let result = inject_bytes_into_image(&img, &[0u8; 2037], &options);
+        assert!(matches!(
+            result,
+            Err(SteganographyError::Capacity {
+                required: 2037,
+                available: 2036
+            })
+        ));
+    }
+
+    #[test]
+    fn test_inject_matrix_several_bits_per_channel() {
+        let options = SteganographyInjectOption {
+            matrix_embedding: true,
+            bits_per_channel: 2,
+            ..Default::default()
+        };
+        let result = inject_bytes_into_image(&DynamicImage::new_rgba8(8, 8), b"A", &options);
+        assert!(matches!(result, Err(SteganographyError::InvalidOption(_))));
+    }
+
+    #[test]
+    fn test_inject_extract_adaptive() {
+        // Flat on the top half, noisy on the bottom half
+        let img = DynamicImage::ImageRgb8(image::RgbImage::from_fn(32, 32, |x, y| {
+            if y < 16 {
+                image::Rgb([120, 120, 120])
+            } else {
+                let v = ((x * 97 + y * 31) % 256) as u8;
+                image::Rgb([v, 255 - v, v / 2])
+            }
+        }));
+        let options = SteganographyInjectOption {
+            order: EmbeddingOrder::Adaptive,
+            ..Default::default()
+        };
+        let new_img = inject_bytes_into_image(&img, b"Hidden in the noise", &options).unwrap();
+        let before = img.to_rgb8();
+        let after = new_img.to_rgb8();
+        // The flat half is untouched, its last row touches the noisy half
+        assert_eq!(
+            before.as_raw()[..15 * 32 * 3],
+            after.as_raw()[..15 * 32 * 3]
+        );
+        assert_ne!(before, after);
+        assert_eq!(
+            extract_bytes_from_image(&new_img, &SteganographyExtractOption::default()).unwrap(),
+            b"Hidden in the noise"
+        );
+    }
+
+    #[test]
+    fn test_inject_adaptive_matching() {
+        let options = SteganographyInjectOption {
+            order: EmbeddingOrder::Adaptive,
+            method: EmbeddingMethod::Matching,
+            ..Default::default()
+        };
+        let result = inject_bytes_into_image(&DynamicImage::new_rgba8(8, 8), b"A", &options);
+        assert!(matches!(result, Err(SteganographyError::InvalidOption(_))));
+    }
+
+    #[test]
+    fn test_inject_without_compression_too_large() {
+        let img = DynamicImage::new_rgba8(64, 64);
+        let payload = "Compressed payload. ".repeat(200).into_bytes();
+        let result = inject_bytes_into_image(&img, &payload, &SteganographyInjectOption::default());
+        assert!(matches!(
+            result,
+            Err(SteganographyError::Capacity {
+                required: 4000,
+                available: 2037
+            })
+        ));
+    }
+
+    #[test]
+    fn test_inject_extract_file_in_memory() {
+        let img = DynamicImage::new_rgb8(32, 32);
+        let envelope = FileEnvelope::new("notes.txt".to_string(), b"Notes".to_vec(), 1);
+        let options = SteganographyInjectOption {
+            compression: Compression::Deflate,
+            ..Default::default()
+        };
+        let new_img = inject_file_into_image(&img, &envelope, &options).unwrap();
+        let options = SteganographyExtractOption::default();
+        assert_eq!(
+            extract_file_from_image(&new_img, &options).unwrap(),
+            envelope
+        );
+        assert_eq!(
+            extract_bytes_from_image(&new_img, &options).unwrap(),
+            b"Notes".to_vec()
+        );
+    }
+
+    #[test]
+    fn test_extract_file_from_message() {
+        let img = DynamicImage::new_rgb8(32, 32);
+        let options = SteganographyInjectOption::default();
+        let new_img = inject_bytes_into_image(&img, b"Notes", &options).unwrap();
+        let result = extract_file_from_image(&new_img, &SteganographyExtractOption::default());
+        assert!(matches!(result, Err(SteganographyError::InvalidOption(_))));
+    }
+
+    #[test]
+    fn test_inject_extract_container_in_memory() {
+        let img = DynamicImage::new_rgb8(32, 32);
+        let mut container = Container::new();
+        container.add("note", b"Note", &None).unwrap();
+        container.add("key", b"Key", &None).unwrap();
+        let options = SteganographyInjectOption::default();
+        let new_img = inject_container_into_image(&img, &container, &options).unwrap();
+        let options = SteganographyExtractOption::default();
+        let result = extract_container_from_image(&new_img, &options).unwrap();
+        assert_eq!(result.get("key", &None).unwrap(), b"Key".to_vec());
+        assert!(matches!(
+            extract_bytes_from_image(&new_img, &options),
+            Err(SteganographyError::InvalidOption(_))
+        ));
+    }
+
+    #[test]
+    fn test_inject_extract_encoded_image() {
+        let png = std::fs::read("testAssets/prestine.png").unwrap();
+        let options = SteganographyInjectOption::default();
+        let output =
+            inject_bytes_into_encoded_image(&png, b"Encoded", &options, ImageFormat::Png).unwrap();
+        assert_eq!(image::guess_format(&output).unwrap(), ImageFormat::Png);
+        let options = SteganographyExtractOption {
+            input_image_path: "".to_string(),
+            password: None,
+            ..Default::default()
+        };
+        assert_eq!(
+            extract_bytes_from_encoded_image(&output, &options).unwrap(),
+            b"Encoded".to_vec()
+        );
+    }
+
+    #[test]
+    fn test_extract_from_invalid_encoded_image() {
+        let options = SteganographyExtractOption {
+            input_image_path: "".to_string(),
+            password: None,
+            ..Default::default()
+        };
+        assert!(extract_bytes_from_encoded_image(b"not an image", &options).is_err());
+    }
+
+    #[test]
+    fn test_inject_unsupported_bits_per_channel() {
+        let options = SteganographyInjectOption {
+            input_image_path: "testAssets/prestine.png".to_string(),
+            message: "Bye".to_string(),
+            output_image_path: "testAssets/out.png".to_string(),
+            bits_per_channel: 5,
+            ..Default::default()
+        };
+        assert!(matches!(
+            add_message_to_image(options),
+            Err(SteganographyError::InvalidOption(_))
+        ));
+    }
+
+    #[test]
+    fn test_capacity_with_bits_per_channel() {
+        let img = DynamicImage::new_rgba8(8, 8);
+        let options = SteganographyCapacityOption {
+            input_image_path: "".to_string(),
+            password: None,
+            bits_per_channel: 4,
+            channels: ChannelMask::RGBA,
+        };
+        // 64 pixels x 4 channels x 4 bits = 128 bytes minus the header (11)
+        assert_eq!(capacity(&img, &options), 117);
+        let options = SteganographyCapacityOption {
+            bits_per_channel: 0,
+            ..options
+        };
+        assert_eq!(capacity(&img, &options), 0);
+    }
+
+    #[test]
+    fn test_get_message_from_buffer_with_header() {
+        let header = PayloadHeader {
+            cipher: None,
+            bits_per_channel: 1,
+            channels: ChannelMask::RGBA,
+            compression: Compression::None,
+            kind: PayloadKind::Bytes,
+            payload_length: 1,
+            matrix_bits: None,
+        };
+        let mut bytes = header.to_bytes().to_vec();
+        bytes.push(b'B');
+        let buffer: Vec<u8> = bytes
+            .iter()
+            .flat_map(|b| char_to_binary_string(b).into_bytes())
+            .map(|c| u8::from(c != b'0'))
+            .collect();
+        let message = get_message_from_buffer(&buffer).unwrap();
+        assert_eq!(message, "B".to_string());
+    }
+
+    #[test]
+    fn test_extract_bytes_forged_length() {
+        let header = PayloadHeader {
+            cipher: None,
+            bits_per_channel: 1,
+            channels: ChannelMask::RGBA,
+            compression: Compression::Zstd,
+            kind: PayloadKind::Bytes,
+            payload_length: u32::MAX,
+            matrix_bits: None,
+        };
+        let mut buffer: Vec<u8> = header
+            .to_bytes()
+            .iter()
+            .flat_map(|b| char_to_binary_string(b).into_bytes())
+            .map(|c| u8::from(c != b'0'))
+            .collect();
+        buffer.resize(8 * 8 * 4, 0);
+        let img = DynamicImage::ImageRgba8(ImageBuffer::from_raw(8, 8, buffer).unwrap());
+        let result = extract_bytes_from_image(&img, &SteganographyExtractOption::default());
+        assert!(matches!(
+            result,
+            Err(SteganographyError::CorruptPayload(reason)) if reason.contains("larger than the image")
+        ));
+    }
+
+    #[test]
+    fn test_get_message_from_buffer_invalid_utf8() {
+        let header = PayloadHeader {
+            cipher: None,
+            bits_per_channel: 1,
+            channels: ChannelMask::RGBA,
+            compression: Compression::None,
+            kind: PayloadKind::Bytes,
+            payload_length: 1,
+            matrix_bits: None,
+        };
+        let mut bytes = header.to_bytes().to_vec();
+        bytes.push(0xE9); // "é" in Latin-1, not a valid UTF-8 sequence alone
+        let buffer: Vec<u8> = bytes
+            .iter()
+            .flat_map(|b| char_to_binary_string(b).into_bytes())
+            .map(|c| u8::from(c != b'0'))
+            .collect();
+        let message = get_message_from_buffer(&buffer);
+        assert!(matches!(
+            message,
+            Err(SteganographyError::CorruptPayload(_))
+        ));
+    }
+
+    #[test]
+    fn test_message_non_ascii_round_trip() {
+        let message = "Café, 東京, ñandú 🦀".to_string();
+        let options = SteganographyInjectOption {
+            input_image_path: "testAssets/prestine.png".to_string(),
+            message: message.clone(),
+            output_image_path: "testAssets/delete_me_utf8.png".to_string(),
+            password: None,
+            ..Default::default()
+        };
+        add_message_to_image(options).unwrap();
+        let options = SteganographyExtractOption {
+            input_image_path: "testAssets/delete_me_utf8.png".to_string(),
+            password: None,
+            ..Default::default()
+        };
+        assert_eq!(get_message_from_image(options).unwrap(), message);
+    }
+
+    #[test]
+    fn test_message_non_ascii_round_trip_with_password() {
+        let message = "Ça va? Привет мир 👋".to_string();
+        let options = SteganographyInjectOption {
+            input_image_path: "testAssets/prestine.png".to_string(),
+            message: message.clone(),
+            output_image_path: "testAssets/delete_me_utf8_password.png".to_string(),
+            password: Some("Mot de passe".to_string()),
+            ..Default::default()
+        };
+        add_message_to_image(options).unwrap();
+        let options = SteganographyExtractOption {
+            input_image_path: "testAssets/delete_me_utf8_password.png".to_string(),
+            password: Some("Mot de passe".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(get_message_from_image(options).unwrap(), message);
+    }
+
+    #[test]
+    fn test_get_message_from_image_binary_payload() {
+        let options = SteganographyInjectOption {
+            input_image_path: "testAssets/prestine.png".to_string(),
+            message: "".to_string(),
+            output_image_path: "testAssets/delete_me_binary.png".to_string(),
+            password: None,
+            ..Default::default()
+        };
+        inject_bytes(&[0xFF, 0xFE, 0x00], options).unwrap();
+        let options = SteganographyExtractOption {
+            input_image_path: "testAssets/delete_me_binary.png".to_string(),
+            password: None,
+            ..Default::default()
+        };
+        assert!(matches!(
+            get_message_from_image(options),
+            Err(SteganographyError::CorruptPayload(_))
+        ));
+    }
+
+    #[test]
+    fn test_get_message_from_buffer_without_eof() {
+        let buffer = [0u8; 12];
+        let message = get_message_from_buffer(&buffer);
+        assert!(matches!(
+            message,
+            Err(SteganographyError::CorruptPayload(_))
+        ));
+    }
+
+    fn prestine_jpeg() -> Vec<u8> {
+        let img = image::open("testAssets/prestine.png").unwrap();
+        encode_jpeg(&img, DEFAULT_JPEG_QUALITY).unwrap()
+    }
+
+    #[test]
+    fn test_add_message_to_jpeg_image() {
+        std::fs::create_dir_all("target").unwrap();
+        let options = SteganographyInjectOption {
+            input_image_path: "testAssets/prestine.png".to_string(),
+            message: "Bye".to_string(),
+            output_image_path: "target/delete_me_message.jpg".to_string(),
+            password: Some("secret".to_string()),
+            ..Default::default()
+        };
+        add_message_to_image(options).unwrap();
+        let output = std::fs::read("target/delete_me_message.jpg").unwrap();
+        assert!(is_jpeg(&output));
+        let options = SteganographyExtractOption {
+            input_image_path: "target/delete_me_message.jpg".to_string(),
+            password: Some("secret".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(get_message_from_image(options).unwrap(), "Bye");
+    }
+
+    #[test]
+    fn test_inject_extract_jpeg_scattered_matrix() {
+        let jpeg = prestine_jpeg();
+        let payload = vec![7u8; 500];
+        let options = SteganographyInjectOption {
+            password: Some("secret".to_string()),
+            order: EmbeddingOrder::Scattered,
+            matrix_embedding: true,
+            compression: Compression::None,
+            ..Default::default()
+        };
+        let output = inject_bytes_into_jpeg(&jpeg, &payload, &options).unwrap();
+        let options = SteganographyExtractOption {
+            password: Some("secret".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(extract_bytes_from_jpeg(&output, &options).unwrap(), payload);
+    }
+
+    #[test]
+    fn test_inject_jpeg_keeps_tables() {
+        let jpeg = prestine_jpeg();
+        let options = SteganographyInjectOption::default();
+        let output = inject_bytes_into_jpeg(&jpeg, b"Tables", &options).unwrap();
+        // The quantization and Huffman tables come before the scan, they are not changed
+        let scan = jpeg.windows(2).position(|w| w == [0xFF, 0xDA]).unwrap();
+        assert_eq!(output[..scan], jpeg[..scan]);
+        let original = JpegImage::decode(&jpeg).unwrap().carriers();
+        let modified = JpegImage::decode(&output).unwrap().carriers();
+        assert_eq!(original.len(), modified.len());
+        assert!(original
+            .iter()
+            .zip(&modified)
+            .all(|(a, b)| a.0.abs_diff(b.0) <= 1 && a.0.signum() == b.0.signum()));
+    }
+
+    #[test]
+    fn test_inject_jpeg_too_large() {
+        let jpeg = prestine_jpeg();
+        let options = SteganographyCapacityOption::default();
+        let available = jpeg_capacity(&jpeg, &options).unwrap();
+        let options = SteganographyInjectOption {
+            compression: Compression::None,
+            ..Default::default()
+        };
+        assert!(inject_bytes_into_jpeg(&jpeg, &vec![1u8; available], &options).is_ok());
+        let result = inject_bytes_into_jpeg(&jpeg, &vec![1u8; available + 1], &options);
+        assert!(matches!(result, Err(SteganographyError::Capacity { .. })));
+    }
+
+    #[test]
+    fn test_inject_jpeg_unsupported_options() {
+        let jpeg = prestine_jpeg();
+        for options in [
+            SteganographyInjectOption {
+                bits_per_channel: 2,
+                ..Default::default()
+            },
+            SteganographyInjectOption {
+                method: EmbeddingMethod::Matching,
+                ..Default::default()
+            },
+            SteganographyInjectOption {
+                order: EmbeddingOrder::Adaptive,
+                ..Default::default()
+            },
+        ] {
+            let result = inject_bytes_into_jpeg(&jpeg, b"Options", &options);
+            assert!(matches!(result, Err(SteganographyError::InvalidOption(_))));
+        }
+    }
+
+    #[test]
+    fn test_inject_extract_encoded_jpeg() {
+        let png = std::fs::read("testAssets/prestine.png").unwrap();
+        let options = SteganographyInjectOption::default();
+        let output =
+            inject_bytes_into_encoded_image(&png, b"Encoded", &options, ImageFormat::Jpeg).unwrap();
+        assert_eq!(image::guess_format(&output).unwrap(), ImageFormat::Jpeg);
+        let options = SteganographyExtractOption::default();
+        assert_eq!(
+            extract_bytes_from_encoded_image(&output, &options).unwrap(),
+            b"Encoded".to_vec()
+        );
+    }
+
+    #[test]
+    fn test_extract_jpeg_without_message() {
+        let options = SteganographyExtractOption::default();
+        let result = extract_bytes_from_jpeg(&prestine_jpeg(), &options);
+        assert!(matches!(result, Err(SteganographyError::CorruptPayload(_))));
+    }
+
+    #[test]
+    fn test_add_message_to_gif_image() {
+        std::fs::create_dir_all("target").unwrap();
+        let options = SteganographyInjectOption {
+            input_image_path: "testAssets/prestine.gif".to_string(),
+            message: "Bye".to_string(),
+            output_image_path: "target/delete_me_message.gif".to_string(),
+            password: Some("secret".to_string()),
+            verify: true,
+            ..Default::default()
+        };
+        add_message_to_image(options).unwrap();
+        let output = std::fs::read("target/delete_me_message.gif").unwrap();
+        assert_eq!(palette_format(&output), Some(ImageFormat::Gif));
+        let options = SteganographyExtractOption {
+            input_image_path: "target/delete_me_message.gif".to_string(),
+            password: Some("secret".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(get_message_from_image(options).unwrap(), "Bye");
+    }
+
+    #[test]
+    fn test_inject_extract_palette_scattered_matrix() {
+        let png = palette_png();
+        let payload = vec![7u8; 500];
+        let options = SteganographyInjectOption {
+            password: Some("secret".to_string()),
+            order: EmbeddingOrder::Scattered,
+            matrix_embedding: true,
+            compression: Compression::None,
+            ..Default::default()
+        };
+        let output = inject_bytes_into_palette_image(&png, &payload, &options).unwrap();
+        let options = SteganographyExtractOption {
+            password: Some("secret".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            extract_bytes_from_palette_image(&output, &options).unwrap(),
+            payload
+        );
+    }
+
+    #[test]
+    fn test_inject_palette_keeps_palette() {
+        let png = palette_png();
+        let options = SteganographyInjectOption::default();
+        let output = inject_bytes_into_palette_image(&png, b"Palette", &options).unwrap();
+        let original = png::Decoder::new(Cursor::new(png)).read_info().unwrap();
+        let modified = png::Decoder::new(Cursor::new(output)).read_info().unwrap();
+        assert_eq!(modified.info().color_type, png::ColorType::Indexed);
+        assert_eq!(modified.info().bit_depth, original.info().bit_depth);
+        assert_eq!(modified.info().palette, original.info().palette);
+    }
+
+    #[test]
+    fn test_inject_palette_too_large() {
+        let png = palette_png();
+        let options = SteganographyCapacityOption::default();
+        let available = palette_capacity(&png, &options).unwrap();
+        let options = SteganographyInjectOption {
+            compression: Compression::None,
+            ..Default::default()
+        };
+        assert!(inject_bytes_into_palette_image(&png, &vec![1u8; available], &options).is_ok());
+        let result = inject_bytes_into_palette_image(&png, &vec![1u8; available + 1], &options);
+        assert!(matches!(result, Err(SteganographyError::Capacity { .. })));
+    }
+
+    #[test]
+    fn test_inject_palette_unsupported_options() {
+        let png = palette_png();
+        for options in [
+            SteganographyInjectOption {
+                bits_per_channel: 2,
+                ..Default::default()
+            },
+            SteganographyInjectOption {
+                method: EmbeddingMethod::Matching,
+                ..Default::default()
+            },
+            SteganographyInjectOption {
+                order: EmbeddingOrder::Adaptive,
+                ..Default::default()
+            },
+        ] {
+            let result = inject_bytes_into_palette_image(&png, b"Options", &options);
+            assert!(matches!(result, Err(SteganographyError::InvalidOption(_))));
+        }
+    }
+
+    #[test]
+    fn test_inject_extract_animated_gif_over_frames() {
+        let single_frame =
+            palette_capacity(&palette_gif(), &SteganographyCapacityOption::default());
+        let gif = animated_gif();
+        let available = palette_capacity(&gif, &SteganographyCapacityOption::default()).unwrap();
+        assert!(available > 2 * single_frame.unwrap());
+        // Longer than what the first frame holds, the end goes into the last frame
+        let payload = (0..available).map(|i| (i * 7) as u8).collect::<Vec<u8>>();
+        let options = SteganographyInjectOption {
+            compression: Compression::None,
+            ..Default::default()
+        };
+        let output = inject_bytes_into_palette_image(&gif, &payload, &options).unwrap();
+        let options = SteganographyExtractOption::default();
+        assert_eq!(
+            extract_bytes_from_palette_image(&output, &options).unwrap(),
+            payload
+        );
+    }
+
+    #[test]
+    fn test_inject_extract_animated_png_scattered() {
+        for separate_default_image in [false, true] {
+            let png = animated_png(separate_default_image);
+            let options = SteganographyInjectOption {
+                password: Some("secret".to_string()),
+                order: EmbeddingOrder::Scattered,
+                ..Default::default()
+            };
+            let output = inject_bytes_into_palette_image(&png, b"Animated", &options).unwrap();
+            let decoder = png::Decoder::new(Cursor::new(output.clone()));
+            let animation = decoder.read_info().unwrap().info().animation_control;
+            assert_eq!(animation.map(|a| a.num_frames), Some(3));
+            let options = SteganographyExtractOption {
+                password: Some("secret".to_string()),
+                ..Default::default()
+            };
+            assert_eq!(
+                extract_bytes_from_palette_image(&output, &options).unwrap(),
+                b"Animated".to_vec()
+            );
+        }
+    }
+
+    #[test]
+    fn test_inject_extract_encoded_gif() {
+        let gif = std::fs::read("testAssets/prestine.gif").unwrap();
+        let options = SteganographyInjectOption::default();
+        let output =
+            inject_bytes_into_encoded_image(&gif, b"Encoded", &options, ImageFormat::Gif).unwrap();
+        assert_eq!(palette_format(&output), Some(ImageFormat::Gif));
+        let options = SteganographyExtractOption::default();
+        assert_eq!(
+            extract_bytes_from_encoded_image(&output, &options).unwrap(),
+            b"Encoded".to_vec()
+        );
+    }
+
+    #[test]
+    fn test_extract_palette_without_message() {
+        let options = SteganographyExtractOption::default();
+        let result = extract_bytes_from_palette_image(&palette_png(), &options);
+        assert!(matches!(result, Err(SteganographyError::CorruptPayload(_))));
+    }
+
+    #[test]
+    fn test_get_capacity_of_gif_image() {
+        let options = SteganographyCapacityOption {
+            input_image_path: "testAssets/prestine.gif".to_string(),
+            ..Default::default()
+        };
+        let gif = std::fs::read("testAssets/prestine.gif").unwrap();
+        let expected = palette_capacity(&gif, &options).unwrap();
+        assert!(expected > 1000);
+        assert_eq!(get_capacity_of_image(options).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_inject_lossy_format() {
+        for output_image_path in ["target/delete_me_lossy.gif", "target/delete_me_lossy.webp"] {
+            let options = SteganographyInjectOption {
+                input_image_path: "testAssets/prestine.png".to_string(),
+                message: "Bye".to_string(),
+                output_image_path: output_image_path.to_string(),
+                ..Default::default()
+            };
+            let result = add_message_to_image(options);
+            assert!(matches!(
+                result,
+                Err(SteganographyError::UnsupportedFormat(_))
+            ));
+            assert!(!Path::new(output_image_path).exists());
+        }
+    }
+
+    #[test]
+    fn test_inject_extract_bmp() {
+        std::fs::create_dir_all("target").unwrap();
+        let options = SteganographyInjectOption {
+            input_image_path: "testAssets/prestine.png".to_string(),
+            message: "Bye".to_string(),
+            output_image_path: "target/delete_me_lossless.bmp".to_string(),
+            ..Default::default()
+        };
+        add_message_to_image(options).unwrap();
+        let options = SteganographyExtractOption {
+            input_image_path: "target/delete_me_lossless.bmp".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(get_message_from_image(options).unwrap(), "Bye");
+    }
+
+    #[test]
+    fn test_inject_format_changes_channels() {
+        // A grayscale BMP image is read back as a RGB image, the message would be lost
+        std::fs::create_dir_all("target").unwrap();
+        let img = image::open("testAssets/prestine.png").unwrap();
+        DynamicImage::ImageLuma8(img.to_luma8())
+            .save("target/delete_me_gray_source.png")
+            .unwrap();
+        let options = SteganographyInjectOption {
+            input_image_path: "target/delete_me_gray_source.png".to_string(),
+            message: "Bye".to_string(),
+            output_image_path: "target/delete_me_gray.bmp".to_string(),
+            ..Default::default()
+        };
+        let result = add_message_to_image(options);
+        assert!(matches!(
+            result,
+            Err(SteganographyError::UnsupportedFormat(_))
+        ));
+    }
+
+    #[test]
+    fn test_inject_encoded_image_lossy_format() {
+        let png = std::fs::read("testAssets/prestine.png").unwrap();
+        let options = SteganographyInjectOption::default();
+        let result = inject_bytes_into_encoded_image(&png, b"Lossy", &options, ImageFormat::Gif);
+        assert!(matches!(
+            result,
+            Err(SteganographyError::UnsupportedFormat(_))
+        ));
+    }
+
+    #[test]
+    fn test_add_message_to_image_verify() {
+        std::fs::create_dir_all("target").unwrap();
+        for output_image_path in ["target/delete_me_verify.png", "target/delete_me_verify.jpg"] {
+            let options = SteganographyInjectOption {
+                input_image_path: "testAssets/prestine.png".to_string(),
+                message: "Bye".to_string(),
+                output_image_path: output_image_path.to_string(),
+                password: Some("secret".to_string()),
+                verify: true,
+                ..Default::default()
+            };
+            add_message_to_image(options).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_verify_payload_different() {
+        let result = verify_payload(b"Injected", Ok(b"Extracted".to_vec()));
+        assert!(matches!(result, Err(SteganographyError::Verification(_))));
+        assert!(verify_payload(b"Same", Ok(b"Same".to_vec())).is_ok());
+    }
+
+    #[test]
+    fn test_verify_payload_extraction_failed() {
+        let result = verify_payload(b"Injected", Err(SteganographyError::BadPassword));
+        match result {
+            Err(SteganographyError::Verification(reason)) => {
+                assert_eq!(reason, SteganographyError::BadPassword.to_string())
+            }
+            _ => panic!("Expected a verification error"),
+        }
     }
 }
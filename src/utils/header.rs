@@ -0,0 +1,364 @@
+use std::io::Read;
+
+use super::compression::{compression_from_id, compression_id};
+use super::encryption::{Cipher, CIPHER_LENGTH};
+use super::error::SteganographyError;
+use super::matrix::MAX_MATRIX_BITS;
+use super::options::{ChannelMask, Compression};
+
+/// Bytes that start every message injected into an image. Used to recognize a message
+/// from an image that does not contain one (or that uses the original end of file format)
+pub const MAGIC: [u8; 4] = *b"STGR";
+
+/// Version of the header layout written by this library
+pub const HEADER_VERSION: u8 = 1;
+
+/// Number of bytes the header takes in the image: magic (4), version (1), flags (1), layout (1),
+/// length (4) followed by the number of bits of the matrix embedding (1) when it is used and the
+/// cipher when the payload is encrypted
+pub const HEADER_LENGTH: usize = 11;
+
+/// Number of bytes the longest header takes in the image without the matrix embedding
+pub const MAX_HEADER_LENGTH: usize = HEADER_LENGTH + CIPHER_LENGTH;
+
+/// Flag set when the payload was encrypted with a password before being injected. The
+/// cipher and its parameters follow the length
+const FLAG_ENCRYPTED: u8 = 0b0000_0001;
+
+/// Bits of the flags that hold the identifier of the compression of the payload
+const FLAG_COMPRESSION: u8 = 0b0000_0110;
+
+/// Shift of the flags to reach the identifier of the compression
+const FLAG_COMPRESSION_SHIFT: u8 = 1;
+
+/// Bits of the flags that hold what the payload is, see `PayloadKind`
+const FLAG_KIND: u8 = 0b0011_1000;
+
+/// Shift of the flags to reach the kind of the payload
+const FLAG_KIND_SHIFT: u8 = 3;
+
+/// Flag set when the payload is embedded with a Hamming code. The number of message bits of
+/// each group of carriers follows the length
+const FLAG_MATRIX: u8 = 0b0100_0000;
+
+/// Bits of the layout byte that hold the number of bits used in each channel
+const LAYOUT_BITS_PER_CHANNEL: u8 = 0b0000_1111;
+
+/// Shift of the layout byte to reach the channel mask
+const LAYOUT_CHANNELS_SHIFT: u8 = 4;
+
+/// What the payload holds once decrypted and decompressed
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PayloadKind {
+    /// Bytes or a message given by the caller, returned as is
+    #[default]
+    Bytes,
+    /// A file with its name and metadata, see `FileEnvelope`
+    File,
+    /// Several named entries, see `Container`
+    Container,
+    /// A part of a payload split across several images, see `inject_bytes_into_images`
+    Shard,
+    /// A share of a payload shared k-of-n across several images, see `share_bytes_into_images`
+    Share,
+}
+
+impl PayloadKind {
+    fn id(self) -> u8 {
+        match self {
+            PayloadKind::Bytes => 0,
+            PayloadKind::File => 1,
+            PayloadKind::Container => 2,
+            PayloadKind::Shard => 3,
+            PayloadKind::Share => 4,
+        }
+    }
+
+    fn from_id(id: u8) -> Option<Self> {
+        match id {
+            0 => Some(PayloadKind::Bytes),
+            1 => Some(PayloadKind::File),
+            2 => Some(PayloadKind::Container),
+            3 => Some(PayloadKind::Shard),
+            4 => Some(PayloadKind::Share),
+            _ => None,
+        }
+    }
+}
+
+/// Information written in front of the payload to know how to read it back
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PayloadHeader {
+    /// How the payload was encrypted, `None` when it was not
+    pub cipher: Option<Cipher>,
+    /// Number of least significant bits of each channel that carry the payload
+    pub bits_per_channel: u8,
+    /// Color channels that carry the payload
+    pub channels: ChannelMask,
+    /// Compression of the payload, reversed after the decryption
+    pub compression: Compression,
+    /// What the payload holds
+    pub kind: PayloadKind,
+    /// Number of bytes of the payload that follows the header
+    pub payload_length: u32,
+    /// Number of message bits embedded in each group of `2^k - 1` carriers with a Hamming code,
+    /// `None` when each carrier holds its own bits. See `matrix::choose_matrix_bits`
+    pub matrix_bits: Option<u8>,
+}
+
+impl PayloadHeader {
+    /// Serialize the header into the bytes that are injected before the payload
+    ///
+    /// # Returns
+    /// `length()` bytes, the numbers are stored big endian
+    pub fn to_bytes(self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.length());
+        bytes.extend(MAGIC);
+        bytes.push(HEADER_VERSION);
+        let encrypted = if self.cipher.is_some() {
+            FLAG_ENCRYPTED
+        } else {
+            0
+        };
+        let matrix = if self.matrix_bits.is_some() {
+            FLAG_MATRIX
+        } else {
+            0
+        };
+        bytes.push(
+            encrypted
+                | matrix
+                | (compression_id(self.compression) << FLAG_COMPRESSION_SHIFT)
+                | (self.kind.id() << FLAG_KIND_SHIFT),
+        );
+        bytes.push(
+            (self.channels.bits() << LAYOUT_CHANNELS_SHIFT)
+                | (self.bits_per_channel & LAYOUT_BITS_PER_CHANNEL),
+        );
+        bytes.extend(self.payload_length.to_be_bytes());
+        if let Some(matrix_bits) = self.matrix_bits {
+            bytes.push(matrix_bits);
+        }
+        if let Some(cipher) = self.cipher {
+            bytes.extend(cipher.to_bytes());
+        }
+        bytes
+    }
+
+    /// Number of bytes the header takes in the image
+    pub fn length(self) -> usize {
+        HEADER_LENGTH + self.matrix_bits.map_or(0, |_| 1) + self.cipher.map_or(0, Cipher::length)
+    }
+
+    /// Read the header from the bytes extracted from an image, consuming only the bytes of
+    /// the header so the payload can be read next from the same reader
+    ///
+    /// # Returns
+    /// `None` when the reader does not start with the magic bytes
+    ///
+    /// # Errors
+    /// `CorruptPayload` when the header is truncated, `UnsupportedFormat` when the header
+    /// was written by a more recent version of the library
+    pub fn read_from<R: Read>(reader: &mut R) -> Result<Option<Self>, SteganographyError> {
+        let mut bytes = [0u8; HEADER_LENGTH];
+        if reader.read_exact(&mut bytes).is_err() || !has_magic(&bytes) {
+            return Ok(None);
+        }
+        if bytes[4] != HEADER_VERSION {
+            return Err(SteganographyError::UnsupportedFormat(format!(
+                "Message header version {}",
+                bytes[4]
+            )));
+        }
+        let compression =
+            match compression_from_id((bytes[5] & FLAG_COMPRESSION) >> FLAG_COMPRESSION_SHIFT) {
+                Some(c) => c,
+                None => {
+                    return Err(SteganographyError::UnsupportedFormat(
+                        "Unknown compression of the message".to_string(),
+                    ))
+                }
+            };
+        let kind = match PayloadKind::from_id((bytes[5] & FLAG_KIND) >> FLAG_KIND_SHIFT) {
+            Some(k) => k,
+            None => {
+                return Err(SteganographyError::UnsupportedFormat(
+                    "Unknown kind of message".to_string(),
+                ))
+            }
+        };
+        let channels = match ChannelMask::from_bits(bytes[6] >> LAYOUT_CHANNELS_SHIFT) {
+            Some(c) => c,
+            None => return Ok(None),
+        };
+        let mut length = [0u8; 4];
+        length.copy_from_slice(&bytes[7..11]);
+        let matrix_bits = if bytes[5] & FLAG_MATRIX != 0 {
+            let mut matrix_bits = [0u8; 1];
+            reader.read_exact(&mut matrix_bits).map_err(|_| {
+                SteganographyError::CorruptPayload("The header is truncated".to_string())
+            })?;
+            if !(1..=MAX_MATRIX_BITS).contains(&matrix_bits[0]) {
+                return Err(SteganographyError::UnsupportedFormat(format!(
+                    "Matrix embedding with {} bits",
+                    matrix_bits[0]
+                )));
+            }
+            Some(matrix_bits[0])
+        } else {
+            None
+        };
+        let cipher = if bytes[5] & FLAG_ENCRYPTED != 0 {
+            Some(Cipher::read_from(reader)?)
+        } else {
+            None
+        };
+        Ok(Some(PayloadHeader {
+            cipher,
+            bits_per_channel: bytes[6] & LAYOUT_BITS_PER_CHANNEL,
+            channels,
+            compression,
+            kind,
+            payload_length: u32::from_be_bytes(length),
+            matrix_bits,
+        }))
+    }
+}
+
+/// Number of bytes the header takes in the image
+///
+/// # Arguments
+/// encrypted - True when the payload is encrypted and the cipher is part of the header
+pub fn header_length(encrypted: bool) -> usize {
+    if encrypted {
+        MAX_HEADER_LENGTH
+    } else {
+        HEADER_LENGTH
+    }
+}
+
+/// Indicate if the bytes start like a header written by this library
+///
+/// # Arguments
+/// bytes - The first bytes extracted from an image
+pub fn has_magic(bytes: &[u8]) -> bool {
+    bytes.starts_with(&MAGIC)
+}
+
+#[cfg(test)]
+mod test_header {
+    use super::*;
+
+    #[test]
+    fn test_header_round_trip() {
+        let header = PayloadHeader {
+            cipher: Some(Cipher::generate()),
+            bits_per_channel: 1,
+            channels: ChannelMask::RGBA,
+            compression: Compression::None,
+            kind: PayloadKind::Bytes,
+            payload_length: 70_000,
+            matrix_bits: None,
+        };
+        let bytes = header.to_bytes();
+        assert_eq!(bytes.len(), MAX_HEADER_LENGTH);
+        let result = PayloadHeader::read_from(&mut &bytes[..]).unwrap();
+        assert_eq!(result, Some(header));
+    }
+
+    #[test]
+    fn test_header_truncated_cipher() {
+        let header = PayloadHeader {
+            cipher: Some(Cipher::generate()),
+            bits_per_channel: 1,
+            channels: ChannelMask::RGBA,
+            compression: Compression::None,
+            kind: PayloadKind::Bytes,
+            payload_length: 1,
+            matrix_bits: None,
+        };
+        let result = PayloadHeader::read_from(&mut &header.to_bytes()[..HEADER_LENGTH + 5]);
+        assert!(matches!(result, Err(SteganographyError::CorruptPayload(_))));
+    }
+
+    #[test]
+    fn test_header_to_bytes() {
+        let header = PayloadHeader {
+            cipher: None,
+            bits_per_channel: 2,
+            channels: ChannelMask::RGB,
+            compression: Compression::Brotli,
+            kind: PayloadKind::File,
+            payload_length: 3,
+            matrix_bits: None,
+        };
+        assert_eq!(
+            header.to_bytes(),
+            vec![b'S', b'T', b'G', b'R', 1, 0b1110, 0x72, 0, 0, 0, 3]
+        );
+    }
+
+    #[test]
+    fn test_header_matrix_round_trip() {
+        let header = PayloadHeader {
+            cipher: Some(Cipher::generate()),
+            bits_per_channel: 1,
+            channels: ChannelMask::RGBA,
+            compression: Compression::None,
+            kind: PayloadKind::Bytes,
+            payload_length: 3,
+            matrix_bits: Some(4),
+        };
+        let bytes = header.to_bytes();
+        assert_eq!(bytes.len(), MAX_HEADER_LENGTH + 1);
+        assert_eq!(bytes[5], 0b0100_0001);
+        assert_eq!(bytes[HEADER_LENGTH], 4);
+        let result = PayloadHeader::read_from(&mut &bytes[..]).unwrap();
+        assert_eq!(result, Some(header));
+    }
+
+    #[test]
+    fn test_header_unsupported_matrix_bits() {
+        let result = PayloadHeader::read_from(
+            &mut &[b'S', b'T', b'G', b'R', 1, 0b0100_0000, 0xF1, 0, 0, 0, 3, 9][..],
+        );
+        assert!(matches!(
+            result,
+            Err(SteganographyError::UnsupportedFormat(_))
+        ));
+    }
+
+    #[test]
+    fn test_header_without_magic() {
+        let result = PayloadHeader::read_from(&mut &[0u8; HEADER_LENGTH][..]);
+        assert!(matches!(result, Ok(None)));
+    }
+
+    #[test]
+    fn test_header_without_channels() {
+        let result =
+            PayloadHeader::read_from(&mut &[b'S', b'T', b'G', b'R', 1, 0, 0x01, 0, 0, 0, 3][..]);
+        assert!(matches!(result, Ok(None)));
+    }
+
+    #[test]
+    fn test_header_unknown_version() {
+        let result =
+            PayloadHeader::read_from(&mut &[b'S', b'T', b'G', b'R', 99, 0, 0xF1, 0, 0, 0, 3][..]);
+        assert!(matches!(
+            result,
+            Err(SteganographyError::UnsupportedFormat(_))
+        ));
+    }
+
+    #[test]
+    fn test_header_unknown_kind() {
+        let result = PayloadHeader::read_from(
+            &mut &[b'S', b'T', b'G', b'R', 1, 0b11_1000, 0xF1, 0, 0, 0, 3][..],
+        );
+        assert!(matches!(
+            result,
+            Err(SteganographyError::UnsupportedFormat(_))
+        ));
+    }
+}
@@ -0,0 +1,873 @@
+use std::io::Cursor;
+
+use image::codecs::jpeg::JpegEncoder;
+use image::error::{DecodingError, EncodingError};
+use image::{DynamicImage, ImageError, ImageFormat};
+
+use super::binary::{pack_bits, unpack_bits};
+use super::bitstream::{Sample, MAX_BITS_PER_CHANNEL};
+use super::error::SteganographyError;
+
+/// Quality of the JPEG image encoded from an image of another format before it carries a message
+pub const DEFAULT_JPEG_QUALITY: u8 = 90;
+
+const MARKER: u8 = 0xFF;
+const SOI: u8 = 0xD8;
+const EOI: u8 = 0xD9;
+const SOS: u8 = 0xDA;
+const DHT: u8 = 0xC4;
+const DRI: u8 = 0xDD;
+const SOF0: u8 = 0xC0;
+const SOF1: u8 = 0xC1;
+const RST0: u8 = 0xD0;
+const RST7: u8 = 0xD7;
+const TEM: u8 = 0x01;
+
+/// Number of coefficients of a block of 8x8 samples
+const BLOCK_LENGTH: usize = 64;
+
+/// Run of 16 zero coefficients
+const ZRL: u8 = 0xF0;
+/// End of the block, the remaining coefficients are zero
+const EOB: u8 = 0x00;
+
+/// Largest number of bits of the difference of two DC coefficients of 8 bits samples
+const MAX_DC_SIZE: u8 = 11;
+
+/// Whether the bytes are the content of a JPEG file
+///
+/// # Arguments
+/// * `bytes` - The content of the file, only its first bytes are read
+pub fn is_jpeg(bytes: &[u8]) -> bool {
+    bytes.starts_with(&[MARKER, SOI, MARKER])
+}
+
+/// Encode an image of any format to JPEG, for it to carry a message in its coefficients. The
+/// alpha channel is dropped
+///
+/// # Arguments
+/// * `img` - The image to encode
+/// * `quality` - The quality of the JPEG image, from 1 to 100
+pub fn encode_jpeg(img: &DynamicImage, quality: u8) -> Result<Vec<u8>, SteganographyError> {
+    let img = match img {
+        DynamicImage::ImageLuma8(_) | DynamicImage::ImageRgb8(_) => img.clone(),
+        DynamicImage::ImageLuma16(_) | DynamicImage::ImageLumaA8(_) => {
+            DynamicImage::ImageLuma8(img.to_luma8())
+        }
+        other => DynamicImage::ImageRgb8(other.to_rgb8()),
+    };
+    let mut output = Cursor::new(Vec::new());
+    JpegEncoder::new_with_quality(&mut output, quality).encode_image(&img)?;
+    Ok(output.into_inner())
+}
+
+/// A quantized AC coefficient of a JPEG image that carries the message in the least significant
+/// bit of its magnitude. Only the coefficients of magnitude 2 or more carry the message: changing
+/// that bit keeps them 2 or more, so the same coefficients are found at the extraction, and
+/// keeps them in the same Huffman category, so the scan is encoded with the same codes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Coefficient(pub i16);
+
+impl Coefficient {
+    fn magnitude(self) -> u16 {
+        self.0.unsigned_abs()
+    }
+
+    fn with_magnitude(self, magnitude: u16) -> Self {
+        let value = magnitude as i16;
+        Coefficient(if self.0 < 0 { -value } else { value })
+    }
+}
+
+impl Sample for Coefficient {
+    fn pack_bits(self, bits: &[u8], depth: u8) -> Self {
+        let magnitude = self.magnitude();
+        self.with_magnitude((magnitude & 0xFF00) | pack_bits(magnitude as u8, bits, depth) as u16)
+    }
+
+    fn match_bits(self, bits: &[u8], depth: u8, _increase: bool) -> Self {
+        // Moving the magnitude by one could bring it to 1 or change its Huffman category
+        self.pack_bits(bits, depth)
+    }
+
+    fn unpack_bits(self, depth: u8) -> Vec<u8> {
+        unpack_bits(self.magnitude() as u8, depth)
+    }
+
+    fn high_bits(self) -> u16 {
+        self.magnitude() >> MAX_BITS_PER_CHANNEL
+    }
+}
+
+/// A baseline JPEG image decoded to its quantized DCT coefficients. The coefficients are
+/// encoded back with the same quantization and Huffman tables, the other segments of the file
+/// are kept byte for byte
+pub struct JpegImage {
+    segments: Vec<Segment>,
+}
+
+enum Segment {
+    /// A marker with its content, written back as is
+    Raw(Vec<u8>),
+    Scan(Scan),
+}
+
+/// A component of the frame, see `Frame`
+#[derive(Clone, Copy)]
+struct FrameComponent {
+    id: u8,
+    horizontal: usize,
+    vertical: usize,
+}
+
+/// The dimension of the image and the sampling of its components, from the SOF segment
+struct Frame {
+    width: usize,
+    height: usize,
+    components: Vec<FrameComponent>,
+}
+
+impl Frame {
+    fn max_horizontal(&self) -> usize {
+        self.components
+            .iter()
+            .map(|c| c.horizontal)
+            .max()
+            .unwrap_or(1)
+    }
+
+    fn max_vertical(&self) -> usize {
+        self.components
+            .iter()
+            .map(|c| c.vertical)
+            .max()
+            .unwrap_or(1)
+    }
+}
+
+/// A component of a scan with the Huffman tables of its coefficients
+struct ScanComponent {
+    dc_table: HuffmanTable,
+    ac_table: HuffmanTable,
+    /// Number of blocks of the component in each MCU
+    blocks_per_mcu: usize,
+}
+
+/// The blocks of a scan. The first coefficient of each block is the difference with the DC
+/// coefficient of the previous block of the component, as it is encoded
+struct Scan {
+    /// The SOS segment, written back as is
+    header: Vec<u8>,
+    components: Vec<ScanComponent>,
+    /// Number of MCUs (minimum coded units) between two restart markers, 0 without restart
+    restart_interval: usize,
+    /// The blocks in the order they are encoded, in zigzag order
+    blocks: Vec<[i16; BLOCK_LENGTH]>,
+}
+
+/// A Huffman table of a DHT segment
+#[derive(Clone)]
+struct HuffmanTable {
+    /// The highest code of each length, -1 when no code has this length
+    max_code: [i32; 17],
+    /// Added to a code of each length to get the index of its symbol
+    offset: [i32; 17],
+    symbols: Vec<u8>,
+    /// The code and the length of the code of each symbol
+    codes: Vec<Option<(u16, u8)>>,
+}
+
+impl HuffmanTable {
+    /// # Arguments
+    /// * `counts` - Number of codes of each length, from 1 to 16 bits
+    /// * `symbols` - The symbols, from the shortest code
+    fn new(counts: &[u8], symbols: &[u8]) -> Self {
+        let mut table = HuffmanTable {
+            max_code: [-1; 17],
+            offset: [0; 17],
+            symbols: symbols.to_vec(),
+            codes: vec![None; 256],
+        };
+        let mut code = 0i32;
+        let mut index = 0usize;
+        for (length, count) in (1..=16).zip(counts) {
+            table.offset[length] = index as i32 - code;
+            for _ in 0..*count {
+                table.codes[symbols[index] as usize] = Some((code as u16, length as u8));
+                code += 1;
+                index += 1;
+            }
+            if *count > 0 {
+                table.max_code[length] = code - 1;
+            }
+            code <<= 1;
+        }
+        table
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Result<u8, SteganographyError> {
+        let mut code = 0i32;
+        for length in 1..=16 {
+            code = (code << 1) | reader.read_bit()? as i32;
+            if code <= self.max_code[length] {
+                return Ok(self.symbols[(code + self.offset[length]) as usize]);
+            }
+        }
+        Err(decoding_error("The scan has an invalid Huffman code"))
+    }
+
+    fn encode(&self, writer: &mut BitWriter, symbol: u8) -> Result<(), SteganographyError> {
+        let (code, length) = self.codes[symbol as usize].ok_or_else(|| {
+            encoding_error(&format!(
+                "The Huffman table has no code for {:#04x}",
+                symbol
+            ))
+        })?;
+        writer.write(code, length);
+        Ok(())
+    }
+}
+
+/// Reads the bits of the entropy coded data of a scan, without the stuffed zero bytes
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+    byte: u8,
+    remaining: u8,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8], position: usize) -> Self {
+        BitReader {
+            data,
+            position,
+            byte: 0,
+            remaining: 0,
+        }
+    }
+
+    fn read_bit(&mut self) -> Result<u8, SteganographyError> {
+        if self.remaining == 0 {
+            let byte = *self
+                .data
+                .get(self.position)
+                .ok_or_else(|| decoding_error("The scan is truncated"))?;
+            self.position += 1;
+            if byte == MARKER {
+                if self.data.get(self.position) != Some(&0) {
+                    return Err(decoding_error("The scan ends before its last block"));
+                }
+                self.position += 1;
+            }
+            self.byte = byte;
+            self.remaining = 8;
+        }
+        self.remaining -= 1;
+        Ok((self.byte >> self.remaining) & 1)
+    }
+
+    fn read_bits(&mut self, count: u8) -> Result<u16, SteganographyError> {
+        (0..count).try_fold(0u16, |bits, _| Ok((bits << 1) | self.read_bit()? as u16))
+    }
+
+    /// Skip the bits left in the byte and the restart marker that follows
+    fn restart(&mut self) -> Result<(), SteganographyError> {
+        self.remaining = 0;
+        match self.data.get(self.position..self.position + 2) {
+            Some([MARKER, RST0..=RST7]) => {
+                self.position += 2;
+                Ok(())
+            }
+            _ => Err(decoding_error("A restart marker is missing")),
+        }
+    }
+}
+
+/// Writes the bits of the entropy coded data of a scan, stuffing a zero byte after each 0xFF
+struct BitWriter {
+    output: Vec<u8>,
+    byte: u8,
+    count: u8,
+}
+
+impl BitWriter {
+    fn new(output: Vec<u8>) -> Self {
+        BitWriter {
+            output,
+            byte: 0,
+            count: 0,
+        }
+    }
+
+    fn write(&mut self, bits: u16, length: u8) {
+        for index in (0..length).rev() {
+            self.byte = (self.byte << 1) | ((bits >> index) & 1) as u8;
+            self.count += 1;
+            if self.count == 8 {
+                self.output.push(self.byte);
+                if self.byte == MARKER {
+                    self.output.push(0);
+                }
+                self.byte = 0;
+                self.count = 0;
+            }
+        }
+    }
+
+    /// Complete the last byte with ones
+    fn flush(&mut self) {
+        if self.count > 0 {
+            let length = 8 - self.count;
+            self.write((1 << length) - 1, length);
+        }
+    }
+
+    fn restart(&mut self, index: usize) {
+        self.flush();
+        self.output.extend([MARKER, RST0 + (index % 8) as u8]);
+    }
+}
+
+/// Number of bits of the magnitude of a value, its Huffman category
+fn category(value: i16) -> u8 {
+    (16 - value.unsigned_abs().leading_zeros()) as u8
+}
+
+/// The bits that follow the Huffman code of a value: the value when it is positive, its one's
+/// complement otherwise
+fn value_bits(value: i16, size: u8) -> u16 {
+    if value >= 0 {
+        value as u16
+    } else {
+        (i32::from(value) + (1 << size) - 1) as u16
+    }
+}
+
+/// The value of the bits that follow a Huffman code, see `value_bits`
+fn extend(bits: u16, size: u8) -> i16 {
+    if size == 0 {
+        0
+    } else if bits < 1 << (size - 1) {
+        (i32::from(bits) - (1 << size) + 1) as i16
+    } else {
+        bits as i16
+    }
+}
+
+fn decoding_error(reason: &str) -> SteganographyError {
+    SteganographyError::Image(ImageError::Decoding(DecodingError::new(
+        ImageFormat::Jpeg.into(),
+        reason.to_string(),
+    )))
+}
+
+fn encoding_error(reason: &str) -> SteganographyError {
+    SteganographyError::Image(ImageError::Encoding(EncodingError::new(
+        ImageFormat::Jpeg.into(),
+        reason.to_string(),
+    )))
+}
+
+fn read_u16(data: &[u8], position: usize) -> Result<usize, SteganographyError> {
+    data.get(position..position + 2)
+        .map(|b| u16::from_be_bytes([b[0], b[1]]) as usize)
+        .ok_or_else(|| decoding_error("A segment is truncated"))
+}
+
+impl JpegImage {
+    /// Decode the coefficients of a baseline JPEG image
+    ///
+    /// # Arguments
+    /// * `bytes` - The content of the JPEG file
+    ///
+    /// # Errors
+    ///
+    /// Returns `UnsupportedFormat` if the image is not a JPEG image or if it is not a baseline
+    /// one (E.g. a progressive JPEG image), `Image` if the image is invalid
+    pub fn decode(bytes: &[u8]) -> Result<Self, SteganographyError> {
+        if !is_jpeg(bytes) {
+            return Err(SteganographyError::UnsupportedFormat(
+                "The image is not a JPEG image".to_string(),
+            ));
+        }
+        let mut segments = vec![Segment::Raw(vec![MARKER, SOI])];
+        let mut frame = None;
+        let mut dc_tables: [Option<HuffmanTable>; 4] = Default::default();
+        let mut ac_tables: [Option<HuffmanTable>; 4] = Default::default();
+        let mut restart_interval = 0;
+        let mut position = 2;
+        loop {
+            // Bytes between the segments are skipped, the fill bytes before a marker too
+            while bytes.get(position).is_some_and(|b| *b != MARKER) {
+                position += 1;
+            }
+            while bytes.get(position) == Some(&MARKER) {
+                position += 1;
+            }
+            let marker = *bytes
+                .get(position)
+                .ok_or_else(|| decoding_error("The image has no end marker"))?;
+            position += 1;
+            match marker {
+                EOI => {
+                    // Anything after the end of the image is kept
+                    let mut segment = vec![MARKER, EOI];
+                    segment.extend(&bytes[position..]);
+                    segments.push(Segment::Raw(segment));
+                    break;
+                }
+                TEM | RST0..=RST7 => segments.push(Segment::Raw(vec![MARKER, marker])),
+                _ => {
+                    let length = read_u16(bytes, position)?;
+                    let data = bytes
+                        .get(position + 2..position + length)
+                        .ok_or_else(|| decoding_error("A segment is truncated"))?;
+                    let mut segment = vec![MARKER, marker];
+                    segment.extend(&bytes[position..position + length]);
+                    position += length;
+                    match marker {
+                        SOF0 | SOF1 => frame = Some(parse_frame(data)?),
+                        0xC2 | 0xC3 | 0xC5..=0xC7 | 0xC9..=0xCB | 0xCD..=0xCF => {
+                            return Err(SteganographyError::UnsupportedFormat(
+                                "Only the baseline JPEG images can carry a message, not the \
+                                 progressive, lossless or arithmetic coded ones"
+                                    .to_string(),
+                            ))
+                        }
+                        DHT => parse_huffman_tables(data, &mut dc_tables, &mut ac_tables)?,
+                        DRI => restart_interval = read_u16(data, 0)?,
+                        SOS => {
+                            let frame = frame
+                                .as_ref()
+                                .ok_or_else(|| decoding_error("The scan comes before the frame"))?;
+                            let mut reader = BitReader::new(bytes, position);
+                            let scan = decode_scan(
+                                segment,
+                                data,
+                                frame,
+                                &dc_tables,
+                                &ac_tables,
+                                restart_interval,
+                                &mut reader,
+                            )?;
+                            position = reader.position;
+                            segments.push(Segment::Scan(scan));
+                            continue;
+                        }
+                        _ => {}
+                    }
+                    segments.push(Segment::Raw(segment));
+                }
+            }
+        }
+        Ok(JpegImage { segments })
+    }
+
+    /// The coefficients that carry the message (see `Coefficient`), scan after scan, block after
+    /// block, in zigzag order
+    pub fn carriers(&self) -> Vec<Coefficient> {
+        self.blocks()
+            .flat_map(|block| block[1..].iter())
+            .filter(|value| value.unsigned_abs() >= 2)
+            .map(|value| Coefficient(*value))
+            .collect()
+    }
+
+    /// Replace the coefficients that carry the message, in the order of `carriers`
+    ///
+    /// # Arguments
+    /// * `carriers` - The coefficients returned by `carriers`, once modified. Their magnitude
+    ///   must stay 2 or more
+    pub fn set_carriers(&mut self, carriers: &[Coefficient]) {
+        let mut carriers = carriers.iter();
+        for segment in self.segments.iter_mut() {
+            if let Segment::Scan(scan) = segment {
+                for block in scan.blocks.iter_mut() {
+                    for value in block[1..].iter_mut() {
+                        if value.unsigned_abs() >= 2 {
+                            if let Some(carrier) = carriers.next() {
+                                *value = carrier.0;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Encode the image back to the content of a JPEG file
+    ///
+    /// # Errors
+    ///
+    /// Returns `Image` if a Huffman table has no code for a coefficient
+    pub fn encode(&self) -> Result<Vec<u8>, SteganographyError> {
+        let mut output = Vec::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Raw(bytes) => output.extend(bytes),
+                Segment::Scan(scan) => output = encode_scan(scan, output)?,
+            }
+        }
+        Ok(output)
+    }
+
+    fn blocks(&self) -> impl Iterator<Item = &[i16; BLOCK_LENGTH]> {
+        self.segments
+            .iter()
+            .filter_map(|segment| match segment {
+                Segment::Scan(scan) => Some(scan.blocks.iter()),
+                Segment::Raw(_) => None,
+            })
+            .flatten()
+    }
+}
+
+fn parse_frame(data: &[u8]) -> Result<Frame, SteganographyError> {
+    let height = read_u16(data, 1)?;
+    let width = read_u16(data, 3)?;
+    let count = *data
+        .get(5)
+        .ok_or_else(|| decoding_error("The frame is truncated"))? as usize;
+    let components = data
+        .get(6..6 + count * 3)
+        .ok_or_else(|| decoding_error("The frame is truncated"))?
+        .chunks(3)
+        .map(|c| FrameComponent {
+            id: c[0],
+            horizontal: (c[1] >> 4) as usize,
+            vertical: (c[1] & 0x0F) as usize,
+        })
+        .collect::<Vec<FrameComponent>>();
+    if height == 0 || width == 0 {
+        return Err(SteganographyError::UnsupportedFormat(
+            "The height of the JPEG image is defined after its first scan".to_string(),
+        ));
+    }
+    if components
+        .iter()
+        .any(|c| !(1..=4).contains(&c.horizontal) || !(1..=4).contains(&c.vertical))
+    {
+        return Err(decoding_error("A component has an invalid sampling factor"));
+    }
+    Ok(Frame {
+        width,
+        height,
+        components,
+    })
+}
+
+fn parse_huffman_tables(
+    mut data: &[u8],
+    dc_tables: &mut [Option<HuffmanTable>; 4],
+    ac_tables: &mut [Option<HuffmanTable>; 4],
+) -> Result<(), SteganographyError> {
+    while !data.is_empty() {
+        let truncated = || decoding_error("The Huffman table is truncated");
+        let class = data[0] >> 4;
+        let id = (data[0] & 0x0F) as usize;
+        let counts = data.get(1..17).ok_or_else(truncated)?;
+        let count = counts.iter().map(|c| *c as usize).sum::<usize>();
+        let symbols = data.get(17..17 + count).ok_or_else(truncated)?;
+        let table = Some(HuffmanTable::new(counts, symbols));
+        match (class, id) {
+            (0, 0..=3) => dc_tables[id] = table,
+            (1, 0..=3) => ac_tables[id] = table,
+            _ => return Err(decoding_error("The Huffman table has an invalid class")),
+        }
+        data = &data[17 + count..];
+    }
+    Ok(())
+}
+
+/// Number of MCUs of a scan
+fn mcu_count(frame: &Frame, components: &[FrameComponent]) -> usize {
+    let (max_horizontal, max_vertical) = (frame.max_horizontal(), frame.max_vertical());
+    match components {
+        // A scan of a single component has one block per MCU
+        [component] => {
+            let width = (frame.width * component.horizontal).div_ceil(max_horizontal);
+            let height = (frame.height * component.vertical).div_ceil(max_vertical);
+            width.div_ceil(8) * height.div_ceil(8)
+        }
+        _ => frame.width.div_ceil(8 * max_horizontal) * frame.height.div_ceil(8 * max_vertical),
+    }
+}
+
+fn decode_scan(
+    header: Vec<u8>,
+    data: &[u8],
+    frame: &Frame,
+    dc_tables: &[Option<HuffmanTable>; 4],
+    ac_tables: &[Option<HuffmanTable>; 4],
+    restart_interval: usize,
+    reader: &mut BitReader,
+) -> Result<Scan, SteganographyError> {
+    let truncated = || decoding_error("The scan header is truncated");
+    let count = *data.first().ok_or_else(truncated)? as usize;
+    let selectors = data.get(1..1 + count * 2).ok_or_else(truncated)?;
+    let frame_components = selectors
+        .chunks(2)
+        .map(|s| {
+            frame
+                .components
+                .iter()
+                .find(|c| c.id == s[0])
+                .copied()
+                .ok_or_else(|| decoding_error("The scan has an unknown component"))
+        })
+        .collect::<Result<Vec<FrameComponent>, SteganographyError>>()?;
+    let missing_table = || decoding_error("The scan uses a missing Huffman table");
+    let components = selectors
+        .chunks(2)
+        .zip(&frame_components)
+        .map(|(s, component)| {
+            Ok(ScanComponent {
+                dc_table: dc_tables
+                    .get((s[1] >> 4) as usize)
+                    .cloned()
+                    .flatten()
+                    .ok_or_else(missing_table)?,
+                ac_table: ac_tables
+                    .get((s[1] & 0x0F) as usize)
+                    .cloned()
+                    .flatten()
+                    .ok_or_else(missing_table)?,
+                blocks_per_mcu: if count == 1 {
+                    1
+                } else {
+                    component.horizontal * component.vertical
+                },
+            })
+        })
+        .collect::<Result<Vec<ScanComponent>, SteganographyError>>()?;
+    let mut scan = Scan {
+        header,
+        components,
+        restart_interval,
+        blocks: Vec::new(),
+    };
+    for mcu in 0..mcu_count(frame, &frame_components) {
+        if restart_interval > 0 && mcu > 0 && mcu % restart_interval == 0 {
+            reader.restart()?;
+        }
+        for component in &scan.components {
+            for _ in 0..component.blocks_per_mcu {
+                scan.blocks.push(decode_block(reader, component)?);
+            }
+        }
+    }
+    Ok(scan)
+}
+
+fn decode_block(
+    reader: &mut BitReader,
+    component: &ScanComponent,
+) -> Result<[i16; BLOCK_LENGTH], SteganographyError> {
+    let mut block = [0i16; BLOCK_LENGTH];
+    let size = component.dc_table.decode(reader)?;
+    // The symbol comes from the file, a larger size would overflow the value
+    if size > MAX_DC_SIZE {
+        return Err(decoding_error("A DC coefficient has more than 11 bits"));
+    }
+    block[0] = extend(reader.read_bits(size)?, size);
+    let mut index = 1;
+    while index < BLOCK_LENGTH {
+        let symbol = component.ac_table.decode(reader)?;
+        let (run, size) = ((symbol >> 4) as usize, symbol & 0x0F);
+        match (run, size) {
+            (15, 0) => index += 16,
+            (_, 0) => break,
+            _ => {
+                index += run;
+                if index >= BLOCK_LENGTH {
+                    return Err(decoding_error("A block has more than 64 coefficients"));
+                }
+                block[index] = extend(reader.read_bits(size)?, size);
+                index += 1;
+            }
+        }
+    }
+    Ok(block)
+}
+
+fn encode_scan(scan: &Scan, mut output: Vec<u8>) -> Result<Vec<u8>, SteganographyError> {
+    output.extend(&scan.header);
+    let mut writer = BitWriter::new(output);
+    let mcu_length = scan.components.iter().map(|c| c.blocks_per_mcu).sum();
+    for (mcu, blocks) in scan.blocks.chunks(mcu_length).enumerate() {
+        if scan.restart_interval > 0 && mcu > 0 && mcu % scan.restart_interval == 0 {
+            writer.restart(mcu / scan.restart_interval - 1);
+        }
+        let mut blocks = blocks.iter();
+        for component in &scan.components {
+            for block in blocks.by_ref().take(component.blocks_per_mcu) {
+                encode_block(&mut writer, component, block)?;
+            }
+        }
+    }
+    writer.flush();
+    Ok(writer.output)
+}
+
+fn encode_block(
+    writer: &mut BitWriter,
+    component: &ScanComponent,
+    block: &[i16; BLOCK_LENGTH],
+) -> Result<(), SteganographyError> {
+    let size = category(block[0]);
+    component.dc_table.encode(writer, size)?;
+    writer.write(value_bits(block[0], size), size);
+    let mut run = 0;
+    for value in &block[1..] {
+        if *value == 0 {
+            run += 1;
+            continue;
+        }
+        while run > 15 {
+            component.ac_table.encode(writer, ZRL)?;
+            run -= 16;
+        }
+        let size = category(*value);
+        component.ac_table.encode(writer, (run << 4) | size)?;
+        writer.write(value_bits(*value, size), size);
+        run = 0;
+    }
+    if run > 0 {
+        component.ac_table.encode(writer, EOB)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test_jpeg {
+    use super::*;
+
+    fn prestine_jpeg() -> Vec<u8> {
+        let img = image::open("testAssets/prestine.png").unwrap();
+        encode_jpeg(&img, DEFAULT_JPEG_QUALITY).unwrap()
+    }
+
+    #[test]
+    fn test_is_jpeg() {
+        assert!(is_jpeg(&prestine_jpeg()));
+        assert!(!is_jpeg(&std::fs::read("testAssets/prestine.png").unwrap()));
+    }
+
+    #[test]
+    fn test_decode_encode_same_bytes() {
+        let jpeg = prestine_jpeg();
+        let image = JpegImage::decode(&jpeg).unwrap();
+        assert_eq!(image.encode().unwrap(), jpeg);
+    }
+
+    #[test]
+    fn test_decode_encode_grayscale() {
+        let img = image::open("testAssets/prestine.png").unwrap();
+        let jpeg = encode_jpeg(&DynamicImage::ImageLuma8(img.to_luma8()), 75).unwrap();
+        let image = JpegImage::decode(&jpeg).unwrap();
+        assert_eq!(image.encode().unwrap(), jpeg);
+    }
+
+    #[test]
+    fn test_set_carriers_changes_only_carriers() {
+        let jpeg = prestine_jpeg();
+        let mut image = JpegImage::decode(&jpeg).unwrap();
+        let carriers: Vec<Coefficient> = image
+            .carriers()
+            .into_iter()
+            .map(|c| c.pack_bits(&[1], 1))
+            .collect();
+        image.set_carriers(&carriers);
+        let encoded = image.encode().unwrap();
+        let decoded = JpegImage::decode(&encoded).unwrap();
+        assert_eq!(decoded.carriers(), carriers);
+        // The pixels change slightly, the image still decodes
+        let original = image::load_from_memory(&jpeg).unwrap().to_rgb8();
+        let modified = image::load_from_memory(&encoded).unwrap().to_rgb8();
+        assert_eq!(original.dimensions(), modified.dimensions());
+    }
+
+    #[test]
+    fn test_coefficient_keeps_category() {
+        for value in [2i16, 3, 4, 7, -2, -3, -6, -7, 1023, -1024] {
+            for bit in [0, 1] {
+                let packed = Coefficient(value).pack_bits(&[bit], 1);
+                assert_eq!(packed.unpack_bits(1), vec![bit]);
+                assert_eq!(category(packed.0), category(value));
+                assert_eq!(packed.0.signum(), value.signum());
+            }
+        }
+    }
+
+    #[test]
+    fn test_value_bits_extend() {
+        for value in [-1023i16, -5, -1, 1, 5, 1023] {
+            let size = category(value);
+            assert_eq!(extend(value_bits(value, size), size), value);
+        }
+    }
+
+    #[test]
+    fn test_decode_restart_markers() {
+        let mut writer = BitWriter::new(Vec::new());
+        writer.write(0b101, 3);
+        writer.restart(0);
+        writer.write(0b1, 1);
+        writer.flush();
+        assert_eq!(writer.output, vec![0b1011_1111, MARKER, RST0, 0xFF, 0x00]);
+        let mut reader = BitReader::new(&writer.output, 0);
+        assert_eq!(reader.read_bits(3).unwrap(), 0b101);
+        reader.restart().unwrap();
+        assert_eq!(reader.read_bits(8).unwrap(), 0xFF);
+        assert_eq!(reader.position, writer.output.len());
+    }
+
+    #[test]
+    fn test_decode_progressive_unsupported() {
+        let mut jpeg = prestine_jpeg();
+        let position = jpeg.windows(2).position(|w| w == [MARKER, SOF0]).unwrap();
+        jpeg[position + 1] = 0xC2;
+        assert!(matches!(
+            JpegImage::decode(&jpeg),
+            Err(SteganographyError::UnsupportedFormat(_))
+        ));
+    }
+
+    #[test]
+    fn test_decode_truncated() {
+        let jpeg = prestine_jpeg();
+        let scan = jpeg.windows(2).position(|w| w == [MARKER, SOS]).unwrap();
+        assert!(matches!(
+            JpegImage::decode(&jpeg[..scan + 4]),
+            Err(SteganographyError::Image(_))
+        ));
+    }
+
+    #[test]
+    fn test_decode_dc_size_too_large() {
+        let mut jpeg = prestine_jpeg();
+        // Every symbol of the first DC table gives a difference of 17 bits
+        let position = jpeg.windows(2).position(|w| w == [MARKER, DHT]).unwrap();
+        assert_eq!(jpeg[position + 4] >> 4, 0);
+        let count = jpeg[position + 5..position + 21]
+            .iter()
+            .map(|c| *c as usize)
+            .sum::<usize>();
+        for symbol in &mut jpeg[position + 21..position + 21 + count] {
+            *symbol = 17;
+        }
+        assert!(matches!(
+            JpegImage::decode(&jpeg),
+            Err(SteganographyError::Image(_))
+        ));
+    }
+
+    #[test]
+    fn test_decode_not_jpeg() {
+        let png = std::fs::read("testAssets/prestine.png").unwrap();
+        assert!(matches!(
+            JpegImage::decode(&png),
+            Err(SteganographyError::UnsupportedFormat(_))
+        ));
+    }
+}
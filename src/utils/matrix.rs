@@ -0,0 +1,91 @@
+/// Highest number of message bits embedded in one group of carriers. A group of `2^k - 1`
+/// carriers holds `k` bits, more bits per group change fewer carriers per bit but need more
+/// carriers
+pub const MAX_MATRIX_BITS: u8 = 8;
+
+/// Number of carriers (least significant bits of channels) of a group that holds `bits` message
+/// bits with a Hamming code
+///
+/// # Arguments
+/// bits - Number of message bits of the group, from 1 to `MAX_MATRIX_BITS`
+pub fn group_length(bits: u8) -> usize {
+    (1 << bits) - 1
+}
+
+/// Choose the largest number of bits per group so that the payload still fits into the carriers.
+/// The larger the groups, the fewer carriers change: at most one per group
+///
+/// # Arguments
+/// * `payload_length` - Number of bytes to embed
+/// * `carriers` - Number of carriers available after the header
+///
+/// # Returns
+/// `None` when the payload does not fit even with one bit per carrier
+pub fn choose_matrix_bits(payload_length: usize, carriers: usize) -> Option<u8> {
+    let payload_bits = payload_length * 8;
+    (1..=MAX_MATRIX_BITS)
+        .rev()
+        .find(|bits| payload_bits.div_ceil(*bits as usize) * group_length(*bits) <= carriers)
+}
+
+/// Syndrome of a group: the exclusive or of the 1-based positions of the carriers set to 1. It
+/// is the value of the message bits the group holds
+///
+/// # Arguments
+/// lsbs - The least significant bit (0 or 1) of each carrier of the group
+pub fn syndrome(lsbs: &[u8]) -> usize {
+    lsbs.iter()
+        .enumerate()
+        .filter(|(_, lsb)| **lsb != 0)
+        .fold(0, |acc, (index, _)| acc ^ (index + 1))
+}
+
+/// Find the carrier to flip for the group to hold the message bits
+///
+/// # Arguments
+/// * `lsbs` - The least significant bit (0 or 1) of each carrier of the group
+/// * `value` - The message bits the group must hold, lower than `lsbs.len() + 1`
+///
+/// # Returns
+/// The index of the carrier to flip, `None` when the group already holds the value
+pub fn carrier_to_flip(lsbs: &[u8], value: usize) -> Option<usize> {
+    match syndrome(lsbs) ^ value {
+        0 => None,
+        position => Some(position - 1),
+    }
+}
+
+#[cfg(test)]
+mod test_matrix {
+    use super::*;
+
+    #[test]
+    fn test_group_length() {
+        assert_eq!(group_length(1), 1);
+        assert_eq!(group_length(3), 7);
+    }
+
+    #[test]
+    fn test_choose_matrix_bits() {
+        // 1 byte: 3 groups of 3 bits (21 carriers) fit into 24 carriers, 2 groups of 4 bits
+        // (30 carriers) do not
+        assert_eq!(choose_matrix_bits(1, 24), Some(3));
+        assert_eq!(choose_matrix_bits(1, 8), Some(1));
+        assert_eq!(choose_matrix_bits(1, 7), None);
+        assert_eq!(choose_matrix_bits(1, 100_000), Some(MAX_MATRIX_BITS));
+    }
+
+    #[test]
+    fn test_carrier_to_flip_every_value() {
+        let lsbs = [1, 0, 1, 1, 0, 0, 1];
+        for value in 0..8 {
+            let mut group = lsbs;
+            if let Some(index) = carrier_to_flip(&group, value) {
+                group[index] ^= 1;
+            }
+            assert_eq!(syndrome(&group), value);
+            let changes = group.iter().zip(lsbs).filter(|(a, b)| **a != *b).count();
+            assert!(changes <= 1);
+        }
+    }
+}
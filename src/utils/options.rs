@@ -1,6 +1,5 @@
 use clap;
 use clap::builder::TypedValueParser;
-use clap::command;
 use clap::Parser;
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
 pub enum Mode {
@@ -8,6 +7,11 @@ pub enum Mode {
     Extract,
     Encrypt,
     Decrypt,
+    Capacity,
+    List,
+    Remove,
+    Share,
+    Recover,
 }
 
 impl std::fmt::Display for Mode {
@@ -17,6 +21,11 @@ impl std::fmt::Display for Mode {
             Self::Extract => "extract",
             Self::Encrypt => "encrypt",
             Self::Decrypt => "decrypt",
+            Self::Capacity => "capacity",
+            Self::List => "list",
+            Self::Remove => "remove",
+            Self::Share => "share",
+            Self::Recover => "recover",
         };
         s.fmt(f)
     }
@@ -30,11 +39,224 @@ impl std::str::FromStr for Mode {
             "extract" => Ok(Self::Extract),
             "encrypt" => Ok(Self::Encrypt),
             "decrypt" => Ok(Self::Decrypt),
+            "capacity" => Ok(Self::Capacity),
+            "list" => Ok(Self::List),
+            "remove" => Ok(Self::Remove),
+            "share" => Ok(Self::Share),
+            "recover" => Ok(Self::Recover),
             _ => Err(format!("Unknown mode: {s}")),
         }
     }
 }
 
+/// Order in which the channels of the image receive the bits of the message
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub enum EmbeddingOrder {
+    /// From the top left pixel, pixel after pixel. Anyone can find where the message is
+    #[default]
+    Sequential,
+    /// Spread across the whole image in a pseudo-random order derived from the password.
+    /// The message can only be located with the password
+    Scattered,
+    /// From the most textured pixels to the flattest ones, where a change is the easiest to
+    /// detect. Requires the replacement method
+    Adaptive,
+}
+
+impl std::fmt::Display for EmbeddingOrder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Self::Sequential => "sequential",
+            Self::Scattered => "scattered",
+            Self::Adaptive => "adaptive",
+        };
+        s.fmt(f)
+    }
+}
+impl std::str::FromStr for EmbeddingOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sequential" => Ok(Self::Sequential),
+            "scattered" => Ok(Self::Scattered),
+            "adaptive" => Ok(Self::Adaptive),
+            _ => Err(format!("Unknown order: {s}")),
+        }
+    }
+}
+
+/// How the least significant bits of a channel are changed to carry the message. The extraction
+/// reads the bits the same way for both
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub enum EmbeddingMethod {
+    /// The bits are overwritten. An even value can only go up and an odd value down, an
+    /// asymmetry that statistical analyses (chi-square, RS) detect
+    #[default]
+    Replacement,
+    /// The value is randomly increased or decreased (by 1 with one bit per channel) to reach the
+    /// bits, which keeps the statistics of the image balanced
+    Matching,
+}
+
+impl std::fmt::Display for EmbeddingMethod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Self::Replacement => "replacement",
+            Self::Matching => "matching",
+        };
+        s.fmt(f)
+    }
+}
+impl std::str::FromStr for EmbeddingMethod {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "replacement" => Ok(Self::Replacement),
+            "matching" => Ok(Self::Matching),
+            _ => Err(format!("Unknown method: {s}")),
+        }
+    }
+}
+
+/// Compression of the payload before it is encrypted and injected. Text often compresses 3 to 5
+/// times, which lets the same image hide a longer message
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub enum Compression {
+    /// The payload is injected as is
+    #[default]
+    None,
+    /// Deflate, the most common and fast
+    Deflate,
+    /// Zstandard, fast with a good ratio
+    Zstd,
+    /// Brotli, slower with the best ratio on text
+    Brotli,
+}
+
+impl std::fmt::Display for Compression {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Self::None => "none",
+            Self::Deflate => "deflate",
+            Self::Zstd => "zstd",
+            Self::Brotli => "brotli",
+        };
+        s.fmt(f)
+    }
+}
+impl std::str::FromStr for Compression {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Self::None),
+            "deflate" => Ok(Self::Deflate),
+            "zstd" => Ok(Self::Zstd),
+            "brotli" => Ok(Self::Brotli),
+            _ => Err(format!("Unknown compression: {s}")),
+        }
+    }
+}
+
+/// Color channels of each pixel that carry the message. Leaving the alpha out keeps the
+/// transparency of the image untouched
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct ChannelMask(u8);
+
+impl ChannelMask {
+    /// The red, green, blue and alpha channels
+    pub const RGBA: ChannelMask = ChannelMask(0b1111);
+    /// The red, green and blue channels, the alpha is left untouched
+    pub const RGB: ChannelMask = ChannelMask(0b0111);
+
+    const LETTERS: [char; 4] = ['r', 'g', 'b', 'a'];
+
+    /// Build a mask from its bits: red is the lowest bit, then green, blue and alpha
+    ///
+    /// # Returns
+    /// `None` when no channel is selected or when a bit does not match a channel
+    pub fn from_bits(bits: u8) -> Option<Self> {
+        if bits == 0 || bits > Self::RGBA.0 {
+            None
+        } else {
+            Some(ChannelMask(bits))
+        }
+    }
+
+    /// The bits of the mask: red is the lowest bit, then green, blue and alpha
+    pub fn bits(self) -> u8 {
+        self.0
+    }
+
+    /// Indicate if the channel (0 = red, 1 = green, 2 = blue, 3 = alpha) carries the message
+    pub fn contains(self, channel: usize) -> bool {
+        channel < Self::LETTERS.len() && self.0 & (1 << channel) != 0
+    }
+
+    /// Indicate if a sample of a pixel carries the message. Grayscale pixels have a single
+    /// luminance sample that carries the message when any of red, green or blue is selected
+    ///
+    /// # Arguments
+    /// * `sample` - Index of the sample in the pixel
+    /// * `channel_count` - Number of samples of a pixel: 1 (luma), 2 (luma and alpha),
+    ///   3 (RGB) or 4 (RGBA)
+    pub fn selects(self, sample: usize, channel_count: u8) -> bool {
+        match (channel_count, sample) {
+            (1, 0) | (2, 0) => self.0 & Self::RGB.0 != 0,
+            (2, 1) => self.contains(3),
+            _ => self.contains(sample),
+        }
+    }
+
+    /// Number of samples of each pixel that carry the message
+    ///
+    /// # Arguments
+    /// * `channel_count` - Number of samples of a pixel, see `selects`
+    pub fn sample_count(self, channel_count: u8) -> u8 {
+        (0..channel_count as usize)
+            .filter(|sample| self.selects(*sample, channel_count))
+            .count() as u8
+    }
+}
+
+impl Default for ChannelMask {
+    fn default() -> Self {
+        Self::RGBA
+    }
+}
+
+impl std::fmt::Display for ChannelMask {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s: String = Self::LETTERS
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| self.contains(*i))
+            .map(|(_, c)| c)
+            .collect();
+        s.fmt(f)
+    }
+}
+impl std::str::FromStr for ChannelMask {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut bits = 0u8;
+        for c in s.chars() {
+            let channel = Self::LETTERS
+                .iter()
+                .position(|l| *l == c.to_ascii_lowercase())
+                .ok_or_else(|| format!("Unknown channel: {c}"))?;
+            if bits & (1 << channel) != 0 {
+                return Err(format!("Channel listed twice: {c}"));
+            }
+            bits |= 1 << channel;
+        }
+        ChannelMask::from_bits(bits).ok_or_else(|| "At least one channel is required".to_string())
+    }
+}
+
 /// CLI arguments
 ///
 /// The command line provided in this Cargo accepts many options to encrypt a message
@@ -70,9 +292,105 @@ pub struct CliData {
     /// "extract" = extract from an image the message.
     /// "encrypt" = encrypt the message without using any image (not steganography related, utility feature).
     /// "decrypt" = decrypt a message withotu using any image  (not steganography related, utility feature).
-    #[arg(short='e', long, value_parser = clap::builder::PossibleValuesParser::new(["inject", "extract", "encrypt", "decrypt"])
+    /// "capacity" = print how many bytes of message the input image can hide.
+    /// "list" = print the names of the entries hidden into an image with `--entry`.
+    /// "remove" = remove the entry named by `--entry` from an image.
+    /// "share" = share the message across the `--shards` images, any `--threshold` of them recover it.
+    /// "recover" = recover the message shared across the `--shards` images.
+    #[arg(short='e', long, value_parser = clap::builder::PossibleValuesParser::new(["inject", "extract", "encrypt", "decrypt", "capacity", "list", "remove", "share", "recover"])
     .map(|s| s.parse::<Mode>().unwrap()),)]
     mode: Option<Mode>,
+
+    /// Order in which the pixels receive the message when injecting:
+    /// "sequential" = from the top left pixel (default).
+    /// "scattered" = spread across the image in an order only the password can reproduce.
+    /// "adaptive" = from the most textured regions to the flattest ones.
+    /// The extraction finds the order by itself.
+    #[arg(long, value_parser = clap::builder::PossibleValuesParser::new(["sequential", "scattered", "adaptive"])
+    .map(|s| s.parse::<EmbeddingOrder>().unwrap()),)]
+    order: Option<EmbeddingOrder>,
+
+    /// How the bits of each channel are changed when injecting:
+    /// "replacement" = the bits are overwritten (default).
+    /// "matching" = the value is randomly increased or decreased, harder to detect.
+    /// The extraction does not need it.
+    #[arg(long, value_parser = clap::builder::PossibleValuesParser::new(["replacement", "matching"])
+    .map(|s| s.parse::<EmbeddingMethod>().unwrap()),)]
+    method: Option<EmbeddingMethod>,
+
+    /// Embed the message with a Hamming code: each group of channels holds several bits of the
+    /// message and at most one of its channels changes. The shorter the message compared to the
+    /// capacity, the fewer channels change. Uses one bit per channel
+    #[arg(long)]
+    matrix: bool,
+
+    /// Reopen the saved image, extract the message and compare it with the injected one. A
+    /// format or an encoder that loses the message is reported as an error
+    #[arg(long)]
+    verify: bool,
+
+    /// Number of least significant bits of each color channel that carry the message (1 to 4).
+    /// More bits hold a longer message but alter the colors more. The extraction finds the
+    /// number of bits by itself
+    #[arg(short, long, value_parser = clap::value_parser!(u8).range(1..=4))]
+    bits_per_channel: Option<u8>,
+
+    /// Color channels that carry the message, any combination of "r", "g", "b" and "a"
+    /// (E.g. "rgb" to leave the alpha untouched). Default to "rgba". The extraction finds the
+    /// channels by itself
+    #[arg(short, long, value_parser = |s: &str| s.parse::<ChannelMask>())]
+    channels: Option<ChannelMask>,
+
+    /// Compress the message before injecting it: "deflate" (when no value is given), "zstd",
+    /// "brotli" or "none". The extraction finds the compression by itself
+    #[arg(long, num_args = 0..=1, default_missing_value = "deflate", value_parser = clap::builder::PossibleValuesParser::new(["none", "deflate", "zstd", "brotli"])
+    .map(|s| s.parse::<Compression>().unwrap()),)]
+    compress: Option<Compression>,
+
+    /// File whose content is hidden into the image instead of the `message`
+    #[arg(long)]
+    input_file: Option<String>,
+
+    /// File where the content hidden into the image is written instead of the standard output
+    #[arg(long)]
+    output_file: Option<String>,
+
+    /// Directory where the file hidden into the image is recreated with its original name
+    #[arg(long)]
+    to_dir: Option<String>,
+
+    /// Name of the entry to inject, extract or remove. The image keeps several entries, each one
+    /// found by its name
+    #[arg(long)]
+    entry: Option<String>,
+
+    /// Password that encrypts the entry, independent of the password of the image
+    #[arg(long)]
+    entry_password: Option<String>,
+
+    /// Images (or directories of images) that share a message too long for a single image.
+    /// When injecting, the images are saved with the same names into the `output_image_path`
+    /// directory. When extracting, the images can be given in any order
+    #[arg(long, num_args = 1..)]
+    shards: Vec<String>,
+
+    /// Number of the `shards` images required to recover a shared message (default 2). Fewer
+    /// images reveal nothing about the message
+    #[arg(short = 'k', long, value_parser = clap::value_parser!(u8).range(1..))]
+    threshold: Option<u8>,
+}
+
+impl CliData {
+    /// Indicate if the message has to be read from the standard input: the mode needs a message
+    /// and neither `message` nor `input_file` provides it
+    pub fn needs_piped_message(&self) -> bool {
+        self.message.is_none()
+            && match self.mode {
+                Some(Mode::Inject) | Some(Mode::Share) => self.input_file.is_none(),
+                Some(Mode::Encrypt) | Some(Mode::Decrypt) => true,
+                _ => false,
+            }
+    }
 }
 
 /// Options to start the steganography into encrypt or decrypt
@@ -80,8 +398,21 @@ pub struct CliData {
 pub enum SteganographyOption {
     InjectMessageIntoImage(SteganographyInjectOption),
     ExtractMessageFromImage(SteganographyExtractOption),
+    CapacityOfImage(SteganographyCapacityOption),
+    ListEntriesOfImage(SteganographyExtractOption),
+    RemoveEntryFromImage(SteganographyInjectOption),
+    ShareMessageIntoImages(SteganographyInjectOption),
+    RecoverMessageFromImages(SteganographyExtractOption),
+    EncryptMessage(SteganographyEncryptOption),
+    DecryptMessage(SteganographyDecryptOption),
 }
 
+/// Number of least significant bits of each channel used when none is specified
+pub const DEFAULT_BITS_PER_CHANNEL: u8 = 1;
+
+/// Number of images required to recover a shared message when none is specified
+pub const DEFAULT_THRESHOLD: u8 = 2;
+
 /// Required options for the injection (text to image)
 #[derive(Clone)]
 pub struct SteganographyInjectOption {
@@ -89,13 +420,111 @@ pub struct SteganographyInjectOption {
     pub password: Option<String>,
     pub input_image_path: String,
     pub output_image_path: String,
+    /// `EmbeddingOrder::Scattered` requires a password
+    pub order: EmbeddingOrder,
+    /// How the bits of each channel are changed
+    pub method: EmbeddingMethod,
+    /// Embed the payload with a Hamming code to change fewer channels, see
+    /// `matrix::choose_matrix_bits`. Requires one bit per channel
+    pub matrix_embedding: bool,
+    /// Number of least significant bits of each channel that carry the message, from 1 to 4
+    pub bits_per_channel: u8,
+    /// Color channels of each pixel that carry the message
+    pub channels: ChannelMask,
+    /// File whose content is hidden instead of the message, see `inject_file`
+    pub input_file_path: Option<String>,
+    /// Compression of the message before it is encrypted
+    pub compression: Compression,
+    /// Name of the entry that receives the message in the container of the image, see
+    /// `add_entry`
+    pub entry: Option<String>,
+    /// Password that encrypts the entry (optional)
+    pub entry_password: Option<String>,
+    /// Images that share the message in place of `input_image_path`, `output_image_path` is
+    /// then the directory where they are saved, see `inject_shards`
+    pub shard_image_paths: Vec<String>,
+    /// Number of the `shard_image_paths` images required to recover the message, see
+    /// `share_message`
+    pub threshold: u8,
+    /// Reopen the saved images, extract the message and compare its hash with the hash of the
+    /// injected message
+    pub verify: bool,
+}
+
+impl Default for SteganographyInjectOption {
+    fn default() -> Self {
+        SteganographyInjectOption {
+            message: String::new(),
+            password: None,
+            input_image_path: String::new(),
+            output_image_path: String::new(),
+            order: EmbeddingOrder::default(),
+            method: EmbeddingMethod::default(),
+            matrix_embedding: false,
+            bits_per_channel: DEFAULT_BITS_PER_CHANNEL,
+            channels: ChannelMask::default(),
+            input_file_path: None,
+            compression: Compression::default(),
+            entry: None,
+            entry_password: None,
+            shard_image_paths: Vec::new(),
+            threshold: DEFAULT_THRESHOLD,
+            verify: false,
+        }
+    }
 }
 
 /// Required options for the extraction (image to text)
-#[derive(Clone)]
+#[derive(Clone, Default)]
 pub struct SteganographyExtractOption {
     pub password: Option<String>,
     pub input_image_path: String,
+    /// File where the hidden content is written instead of being returned, see `extract_file`
+    pub output_file_path: Option<String>,
+    /// Directory where the hidden file is recreated with its name, see `extract_file_to_dir`
+    pub output_dir_path: Option<String>,
+    /// Name of the entry to get from the container of the image, see `extract_entry`
+    pub entry: Option<String>,
+    /// Password that decrypts the entry (optional)
+    pub entry_password: Option<String>,
+    /// Images that share the message in place of `input_image_path`, see `extract_shards`
+    pub shard_image_paths: Vec<String>,
+}
+
+/// Required options to compute how many bytes an image can hide
+#[derive(Clone)]
+pub struct SteganographyCapacityOption {
+    pub password: Option<String>,
+    pub input_image_path: String,
+    /// Number of least significant bits of each channel that would carry the message, from 1 to 4
+    pub bits_per_channel: u8,
+    /// Color channels of each pixel that would carry the message
+    pub channels: ChannelMask,
+}
+
+impl Default for SteganographyCapacityOption {
+    fn default() -> Self {
+        SteganographyCapacityOption {
+            password: None,
+            input_image_path: String::new(),
+            bits_per_channel: DEFAULT_BITS_PER_CHANNEL,
+            channels: ChannelMask::default(),
+        }
+    }
+}
+
+/// Required options to encrypt a message without any image
+#[derive(Clone)]
+pub struct SteganographyEncryptOption {
+    pub message: String,
+    pub password: String,
+}
+
+/// Required options to decrypt a message without any image
+#[derive(Clone)]
+pub struct SteganographyDecryptOption {
+    pub message: String,
+    pub password: String,
 }
 
 /// Extract from the command line (CLI) argument the option.
@@ -116,34 +545,613 @@ pub fn extract_options(
     Ok(match args.mode {
         Some(i) => match i {
             Mode::Inject => {
-                let message = piped_message.unwrap_or_else(|| {
-                    args.message
-                        .unwrap_or_else(|| panic!("Message is required"))
-                });
+                let message = match args.input_file {
+                    // The content of the file replaces the message
+                    Some(_) => String::new(),
+                    None => args
+                        .message
+                        .or(piped_message)
+                        .ok_or("Message is required")?,
+                };
                 SteganographyOption::InjectMessageIntoImage({
                     SteganographyInjectOption {
-                        message: message,
+                        message,
                         password: args.password,
-                        input_image_path: args
-                            .input_image_path
-                            .unwrap_or_else(|| panic!("Input image path")),
+                        input_image_path: if args.shards.is_empty() {
+                            args.input_image_path
+                                .ok_or("Input image path is required")?
+                        } else {
+                            String::new()
+                        },
                         output_image_path: args
                             .output_image_path
-                            .unwrap_or_else(|| panic!("Output image path is required")),
+                            .ok_or("Output image path is required")?,
+                        order: args.order.unwrap_or_default(),
+                        method: args.method.unwrap_or_default(),
+                        matrix_embedding: args.matrix,
+                        bits_per_channel: args.bits_per_channel.unwrap_or(DEFAULT_BITS_PER_CHANNEL),
+                        channels: args.channels.unwrap_or_default(),
+                        input_file_path: args.input_file,
+                        compression: args.compress.unwrap_or_default(),
+                        entry: args.entry,
+                        entry_password: args.entry_password,
+                        shard_image_paths: args.shards,
+                        threshold: args.threshold.unwrap_or(DEFAULT_THRESHOLD),
+                        verify: args.verify,
                     }
                 })
             }
             Mode::Extract => SteganographyOption::ExtractMessageFromImage({
                 SteganographyExtractOption {
+                    password: args.password,
+                    input_image_path: if args.shards.is_empty() {
+                        args.input_image_path.ok_or("Input image is required")?
+                    } else {
+                        String::new()
+                    },
+                    output_file_path: args.output_file,
+                    output_dir_path: args.to_dir,
+                    entry: args.entry,
+                    entry_password: args.entry_password,
+                    shard_image_paths: args.shards,
+                }
+            }),
+            Mode::List => SteganographyOption::ListEntriesOfImage({
+                SteganographyExtractOption {
+                    password: args.password,
+                    input_image_path: args.input_image_path.ok_or("Input image is required")?,
+                    ..Default::default()
+                }
+            }),
+            Mode::Remove => SteganographyOption::RemoveEntryFromImage({
+                SteganographyInjectOption {
                     password: args.password,
                     input_image_path: args
                         .input_image_path
-                        .unwrap_or_else(|| panic!("Input image is required")),
+                        .ok_or("Input image path is required")?,
+                    output_image_path: args
+                        .output_image_path
+                        .ok_or("Output image path is required")?,
+                    order: args.order.unwrap_or_default(),
+                    method: args.method.unwrap_or_default(),
+                    matrix_embedding: args.matrix,
+                    bits_per_channel: args.bits_per_channel.unwrap_or(DEFAULT_BITS_PER_CHANNEL),
+                    channels: args.channels.unwrap_or_default(),
+                    compression: args.compress.unwrap_or_default(),
+                    entry: Some(args.entry.ok_or("Entry is required")?),
+                    ..Default::default()
+                }
+            }),
+            Mode::Share => {
+                let message = match args.input_file {
+                    Some(_) => String::new(),
+                    None => args
+                        .message
+                        .or(piped_message)
+                        .ok_or("Message is required")?,
+                };
+                if args.shards.is_empty() {
+                    return Err("Shards are required".to_string());
+                }
+                SteganographyOption::ShareMessageIntoImages({
+                    SteganographyInjectOption {
+                        message,
+                        password: args.password,
+                        output_image_path: args
+                            .output_image_path
+                            .ok_or("Output image path is required")?,
+                        order: args.order.unwrap_or_default(),
+                        method: args.method.unwrap_or_default(),
+                        matrix_embedding: args.matrix,
+                        bits_per_channel: args.bits_per_channel.unwrap_or(DEFAULT_BITS_PER_CHANNEL),
+                        channels: args.channels.unwrap_or_default(),
+                        input_file_path: args.input_file,
+                        compression: args.compress.unwrap_or_default(),
+                        shard_image_paths: args.shards,
+                        threshold: args.threshold.unwrap_or(DEFAULT_THRESHOLD),
+                        verify: args.verify,
+                        ..Default::default()
+                    }
+                })
+            }
+            Mode::Recover => SteganographyOption::RecoverMessageFromImages({
+                if args.shards.is_empty() {
+                    return Err("Shards are required".to_string());
+                }
+                SteganographyExtractOption {
+                    password: args.password,
+                    output_file_path: args.output_file,
+                    shard_image_paths: args.shards,
+                    ..Default::default()
+                }
+            }),
+            Mode::Capacity => SteganographyOption::CapacityOfImage({
+                SteganographyCapacityOption {
+                    password: args.password,
+                    input_image_path: args.input_image_path.ok_or("Input image is required")?,
+                    bits_per_channel: args.bits_per_channel.unwrap_or(DEFAULT_BITS_PER_CHANNEL),
+                    channels: args.channels.unwrap_or_default(),
+                }
+            }),
+            Mode::Encrypt => SteganographyOption::EncryptMessage({
+                SteganographyEncryptOption {
+                    message: args
+                        .message
+                        .or(piped_message)
+                        .ok_or("Message is required")?,
+                    password: args.password.ok_or("Password is required")?,
+                }
+            }),
+            Mode::Decrypt => SteganographyOption::DecryptMessage({
+                SteganographyDecryptOption {
+                    message: args
+                        .message
+                        .or(piped_message)
+                        .ok_or("Message is required")?,
+                    password: args.password.ok_or("Password is required")?,
                 }
             }),
-            Mode::Encrypt => todo!(""),
-            Mode::Decrypt => todo!(""),
         },
-        None => panic!("Encrypt mode is required"),
+        None => return Err("Encrypt mode is required".to_string()),
     })
 }
+
+#[cfg(test)]
+mod test_options {
+    use super::*;
+
+    #[test]
+    fn test_extract_options_encrypt() {
+        let args =
+            CliData::parse_from(["steganographyrs", "-e", "encrypt", "-m", "Hi", "-p", "pw"]);
+        let options = extract_options(args, None).unwrap();
+        match options {
+            SteganographyOption::EncryptMessage(o) => {
+                assert_eq!(o.message, "Hi");
+                assert_eq!(o.password, "pw");
+            }
+            _ => panic!("Expected the encrypt option"),
+        }
+    }
+
+    #[test]
+    fn test_extract_options_decrypt_from_pipe() {
+        let args = CliData::parse_from(["steganographyrs", "-e", "decrypt", "-p", "pw"]);
+        let options = extract_options(args, Some("Piped".to_string())).unwrap();
+        match options {
+            SteganographyOption::DecryptMessage(o) => assert_eq!(o.message, "Piped"),
+            _ => panic!("Expected the decrypt option"),
+        }
+    }
+
+    #[test]
+    fn test_extract_options_message_over_pipe() {
+        let args =
+            CliData::parse_from(["steganographyrs", "-e", "encrypt", "-m", "Hi", "-p", "pw"]);
+        let options = extract_options(args, Some("Piped".to_string())).unwrap();
+        match options {
+            SteganographyOption::EncryptMessage(o) => assert_eq!(o.message, "Hi"),
+            _ => panic!("Expected the encrypt option"),
+        }
+    }
+
+    #[test]
+    fn test_needs_piped_message() {
+        let needs = |arguments: &[&str]| CliData::parse_from(arguments).needs_piped_message();
+        assert!(needs(&["steganographyrs", "-e", "decrypt", "-p", "pw"]));
+        assert!(needs(&[
+            "steganographyrs",
+            "-e",
+            "inject",
+            "-i",
+            "in.png",
+            "-o",
+            "out.png"
+        ]));
+        assert!(!needs(&[
+            "steganographyrs",
+            "-e",
+            "encrypt",
+            "-m",
+            "Hi",
+            "-p",
+            "pw"
+        ]));
+        assert!(!needs(&[
+            "steganographyrs",
+            "-e",
+            "inject",
+            "--input-file",
+            "in.txt",
+            "-i",
+            "in.png",
+            "-o",
+            "out.png"
+        ]));
+        assert!(!needs(&[
+            "steganographyrs",
+            "-e",
+            "extract",
+            "-i",
+            "in.png"
+        ]));
+    }
+
+    #[test]
+    fn test_extract_options_inject_scattered() {
+        let args = CliData::parse_from([
+            "steganographyrs",
+            "-e",
+            "inject",
+            "-m",
+            "Hi",
+            "-p",
+            "pw",
+            "-i",
+            "in.png",
+            "-o",
+            "out.png",
+            "--order",
+            "scattered",
+        ]);
+        match extract_options(args, None).unwrap() {
+            SteganographyOption::InjectMessageIntoImage(o) => {
+                assert_eq!(o.order, EmbeddingOrder::Scattered)
+            }
+            _ => panic!("Expected the inject option"),
+        }
+    }
+
+    #[test]
+    fn test_extract_options_inject_adaptive() {
+        let args = CliData::parse_from([
+            "steganographyrs",
+            "-e",
+            "inject",
+            "-m",
+            "Hi",
+            "-i",
+            "in.png",
+            "-o",
+            "out.png",
+            "--order",
+            "adaptive",
+        ]);
+        match extract_options(args, None).unwrap() {
+            SteganographyOption::InjectMessageIntoImage(o) => {
+                assert_eq!(o.order, EmbeddingOrder::Adaptive)
+            }
+            _ => panic!("Expected the inject option"),
+        }
+    }
+
+    #[test]
+    fn test_extract_options_inject_matching() {
+        let args = CliData::parse_from([
+            "steganographyrs",
+            "-e",
+            "inject",
+            "-m",
+            "Hi",
+            "-i",
+            "in.png",
+            "-o",
+            "out.png",
+            "--method",
+            "matching",
+        ]);
+        match extract_options(args, None).unwrap() {
+            SteganographyOption::InjectMessageIntoImage(o) => {
+                assert_eq!(o.method, EmbeddingMethod::Matching)
+            }
+            _ => panic!("Expected the inject option"),
+        }
+    }
+
+    #[test]
+    fn test_extract_options_inject_matrix() {
+        let args = CliData::parse_from([
+            "steganographyrs",
+            "-e",
+            "inject",
+            "-m",
+            "Hi",
+            "-i",
+            "in.png",
+            "-o",
+            "out.png",
+            "--matrix",
+        ]);
+        match extract_options(args, None).unwrap() {
+            SteganographyOption::InjectMessageIntoImage(o) => assert!(o.matrix_embedding),
+            _ => panic!("Expected the inject option"),
+        }
+    }
+
+    #[test]
+    fn test_extract_options_inject_verify() {
+        let args = CliData::parse_from([
+            "steganographyrs",
+            "-e",
+            "inject",
+            "-m",
+            "Hi",
+            "-i",
+            "in.png",
+            "-o",
+            "out.png",
+            "--verify",
+        ]);
+        match extract_options(args, None).unwrap() {
+            SteganographyOption::InjectMessageIntoImage(o) => assert!(o.verify),
+            _ => panic!("Expected the inject option"),
+        }
+    }
+
+    #[test]
+    fn test_extract_options_capacity_bits_per_channel() {
+        let args = CliData::parse_from([
+            "steganographyrs",
+            "-e",
+            "capacity",
+            "-i",
+            "in.png",
+            "-b",
+            "3",
+        ]);
+        match extract_options(args, None).unwrap() {
+            SteganographyOption::CapacityOfImage(o) => assert_eq!(o.bits_per_channel, 3),
+            _ => panic!("Expected the capacity option"),
+        }
+    }
+
+    #[test]
+    fn test_cli_refuses_five_bits_per_channel() {
+        let result = CliData::try_parse_from(["steganographyrs", "-e", "capacity", "-b", "5"]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_channel_mask_from_str() {
+        let mask: ChannelMask = "bR".parse().unwrap();
+        assert_eq!(mask.bits(), 0b0101);
+        assert_eq!(mask.sample_count(4), 2);
+        assert_eq!(mask.to_string(), "rb");
+        assert!("rr".parse::<ChannelMask>().is_err());
+        assert!("x".parse::<ChannelMask>().is_err());
+        assert!("".parse::<ChannelMask>().is_err());
+    }
+
+    #[test]
+    fn test_channel_mask_on_grayscale() {
+        let mask: ChannelMask = "ba".parse().unwrap();
+        assert!(mask.selects(0, 1));
+        assert!(mask.selects(1, 2));
+        assert_eq!(mask.sample_count(2), 2);
+        assert_eq!(mask.sample_count(3), 1);
+        let alpha: ChannelMask = "a".parse().unwrap();
+        assert_eq!(alpha.sample_count(1), 0);
+        assert_eq!(alpha.sample_count(3), 0);
+    }
+
+    #[test]
+    fn test_extract_options_inject_channels() {
+        let args = CliData::parse_from([
+            "steganographyrs",
+            "-e",
+            "inject",
+            "-m",
+            "Hi",
+            "-i",
+            "in.png",
+            "-o",
+            "out.png",
+            "-c",
+            "rgb",
+        ]);
+        match extract_options(args, None).unwrap() {
+            SteganographyOption::InjectMessageIntoImage(o) => {
+                assert_eq!(o.channels, ChannelMask::RGB)
+            }
+            _ => panic!("Expected the inject option"),
+        }
+    }
+
+    #[test]
+    fn test_extract_options_compress() {
+        let base = [
+            "steganographyrs",
+            "-e",
+            "inject",
+            "-m",
+            "Hi",
+            "-i",
+            "in.png",
+            "-o",
+            "out.png",
+        ];
+        let args = CliData::parse_from(base.iter().chain(["--compress"].iter()));
+        match extract_options(args, None).unwrap() {
+            SteganographyOption::InjectMessageIntoImage(o) => {
+                assert_eq!(o.compression, Compression::Deflate)
+            }
+            _ => panic!("Expected the inject option"),
+        }
+        let args = CliData::parse_from(base.iter().chain(["--compress", "brotli"].iter()));
+        match extract_options(args, None).unwrap() {
+            SteganographyOption::InjectMessageIntoImage(o) => {
+                assert_eq!(o.compression, Compression::Brotli)
+            }
+            _ => panic!("Expected the inject option"),
+        }
+    }
+
+    #[test]
+    fn test_extract_options_inject_file_without_message() {
+        let args = CliData::parse_from([
+            "steganographyrs",
+            "-e",
+            "inject",
+            "-i",
+            "in.png",
+            "-o",
+            "out.png",
+            "--input-file",
+            "secret.zip",
+        ]);
+        match extract_options(args, None).unwrap() {
+            SteganographyOption::InjectMessageIntoImage(o) => {
+                assert_eq!(o.input_file_path, Some("secret.zip".to_string()))
+            }
+            _ => panic!("Expected the inject option"),
+        }
+    }
+
+    #[test]
+    fn test_extract_options_extract_output_file() {
+        let args = CliData::parse_from([
+            "steganographyrs",
+            "-e",
+            "extract",
+            "-i",
+            "in.png",
+            "--output-file",
+            "secret.zip",
+        ]);
+        match extract_options(args, None).unwrap() {
+            SteganographyOption::ExtractMessageFromImage(o) => {
+                assert_eq!(o.output_file_path, Some("secret.zip".to_string()))
+            }
+            _ => panic!("Expected the extract option"),
+        }
+    }
+
+    #[test]
+    fn test_extract_options_extract_to_dir() {
+        let args = CliData::parse_from([
+            "steganographyrs",
+            "-e",
+            "extract",
+            "-i",
+            "in.png",
+            "--to-dir",
+            "documents",
+        ]);
+        match extract_options(args, None).unwrap() {
+            SteganographyOption::ExtractMessageFromImage(o) => {
+                assert_eq!(o.output_dir_path, Some("documents".to_string()))
+            }
+            _ => panic!("Expected the extract option"),
+        }
+    }
+
+    #[test]
+    fn test_extract_options_entry() {
+        let args = CliData::parse_from([
+            "steganographyrs",
+            "-e",
+            "extract",
+            "-i",
+            "in.png",
+            "--entry",
+            "key",
+            "--entry-password",
+            "secret",
+        ]);
+        match extract_options(args, None).unwrap() {
+            SteganographyOption::ExtractMessageFromImage(o) => {
+                assert_eq!(o.entry, Some("key".to_string()));
+                assert_eq!(o.entry_password, Some("secret".to_string()))
+            }
+            _ => panic!("Expected the extract option"),
+        }
+    }
+
+    #[test]
+    fn test_extract_options_remove_without_entry() {
+        let args = CliData::parse_from([
+            "steganographyrs",
+            "-e",
+            "remove",
+            "-i",
+            "in.png",
+            "-o",
+            "out.png",
+        ]);
+        assert_eq!(
+            extract_options(args, None).err(),
+            Some("Entry is required".to_string())
+        );
+    }
+
+    #[test]
+    fn test_extract_options_shards() {
+        let args = CliData::parse_from([
+            "steganographyrs",
+            "-e",
+            "extract",
+            "--shards",
+            "b.png",
+            "a.png",
+        ]);
+        match extract_options(args, None).unwrap() {
+            SteganographyOption::ExtractMessageFromImage(o) => {
+                assert_eq!(o.shard_image_paths, vec!["b.png", "a.png"])
+            }
+            _ => panic!("Expected the extract option"),
+        }
+    }
+
+    #[test]
+    fn test_extract_options_share() {
+        let args = CliData::parse_from([
+            "steganographyrs",
+            "-e",
+            "share",
+            "-m",
+            "Hi",
+            "-o",
+            "out",
+            "-k",
+            "3",
+            "--shards",
+            "a.png",
+            "b.png",
+            "c.png",
+        ]);
+        match extract_options(args, None).unwrap() {
+            SteganographyOption::ShareMessageIntoImages(o) => {
+                assert_eq!(o.threshold, 3);
+                assert_eq!(o.shard_image_paths, vec!["a.png", "b.png", "c.png"]);
+            }
+            _ => panic!("Expected the share option"),
+        }
+    }
+
+    #[test]
+    fn test_extract_options_without_mode() {
+        let args = CliData::parse_from(["steganographyrs", "-m", "Hi"]);
+        assert_eq!(
+            extract_options(args, None).err(),
+            Some("Encrypt mode is required".to_string())
+        );
+    }
+
+    #[test]
+    fn test_extract_options_recover_without_shards() {
+        let args = CliData::parse_from(["steganographyrs", "-e", "recover"]);
+        assert_eq!(
+            extract_options(args, None).err(),
+            Some("Shards are required".to_string())
+        );
+    }
+
+    #[test]
+    fn test_extract_options_encrypt_without_password() {
+        let args = CliData::parse_from(["steganographyrs", "-e", "encrypt", "-m", "Hi"]);
+        assert_eq!(
+            extract_options(args, None).err(),
+            Some("Password is required".to_string())
+        );
+    }
+}
@@ -0,0 +1,768 @@
+use std::borrow::Cow;
+use std::io::Cursor;
+
+use gif::{ColorOutput, DecodeOptions, Repeat};
+use image::error::{DecodingError, EncodingError};
+use image::{ImageError, ImageFormat};
+use png::{
+    AnimationControl, BitDepth, ColorType, FrameControl, PixelDimensions, ScaledFloat,
+    SourceChromaticities, SrgbRenderingIntent, Transformations,
+};
+
+use super::error::SteganographyError;
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+/// Position of the color type in a PNG file: after the signature, the length and the type of
+/// the IHDR chunk, the width, the height and the bit depth
+const PNG_COLOR_TYPE_POSITION: usize = 25;
+const PNG_INDEXED: u8 = 3;
+const GIF_SIGNATURES: [&[u8]; 2] = [b"GIF87a", b"GIF89a"];
+
+/// Largest number of colors of a palette
+const PALETTE_LENGTH: usize = 256;
+
+/// The format of the file if it is an image with a palette: a PNG image of the indexed color
+/// type or a GIF image
+///
+/// # Arguments
+/// * `bytes` - The content of the file, only its first bytes are read
+pub fn palette_format(bytes: &[u8]) -> Option<ImageFormat> {
+    if bytes.starts_with(&PNG_SIGNATURE) && bytes.get(PNG_COLOR_TYPE_POSITION) == Some(&PNG_INDEXED)
+    {
+        Some(ImageFormat::Png)
+    } else if GIF_SIGNATURES.iter().any(|s| bytes.starts_with(s)) {
+        Some(ImageFormat::Gif)
+    } else {
+        None
+    }
+}
+
+/// An image with a palette, decoded to the index of the color of each pixel. Changing an index
+/// to an arbitrary one changes the color arbitrarily, so the palette is sorted by luminance (as
+/// EzStego does) and the colors are paired two by two in that order: the rank of an index in the
+/// sorted palette is its carrier, the least significant bit of the rank is the bit of the message
+/// and writing it swaps the index with the one of the other color of the pair, which looks alike.
+///
+/// The image is encoded back in its format with the same palette, so it keeps its color type,
+/// its size on disk and its appearance. An animated image (GIF or APNG) carries the message in
+/// all its frames and keeps their position, delay and disposal
+pub struct PaletteImage {
+    frames: Vec<PaletteFrame>,
+    encoding: Encoding,
+}
+
+/// The pixels of a frame with the pairs of colors of its palette
+struct PaletteFrame {
+    indices: Vec<u8>,
+    /// The rank of each index in the sorted palette, `None` for an index without a color of the
+    /// same transparency to swap with
+    ranks: Vec<Option<u8>>,
+    /// The index at each rank, the paired colors sorted by luminance
+    sorted: Vec<u8>,
+}
+
+/// What is needed to encode the image back, besides the indices
+enum Encoding {
+    Png(PngEncoding),
+    Gif(GifEncoding),
+}
+
+struct PngEncoding {
+    width: u32,
+    height: u32,
+    bit_depth: BitDepth,
+    palette: Vec<u8>,
+    trns: Option<Vec<u8>>,
+    source_gamma: Option<ScaledFloat>,
+    source_chromaticities: Option<SourceChromaticities>,
+    srgb: Option<SrgbRenderingIntent>,
+    pixel_dims: Option<PixelDimensions>,
+    animation: Option<AnimationControl>,
+    /// The position, delay, disposal and blending of each frame, `None` for the default image
+    /// of an APNG that is not part of the animation and for an image that is not animated
+    frame_controls: Vec<Option<FrameControl>>,
+}
+
+struct GifEncoding {
+    width: u16,
+    height: u16,
+    global_palette: Option<Vec<u8>>,
+    repeat: Repeat,
+    /// The frames without their pixels, with their position, delay and disposal
+    frames: Vec<gif::Frame<'static>>,
+}
+
+impl PaletteFrame {
+    /// # Arguments
+    /// * `indices` - The index of the color of each pixel
+    /// * `colors` - The palette, one RGBA color per index
+    fn new(indices: Vec<u8>, colors: &[[u8; 4]]) -> Self {
+        let colors = &colors[..colors.len().min(PALETTE_LENGTH)];
+        let mut by_luminance = (0..colors.len() as u16)
+            .map(|index| index as u8)
+            .collect::<Vec<u8>>();
+        by_luminance.sort_by_key(|&index| {
+            let [r, g, b, a] = colors[index as usize];
+            (a, 299 * r as u32 + 587 * g as u32 + 114 * b as u32)
+        });
+        // Two neighbours of the same transparency make a pair, their ranks differ by the last
+        // bit. A transparent color is never swapped with an opaque one
+        let mut sorted = Vec::with_capacity(by_luminance.len());
+        let mut position = 0;
+        while position + 1 < by_luminance.len() {
+            let (index, next) = (by_luminance[position], by_luminance[position + 1]);
+            if colors[index as usize][3] == colors[next as usize][3] {
+                sorted.extend([index, next]);
+                position += 2;
+            } else {
+                position += 1;
+            }
+        }
+        let mut ranks = vec![None; PALETTE_LENGTH];
+        for (rank, &index) in sorted.iter().enumerate() {
+            ranks[index as usize] = Some(rank as u8);
+        }
+        PaletteFrame {
+            indices,
+            ranks,
+            sorted,
+        }
+    }
+}
+
+impl PaletteImage {
+    /// Decode a PNG image of the indexed color type or a GIF image
+    ///
+    /// # Arguments
+    /// * `bytes` - The content of the file
+    ///
+    /// # Errors
+    ///
+    /// Returns `UnsupportedFormat` if the image has no palette, `Image` if the content is not a
+    /// valid image
+    pub fn decode(bytes: &[u8]) -> Result<Self, SteganographyError> {
+        match palette_format(bytes) {
+            Some(ImageFormat::Png) => decode_png(bytes),
+            Some(ImageFormat::Gif) => decode_gif(bytes),
+            _ => Err(SteganographyError::UnsupportedFormat(
+                "The image has no palette, only PNG images of the indexed color type and GIF images have one".to_string(),
+            )),
+        }
+    }
+
+    /// The ranks in the sorted palette of the pixels that carry the message (see `PaletteImage`),
+    /// frame after frame, row after row
+    pub fn carriers(&self) -> Vec<u8> {
+        self.frames
+            .iter()
+            .flat_map(|frame| {
+                frame
+                    .indices
+                    .iter()
+                    .filter_map(|&index| frame.ranks[index as usize])
+            })
+            .collect()
+    }
+
+    /// Replace the pixels that carry the message, in the order of `carriers`
+    ///
+    /// # Arguments
+    /// * `carriers` - The ranks returned by `carriers`, once modified. A rank can only change
+    ///   its least significant bit, to the other color of its pair, any other rank is ignored
+    pub fn set_carriers(&mut self, carriers: &[u8]) {
+        let mut carriers = carriers.iter();
+        for frame in self.frames.iter_mut() {
+            for index in frame.indices.iter_mut() {
+                if let Some(rank) = frame.ranks[*index as usize] {
+                    if let Some(&carrier) = carriers.next() {
+                        if carrier >> 1 == rank >> 1 {
+                            *index = frame.sorted[carrier as usize];
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Encode the image back to the content of a file of its format, with the same palette
+    ///
+    /// # Errors
+    ///
+    /// Returns `Image` if the image cannot be encoded
+    pub fn encode(&self) -> Result<Vec<u8>, SteganographyError> {
+        match &self.encoding {
+            Encoding::Png(encoding) => encode_png(encoding, &self.frames),
+            Encoding::Gif(encoding) => encode_gif(encoding, &self.frames),
+        }
+    }
+}
+
+fn decoding_error<E>(format: ImageFormat, error: E) -> SteganographyError
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    SteganographyError::Image(ImageError::Decoding(DecodingError::new(
+        format.into(),
+        error,
+    )))
+}
+
+fn encoding_error<E>(format: ImageFormat, error: E) -> SteganographyError
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    SteganographyError::Image(ImageError::Encoding(EncodingError::new(
+        format.into(),
+        error,
+    )))
+}
+
+fn decode_png(bytes: &[u8]) -> Result<PaletteImage, SteganographyError> {
+    let png_error = |e| decoding_error(ImageFormat::Png, e);
+    let mut decoder = png::Decoder::new(Cursor::new(bytes));
+    // The indices are read as is, without expanding them to their colors
+    decoder.set_transformations(Transformations::IDENTITY);
+    let mut reader = decoder.read_info().map_err(png_error)?;
+    let info = reader.info();
+    if info.color_type != ColorType::Indexed {
+        return Err(SteganographyError::UnsupportedFormat(
+            "The PNG image is not of the indexed color type".to_string(),
+        ));
+    }
+    let palette = info
+        .palette
+        .as_ref()
+        .ok_or_else(|| decoding_error(ImageFormat::Png, "The PNG image has no palette"))?
+        .to_vec();
+    // Without a frame control before its data, the default image is a frame that is not part
+    // of the animation
+    let frame_count = match info.animation_control {
+        Some(animation) => animation.num_frames as usize + info.frame_control.is_none() as usize,
+        None => 1,
+    };
+    let mut encoding = PngEncoding {
+        width: info.width,
+        height: info.height,
+        bit_depth: info.bit_depth,
+        trns: info.trns.as_ref().map(|trns| trns.to_vec()),
+        source_gamma: info.source_gamma,
+        source_chromaticities: info.source_chromaticities,
+        srgb: info.srgb,
+        pixel_dims: info.pixel_dims,
+        animation: info.animation_control,
+        frame_controls: Vec::with_capacity(frame_count),
+        palette,
+    };
+    let colors = png_colors(&encoding.palette, encoding.trns.as_deref());
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let mut frames = Vec::with_capacity(frame_count);
+    for _ in 0..frame_count {
+        let output = reader.next_frame(&mut buffer).map_err(png_error)?;
+        let indices = unpack_indices(
+            &buffer,
+            output.line_size,
+            output.width as usize,
+            output.height as usize,
+            encoding.bit_depth as u8,
+        );
+        frames.push(PaletteFrame::new(indices, &colors));
+        // The frame control of the frame just read
+        let frame_control = encoding.animation.and(reader.info().frame_control);
+        encoding.frame_controls.push(frame_control);
+    }
+    Ok(PaletteImage {
+        frames,
+        encoding: Encoding::Png(encoding),
+    })
+}
+
+/// The RGBA colors of a PNG palette, opaque unless the tRNS chunk gives their alpha
+fn png_colors(palette: &[u8], trns: Option<&[u8]>) -> Vec<[u8; 4]> {
+    palette
+        .chunks_exact(3)
+        .enumerate()
+        .map(|(index, rgb)| {
+            let alpha = trns.and_then(|t| t.get(index)).copied().unwrap_or(u8::MAX);
+            [rgb[0], rgb[1], rgb[2], alpha]
+        })
+        .collect()
+}
+
+/// One index per byte from rows of indices of `depth` bits, each row starting on a new byte
+fn unpack_indices(
+    buffer: &[u8],
+    line_size: usize,
+    width: usize,
+    height: usize,
+    depth: u8,
+) -> Vec<u8> {
+    let depth = depth as usize;
+    let mask = ((1u16 << depth) - 1) as u8;
+    let mut indices = Vec::with_capacity(width * height);
+    for line in buffer.chunks(line_size).take(height) {
+        for x in 0..width {
+            let bit = x * depth;
+            let shift = 8 - depth - bit % 8;
+            indices.push((line[bit / 8] >> shift) & mask);
+        }
+    }
+    indices
+}
+
+/// The rows of indices of `depth` bits, the reverse of `unpack_indices`
+fn pack_indices(indices: &[u8], width: usize, depth: u8) -> Vec<u8> {
+    let depth = depth as usize;
+    let line_size = (width * depth).div_ceil(8);
+    let mut buffer = Vec::with_capacity(line_size * indices.len() / width.max(1));
+    for row in indices.chunks(width) {
+        let mut line = vec![0u8; line_size];
+        for (x, index) in row.iter().enumerate() {
+            let bit = x * depth;
+            line[bit / 8] |= index << (8 - depth - bit % 8);
+        }
+        buffer.extend(line);
+    }
+    buffer
+}
+
+fn encode_png(
+    encoding: &PngEncoding,
+    frames: &[PaletteFrame],
+) -> Result<Vec<u8>, SteganographyError> {
+    let png_error = |e| encoding_error(ImageFormat::Png, e);
+    let mut output = Vec::new();
+    let mut encoder = png::Encoder::new(&mut output, encoding.width, encoding.height);
+    encoder.set_color(ColorType::Indexed);
+    encoder.set_depth(encoding.bit_depth);
+    encoder.set_palette(encoding.palette.as_slice());
+    if let Some(trns) = &encoding.trns {
+        encoder.set_trns(trns.as_slice());
+    }
+    if let Some(gamma) = encoding.source_gamma {
+        encoder.set_source_gamma(gamma);
+    }
+    if let Some(chromaticities) = encoding.source_chromaticities {
+        encoder.set_source_chromaticities(chromaticities);
+    }
+    if let Some(intent) = encoding.srgb {
+        encoder.set_source_srgb(intent);
+    }
+    encoder.set_pixel_dims(encoding.pixel_dims);
+    if let Some(animation) = encoding.animation {
+        encoder
+            .set_animated(animation.num_frames, animation.num_plays)
+            .map_err(png_error)?;
+        let separate_default_image = matches!(encoding.frame_controls.first(), Some(None));
+        encoder
+            .set_sep_def_img(separate_default_image)
+            .map_err(png_error)?;
+    }
+    let mut writer = encoder.write_header().map_err(png_error)?;
+    for (frame, frame_control) in frames.iter().zip(&encoding.frame_controls) {
+        let mut width = encoding.width;
+        if let Some(control) = frame_control {
+            // The position goes back to the corner first, the size is checked against it
+            writer.reset_frame_position().map_err(png_error)?;
+            writer
+                .set_frame_dimension(control.width, control.height)
+                .map_err(png_error)?;
+            writer
+                .set_frame_position(control.x_offset, control.y_offset)
+                .map_err(png_error)?;
+            writer
+                .set_frame_delay(control.delay_num, control.delay_den)
+                .map_err(png_error)?;
+            writer
+                .set_dispose_op(control.dispose_op)
+                .map_err(png_error)?;
+            writer.set_blend_op(control.blend_op).map_err(png_error)?;
+            width = control.width;
+        }
+        writer
+            .write_image_data(&pack_indices(
+                &frame.indices,
+                width as usize,
+                encoding.bit_depth as u8,
+            ))
+            .map_err(png_error)?;
+    }
+    writer.finish().map_err(png_error)?;
+    Ok(output)
+}
+
+fn decode_gif(bytes: &[u8]) -> Result<PaletteImage, SteganographyError> {
+    let gif_error = |e| decoding_error(ImageFormat::Gif, e);
+    let mut options = DecodeOptions::new();
+    options.set_color_output(ColorOutput::Indexed);
+    let mut decoder = options.read_info(Cursor::new(bytes)).map_err(gif_error)?;
+    let global_palette = decoder.global_palette().map(|palette| palette.to_vec());
+    let mut frames = Vec::new();
+    let mut metadata = Vec::new();
+    while let Some(frame) = decoder.read_next_frame().map_err(gif_error)? {
+        let mut frame = frame.clone();
+        let palette = frame
+            .palette
+            .as_ref()
+            .or(global_palette.as_ref())
+            .ok_or_else(|| {
+                decoding_error(ImageFormat::Gif, "A frame of the GIF image has no palette")
+            })?;
+        let mut colors = png_colors(palette, None);
+        if let Some(color) = frame
+            .transparent
+            .and_then(|index| colors.get_mut(index as usize))
+        {
+            color[3] = 0;
+        }
+        let indices = std::mem::take(&mut frame.buffer).into_owned();
+        frames.push(PaletteFrame::new(indices, &colors));
+        metadata.push(frame);
+    }
+    if frames.is_empty() {
+        return Err(decoding_error(
+            ImageFormat::Gif,
+            "The GIF image has no frame",
+        ));
+    }
+    Ok(PaletteImage {
+        frames,
+        encoding: Encoding::Gif(GifEncoding {
+            width: decoder.width(),
+            height: decoder.height(),
+            global_palette,
+            repeat: decoder.repeat(),
+            frames: metadata,
+        }),
+    })
+}
+
+fn encode_gif(
+    encoding: &GifEncoding,
+    frames: &[PaletteFrame],
+) -> Result<Vec<u8>, SteganographyError> {
+    let gif_error = |e| encoding_error(ImageFormat::Gif, e);
+    let mut output = Vec::new();
+    let mut encoder = gif::Encoder::new(
+        &mut output,
+        encoding.width,
+        encoding.height,
+        encoding.global_palette.as_deref().unwrap_or(&[]),
+    )
+    .map_err(gif_error)?;
+    if encoding.repeat != Repeat::default() {
+        encoder.set_repeat(encoding.repeat).map_err(gif_error)?;
+    }
+    for (metadata, frame) in encoding.frames.iter().zip(frames) {
+        // The rows were read in their display order, they are written in that order
+        let frame = gif::Frame {
+            buffer: Cow::Borrowed(&frame.indices),
+            interlaced: false,
+            ..metadata.clone()
+        };
+        encoder.write_frame(&frame).map_err(gif_error)?;
+    }
+    drop(encoder);
+    Ok(output)
+}
+
+#[cfg(test)]
+pub(crate) mod test_palette {
+    use super::*;
+    use png::{BlendOp, DisposeOp};
+
+    /// The test image reduced to a palette of 6 levels of red, green and blue
+    pub(crate) fn palette_indices() -> (u32, u32, Vec<u8>, Vec<u8>) {
+        let img = image::open("testAssets/prestine.png").unwrap().to_rgb8();
+        let palette = (0..216u32)
+            .flat_map(|i| {
+                [
+                    (i / 36 * 51) as u8,
+                    (i / 6 % 6 * 51) as u8,
+                    (i % 6 * 51) as u8,
+                ]
+            })
+            .collect::<Vec<u8>>();
+        let indices = img
+            .pixels()
+            .map(|p| {
+                let level = |c: u8| (c as u32 + 25) / 51;
+                (level(p[0]) * 36 + level(p[1]) * 6 + level(p[2])) as u8
+            })
+            .collect::<Vec<u8>>();
+        (img.width(), img.height(), palette, indices)
+    }
+
+    pub(crate) fn palette_png() -> Vec<u8> {
+        let (width, height, palette, indices) = palette_indices();
+        let mut output = Vec::new();
+        let mut encoder = png::Encoder::new(&mut output, width, height);
+        encoder.set_color(ColorType::Indexed);
+        encoder.set_depth(BitDepth::Eight);
+        encoder.set_palette(palette);
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(&indices).unwrap();
+        writer.finish().unwrap();
+        output
+    }
+
+    pub(crate) fn palette_gif() -> Vec<u8> {
+        let (width, height, palette, indices) = palette_indices();
+        let mut output = Vec::new();
+        let mut encoder =
+            gif::Encoder::new(&mut output, width as u16, height as u16, &palette).unwrap();
+        let frame = gif::Frame {
+            width: width as u16,
+            height: height as u16,
+            buffer: Cow::Owned(indices),
+            ..Default::default()
+        };
+        encoder.write_frame(&frame).unwrap();
+        drop(encoder);
+        output
+    }
+
+    /// The frames of the animated test images: the whole image, a part of it moved by 8 pixels
+    /// and the whole image upside down, as (left, top, width, height, indices)
+    fn animation_frames() -> Vec<(u32, u32, u32, u32, Vec<u8>)> {
+        let (width, height, _, indices) = palette_indices();
+        let part = indices
+            .chunks(width as usize)
+            .skip(8)
+            .take(48)
+            .flat_map(|row| row[8..72].to_vec())
+            .collect::<Vec<u8>>();
+        let upside_down = indices
+            .chunks(width as usize)
+            .rev()
+            .flatten()
+            .copied()
+            .collect::<Vec<u8>>();
+        vec![
+            (0, 0, width, height, indices),
+            (8, 8, 64, 48, part),
+            (0, 0, width, height, upside_down),
+        ]
+    }
+
+    pub(crate) fn animated_gif() -> Vec<u8> {
+        let (width, height, palette, _) = palette_indices();
+        let mut output = Vec::new();
+        let mut encoder =
+            gif::Encoder::new(&mut output, width as u16, height as u16, &palette).unwrap();
+        encoder.set_repeat(Repeat::Infinite).unwrap();
+        let disposals = [
+            gif::DisposalMethod::Keep,
+            gif::DisposalMethod::Background,
+            gif::DisposalMethod::Previous,
+        ];
+        for (number, (left, top, width, height, indices)) in
+            animation_frames().into_iter().enumerate()
+        {
+            let frame = gif::Frame {
+                left: left as u16,
+                top: top as u16,
+                width: width as u16,
+                height: height as u16,
+                delay: 10 * (number as u16 + 1),
+                dispose: disposals[number],
+                transparent: (number == 1).then_some(0),
+                buffer: Cow::Owned(indices),
+                ..Default::default()
+            };
+            encoder.write_frame(&frame).unwrap();
+        }
+        drop(encoder);
+        output
+    }
+
+    /// An animated PNG of 3 frames, after a default image that is not part of the animation
+    /// with `separate_default_image`
+    pub(crate) fn animated_png(separate_default_image: bool) -> Vec<u8> {
+        let (width, height, palette, indices) = palette_indices();
+        let mut output = Vec::new();
+        let mut encoder = png::Encoder::new(&mut output, width, height);
+        encoder.set_color(ColorType::Indexed);
+        encoder.set_depth(BitDepth::Eight);
+        encoder.set_palette(palette);
+        encoder.set_animated(3, 2).unwrap();
+        encoder.set_sep_def_img(separate_default_image).unwrap();
+        let mut writer = encoder.write_header().unwrap();
+        if separate_default_image {
+            writer.write_image_data(&indices).unwrap();
+        }
+        let disposals = [DisposeOp::None, DisposeOp::Background, DisposeOp::Previous];
+        for (number, (left, top, width, height, indices)) in
+            animation_frames().into_iter().enumerate()
+        {
+            writer.reset_frame_position().unwrap();
+            writer.set_frame_dimension(width, height).unwrap();
+            writer.set_frame_position(left, top).unwrap();
+            writer.set_frame_delay(number as u16 + 1, 10).unwrap();
+            writer.set_dispose_op(disposals[number]).unwrap();
+            writer.set_blend_op(BlendOp::Over).unwrap();
+            writer.write_image_data(&indices).unwrap();
+        }
+        writer.finish().unwrap();
+        output
+    }
+
+    #[test]
+    fn test_palette_format() {
+        assert_eq!(palette_format(&palette_png()), Some(ImageFormat::Png));
+        assert_eq!(palette_format(&palette_gif()), Some(ImageFormat::Gif));
+        let truecolor = std::fs::read("testAssets/prestine.png").unwrap();
+        assert_eq!(palette_format(&truecolor), None);
+    }
+
+    #[test]
+    fn test_decode_truecolor_png() {
+        let truecolor = std::fs::read("testAssets/prestine.png").unwrap();
+        assert!(matches!(
+            PaletteImage::decode(&truecolor),
+            Err(SteganographyError::UnsupportedFormat(_))
+        ));
+    }
+
+    #[test]
+    fn test_decode_encode_same_pixels() {
+        for bytes in [palette_png(), palette_gif()] {
+            let image = PaletteImage::decode(&bytes).unwrap();
+            let output = image.encode().unwrap();
+            assert_eq!(palette_format(&output), palette_format(&bytes));
+            let decoded = PaletteImage::decode(&output).unwrap();
+            assert_eq!(decoded.frames[0].indices, image.frames[0].indices);
+        }
+    }
+
+    #[test]
+    fn test_decode_encode_animated_gif() {
+        let gif = animated_gif();
+        let image = PaletteImage::decode(&gif).unwrap();
+        assert_eq!(image.frames.len(), 3);
+        let output = image.encode().unwrap();
+        let read_frames = |bytes: &[u8]| {
+            let mut options = DecodeOptions::new();
+            options.set_color_output(ColorOutput::Indexed);
+            let mut decoder = options.read_info(Cursor::new(bytes.to_vec())).unwrap();
+            let mut frames = Vec::new();
+            while let Some(frame) = decoder.read_next_frame().unwrap() {
+                frames.push(frame.clone());
+            }
+            (decoder.repeat(), frames)
+        };
+        let (repeat, original) = read_frames(&gif);
+        let (output_repeat, frames) = read_frames(&output);
+        assert_eq!(output_repeat, repeat);
+        assert_eq!(frames.len(), original.len());
+        for (frame, original) in frames.iter().zip(&original) {
+            assert_eq!(frame.delay, original.delay);
+            assert_eq!(frame.dispose, original.dispose);
+            assert_eq!(frame.transparent, original.transparent);
+            assert_eq!(
+                (frame.left, frame.top, frame.width, frame.height),
+                (original.left, original.top, original.width, original.height)
+            );
+            assert_eq!(frame.buffer, original.buffer);
+        }
+    }
+
+    #[test]
+    fn test_decode_encode_animated_png() {
+        for separate_default_image in [false, true] {
+            let png = animated_png(separate_default_image);
+            let image = PaletteImage::decode(&png).unwrap();
+            assert_eq!(image.frames.len(), 3 + separate_default_image as usize);
+            let output = PaletteImage::decode(&image.encode().unwrap()).unwrap();
+            let (Encoding::Png(encoding), Encoding::Png(output_encoding)) =
+                (&image.encoding, &output.encoding)
+            else {
+                panic!("Expected PNG images");
+            };
+            let animation = output_encoding.animation.unwrap();
+            assert_eq!((animation.num_frames, animation.num_plays), (3, 2));
+            assert_eq!(
+                output_encoding.frame_controls[0].is_none(),
+                separate_default_image
+            );
+            for (control, original) in output_encoding
+                .frame_controls
+                .iter()
+                .zip(&encoding.frame_controls)
+                .filter_map(|(c, o)| c.as_ref().zip(o.as_ref()))
+            {
+                assert_eq!(
+                    (
+                        control.x_offset,
+                        control.y_offset,
+                        control.width,
+                        control.height
+                    ),
+                    (
+                        original.x_offset,
+                        original.y_offset,
+                        original.width,
+                        original.height
+                    )
+                );
+                assert_eq!(
+                    (control.delay_num, control.delay_den),
+                    (original.delay_num, original.delay_den)
+                );
+                assert_eq!(control.dispose_op, original.dispose_op);
+                assert_eq!(control.blend_op, original.blend_op);
+            }
+            for (frame, original) in output.frames.iter().zip(&image.frames) {
+                assert_eq!(frame.indices, original.indices);
+            }
+        }
+    }
+
+    #[test]
+    fn test_pairs_sorted_by_luminance() {
+        // Black, white, dark gray and light gray: black goes with dark gray
+        let colors = [
+            [0, 0, 0, 255],
+            [255, 255, 255, 255],
+            [60, 60, 60, 255],
+            [200, 200, 200, 255],
+        ];
+        let frame = PaletteFrame::new(vec![], &colors);
+        assert_eq!(frame.sorted, vec![0, 2, 3, 1]);
+        assert_eq!(&frame.ranks[..4], &[Some(0), Some(3), Some(1), Some(2)]);
+    }
+
+    #[test]
+    fn test_transparent_color_without_pair() {
+        let colors = [[0, 0, 0, 255], [10, 10, 10, 255], [5, 5, 5, 0]];
+        let frame = PaletteFrame::new(vec![], &colors);
+        assert_eq!(&frame.ranks[..3], &[Some(0), Some(1), None]);
+        // An odd palette leaves its last color without a pair
+        let frame = PaletteFrame::new(vec![], &colors[..1]);
+        assert_eq!(frame.ranks[0], None);
+    }
+
+    #[test]
+    fn test_set_carriers_swaps_within_pairs() {
+        let mut image = PaletteImage::decode(&palette_png()).unwrap();
+        let original = image.frames[0].indices.clone();
+        let carriers = image.carriers();
+        let flipped = carriers.iter().map(|c| c ^ 1).collect::<Vec<u8>>();
+        image.set_carriers(&flipped);
+        assert_eq!(image.carriers(), flipped);
+        let frame = &image.frames[0];
+        for (before, after) in original.iter().zip(&frame.indices) {
+            match frame.ranks[*before as usize] {
+                Some(rank) => assert_eq!(frame.ranks[*after as usize], Some(rank ^ 1)),
+                None => assert_eq!(before, after),
+            }
+        }
+    }
+
+    #[test]
+    fn test_unpack_pack_indices() {
+        let indices = vec![1, 0, 3, 2, 2, 3, 0];
+        let packed = pack_indices(&indices, 7, 2);
+        assert_eq!(packed, vec![0b0100_1110, 0b1011_0000]);
+        assert_eq!(unpack_indices(&packed, 2, 7, 1, 2), indices);
+    }
+}
@@ -0,0 +1,541 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{Cursor, Read};
+use std::path::PathBuf;
+
+use image::DynamicImage;
+
+use super::compression::{compress, compression_from_id, compression_id, decompress_reader};
+use super::error::SteganographyError;
+use super::function::{
+    bytes_to_message, saved_extract_options, verify_payload, CarrierImage, PayloadCarrier,
+};
+use super::header::PayloadKind;
+use super::options::{
+    Compression, SteganographyCapacityOption, SteganographyExtractOption, SteganographyInjectOption,
+};
+
+/// Number of bytes the shard header takes in front of each part: payload identifier (8),
+/// sequence number (2), total count (2) and compression (1)
+pub const SHARD_HEADER_LENGTH: usize = 13;
+
+/// Number of random bytes that identify the payload split across the images
+const PAYLOAD_ID_LENGTH: usize = 8;
+
+/// Information written in front of each part of a payload split across several images
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct ShardHeader {
+    /// Random identifier shared by all the parts of the payload
+    payload_id: [u8; PAYLOAD_ID_LENGTH],
+    /// Position of the part, from 1 to `total`
+    sequence: u16,
+    /// Number of parts of the payload
+    total: u16,
+    /// Compression of the whole payload, reversed once the parts are joined
+    compression: Compression,
+}
+
+impl ShardHeader {
+    fn to_bytes(self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(SHARD_HEADER_LENGTH);
+        bytes.extend(self.payload_id);
+        bytes.extend(self.sequence.to_be_bytes());
+        bytes.extend(self.total.to_be_bytes());
+        bytes.push(compression_id(self.compression));
+        bytes
+    }
+
+    /// Split a part read from an image into its header and its bytes
+    ///
+    /// # Errors
+    /// Returns `CorruptPayload` if the part is too short or its header is not valid
+    fn split(part: &[u8]) -> Result<(Self, &[u8]), SteganographyError> {
+        let corrupt = || {
+            SteganographyError::CorruptPayload(
+                "The header of the part of the message is not valid".to_string(),
+            )
+        };
+        if part.len() < SHARD_HEADER_LENGTH {
+            return Err(corrupt());
+        }
+        let (bytes, content) = part.split_at(SHARD_HEADER_LENGTH);
+        let mut payload_id = [0u8; PAYLOAD_ID_LENGTH];
+        payload_id.copy_from_slice(&bytes[..PAYLOAD_ID_LENGTH]);
+        let header = ShardHeader {
+            payload_id,
+            sequence: u16::from_be_bytes([bytes[8], bytes[9]]),
+            total: u16::from_be_bytes([bytes[10], bytes[11]]),
+            compression: compression_from_id(bytes[12]).ok_or_else(corrupt)?,
+        };
+        if header.sequence == 0 || header.sequence > header.total {
+            return Err(corrupt());
+        }
+        Ok((header, content))
+    }
+}
+
+/// Split bytes across several images already in memory when they do not fit into a single one.
+/// Every image receives a part, in proportion to what it can hold. The bytes are joined back
+/// with `extract_bytes_from_images`
+///
+/// # Arguments
+///
+/// * `images` - The images to carry the bytes, at most 65535. They are not altered, copies are
+///   returned in the same order
+/// * `payload` - The bytes to hide
+/// * `options` - The encoding to use for every image, see `inject_bytes_into_image`. The
+///   compression applies to the whole payload before it is split
+///
+/// # Errors
+///
+/// Returns `Capacity` if the images together cannot hold the payload, or an error if the
+/// options cannot be used together
+pub fn inject_bytes_into_images(
+    images: &[DynamicImage],
+    payload: &[u8],
+    options: &SteganographyInjectOption,
+) -> Result<Vec<DynamicImage>, SteganographyError> {
+    split_into_carriers(images.to_vec(), payload, options)
+}
+
+/// Split the bytes across any carriers, see `inject_bytes_into_images`
+pub(crate) fn split_into_carriers<C: PayloadCarrier>(
+    mut images: Vec<C>,
+    payload: &[u8],
+    options: &SteganographyInjectOption,
+) -> Result<Vec<C>, SteganographyError> {
+    let total = u16::try_from(images.len())
+        .ok()
+        .filter(|t| *t > 0)
+        .ok_or_else(|| {
+            SteganographyError::InvalidOption("Between 1 and 65535 images are required".to_string())
+        })?;
+    let payload = compress(payload, options.compression)?;
+    let capacity_options = SteganographyCapacityOption {
+        password: options.password.clone(),
+        input_image_path: String::new(),
+        bits_per_channel: options.bits_per_channel,
+        channels: options.channels,
+    };
+    let capacities: Vec<usize> = images
+        .iter()
+        .map(|img| {
+            img.capacity(&capacity_options)
+                .saturating_sub(SHARD_HEADER_LENGTH)
+        })
+        .collect();
+    let available: usize = capacities.iter().sum();
+    if payload.len() > available {
+        return Err(SteganographyError::Capacity {
+            required: payload.len(),
+            available,
+        });
+    }
+    let header = ShardHeader {
+        payload_id: rand::random(),
+        sequence: 0,
+        total,
+        compression: options.compression,
+    };
+    // The payload is compressed once as a whole, the parts are not compressed again
+    let part_options = SteganographyInjectOption {
+        compression: Compression::None,
+        ..options.clone()
+    };
+    let mut remaining = &payload[..];
+    let mut remaining_capacity = available;
+    let count = images.len();
+    for (index, (img, capacity)) in images.iter_mut().zip(&capacities).enumerate() {
+        remaining_capacity -= capacity;
+        // An even share, unless the next images cannot hold what this one leaves
+        let even = remaining.len().div_ceil(count - index);
+        let length = even
+            .max(remaining.len().saturating_sub(remaining_capacity))
+            .min(*capacity);
+        let (part, rest) = remaining.split_at(length);
+        remaining = rest;
+        let mut bytes = ShardHeader {
+            sequence: index as u16 + 1,
+            ..header
+        }
+        .to_bytes();
+        bytes.extend(part);
+        img.inject(&bytes, PayloadKind::Shard, &part_options)?;
+    }
+    Ok(images)
+}
+
+/// Join the bytes split across several images with `inject_bytes_into_images`
+///
+/// # Arguments
+///
+/// * `images` - The images that carry the parts, in any order
+/// * `options` - The password used for every image (optional). The paths of the options are not
+///   used
+///
+/// # Errors
+///
+/// Returns `MissingShards` with the sequence numbers of the missing parts, `InvalidOption` if an
+/// image does not carry a part or if the images carry parts of different payloads
+pub fn extract_bytes_from_images(
+    images: &[DynamicImage],
+    options: &SteganographyExtractOption,
+) -> Result<Vec<u8>, SteganographyError> {
+    join_from_carriers(images, options)
+}
+
+/// Join the bytes split across any carriers, see `extract_bytes_from_images`
+pub(crate) fn join_from_carriers<C: PayloadCarrier>(
+    images: &[C],
+    options: &SteganographyExtractOption,
+) -> Result<Vec<u8>, SteganographyError> {
+    let mut first: Option<ShardHeader> = None;
+    let mut parts = BTreeMap::new();
+    for (index, img) in images.iter().enumerate() {
+        let (kind, mut reader) = img.open_framed_payload(&options.password)?;
+        if kind != PayloadKind::Shard {
+            return Err(SteganographyError::InvalidOption(format!(
+                "The image {} does not carry a part of a message split across several images",
+                index + 1
+            )));
+        }
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        let (header, part) = ShardHeader::split(&bytes)?;
+        let first = *first.get_or_insert(header);
+        if (header.payload_id, header.total, header.compression)
+            != (first.payload_id, first.total, first.compression)
+        {
+            return Err(SteganographyError::InvalidOption(
+                "The images carry parts of different messages".to_string(),
+            ));
+        }
+        parts.insert(header.sequence, part.to_vec());
+    }
+    let total = match first {
+        Some(header) => header.total,
+        None => {
+            return Err(SteganographyError::InvalidOption(
+                "At least one image is required".to_string(),
+            ))
+        }
+    };
+    let missing: Vec<u16> = (1..=total).filter(|s| !parts.contains_key(s)).collect();
+    if !missing.is_empty() {
+        return Err(SteganographyError::MissingShards { missing, total });
+    }
+    let payload: Vec<u8> = parts.into_values().flatten().collect();
+    let length = payload.len();
+    let mut result = Vec::new();
+    decompress_reader(
+        Box::new(Cursor::new(payload)),
+        first.map_or(Compression::None, |h| h.compression),
+        length,
+    )?
+    .read_to_end(&mut result)?;
+    Ok(result)
+}
+
+/// List the images of the paths, the images of a directory are sorted by name
+pub(crate) fn image_paths(paths: &[String]) -> Result<Vec<PathBuf>, SteganographyError> {
+    let mut result = Vec::new();
+    for path in paths.iter().map(PathBuf::from) {
+        if path.is_dir() {
+            let mut files = fs::read_dir(&path)?
+                .map(|entry| entry.map(|e| e.path()))
+                .collect::<Result<Vec<_>, _>>()?;
+            files.retain(|f| f.is_file());
+            files.sort();
+            result.extend(files);
+        } else {
+            result.push(path);
+        }
+    }
+    Ok(result)
+}
+
+/// Split the message (or the content of the file referenced by `input_file_path`) across the
+/// images referenced by `shard_image_paths` and save them with the same names into the directory
+/// referenced by `output_image_path`. A JPEG image stays a JPEG image, see `inject_bytes`
+///
+/// # Arguments
+///
+/// * `options` - Structure with the images or the directories of images, the directory to
+///   save them and the encoding to use
+///
+/// # Errors
+///
+/// Returns an error if an image cannot be read or written, or if the images together cannot
+/// hold the message
+pub fn inject_shards(options: SteganographyInjectOption) -> Result<(), SteganographyError> {
+    let paths = image_paths(&options.shard_image_paths)?;
+    let payload = match &options.input_file_path {
+        Some(path) => fs::read(path)?,
+        None => options.message.clone().into_bytes(),
+    };
+    let output_paths = output_image_paths(&paths, &options.output_image_path)?;
+    let images = open_images_for_output(&paths, &output_paths)?;
+    let result = split_into_carriers(images, &payload, &options)?;
+    save_images(&output_paths, result)?;
+    if options.verify {
+        let extracted = open_images(&output_paths)
+            .and_then(|images| join_from_carriers(&images, &saved_extract_options(&options)));
+        verify_payload(&payload, extracted)?;
+    }
+    Ok(())
+}
+
+/// Open the images to extract their payloads (E.g. the images saved by `save_images`), see
+/// `CarrierImage::open`
+pub(crate) fn open_images(paths: &[PathBuf]) -> Result<Vec<CarrierImage>, SteganographyError> {
+    paths.iter().map(CarrierImage::open).collect()
+}
+
+/// Open the images to inject the payloads into, each one for the image to be saved at the
+/// output path of the same position, see `CarrierImage::open_for_output`
+pub(crate) fn open_images_for_output(
+    paths: &[PathBuf],
+    output_paths: &[PathBuf],
+) -> Result<Vec<CarrierImage>, SteganographyError> {
+    paths
+        .iter()
+        .zip(output_paths)
+        .map(|(path, output_path)| CarrierImage::open_for_output(path, output_path))
+        .collect()
+}
+
+/// Paths of the images once saved with the same names into the output directory
+///
+/// # Errors
+///
+/// Returns `InvalidOption` if two images have the same name
+pub(crate) fn output_image_paths(
+    paths: &[PathBuf],
+    output_dir: &str,
+) -> Result<Vec<PathBuf>, SteganographyError> {
+    let output_dir = PathBuf::from(output_dir);
+    let mut output_paths = Vec::with_capacity(paths.len());
+    for path in paths {
+        let output_path = path
+            .file_name()
+            .map(|name| output_dir.join(name))
+            .ok_or_else(|| {
+                SteganographyError::InvalidOption(format!("\"{}\" is not an image", path.display()))
+            })?;
+        // Two images with the same name would overwrite each other in the directory
+        if output_paths.contains(&output_path) {
+            return Err(SteganographyError::InvalidOption(format!(
+                "Several images are named \"{}\"",
+                output_path.display()
+            )));
+        }
+        output_paths.push(output_path);
+    }
+    Ok(output_paths)
+}
+
+/// Save the images, creating their directory when missing. No image is saved when one of the
+/// formats would lose the payload
+pub(crate) fn save_images(
+    output_paths: &[PathBuf],
+    images: Vec<CarrierImage>,
+) -> Result<(), SteganographyError> {
+    for (output_path, img) in output_paths.iter().zip(&images) {
+        img.check_output(output_path)?;
+    }
+    for (output_path, img) in output_paths.iter().zip(images) {
+        if let Some(dir) = output_path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        img.save(output_path)?;
+    }
+    Ok(())
+}
+
+/// Join the message split across the images referenced by `shard_image_paths`, given in any
+/// order or as directories
+///
+/// # Errors
+///
+/// Returns an error if an image cannot be read, if some images are missing or if the password
+/// cannot decrypt them
+pub fn extract_shards(options: SteganographyExtractOption) -> Result<Vec<u8>, SteganographyError> {
+    let images = open_images(&image_paths(&options.shard_image_paths)?)?;
+    join_from_carriers(&images, &options)
+}
+
+/// Join the message split across the images referenced by `shard_image_paths` as a UTF-8
+/// message
+///
+/// # Errors
+///
+/// Returns the errors of `extract_shards` and `CorruptPayload` if the bytes are not valid UTF-8
+pub fn get_message_from_shards(
+    options: SteganographyExtractOption,
+) -> Result<String, SteganographyError> {
+    bytes_to_message(extract_shards(options)?)
+}
+
+/// Write the message split across the images referenced by `shard_image_paths` into the file
+/// referenced by `output_file_path`
+///
+/// # Errors
+///
+/// Returns the errors of `extract_shards` and an error if the file cannot be written
+pub fn extract_shards_to_file(
+    options: SteganographyExtractOption,
+) -> Result<(), SteganographyError> {
+    let output_file_path = options.output_file_path.clone().ok_or_else(|| {
+        SteganographyError::InvalidOption("The file to create is required".to_string())
+    })?;
+    fs::write(output_file_path, extract_shards(options)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test_shard {
+    use super::*;
+    use crate::utils::jpeg::{encode_jpeg, is_jpeg, DEFAULT_JPEG_QUALITY};
+    use crate::utils::palette::palette_format;
+    use crate::utils::palette::test_palette::palette_png;
+
+    fn images() -> Vec<DynamicImage> {
+        // 8, 104 and 8 bytes for the parts once the headers are written
+        vec![
+            DynamicImage::new_rgba8(8, 8),
+            DynamicImage::new_rgba8(16, 16),
+            DynamicImage::new_rgba8(8, 8),
+        ]
+    }
+
+    #[test]
+    fn test_split_join_in_any_order() {
+        let payload: Vec<u8> = (0..80).collect();
+        let mut result =
+            inject_bytes_into_images(&images(), &payload, &SteganographyInjectOption::default())
+                .unwrap();
+        result.reverse();
+        let options = SteganographyExtractOption::default();
+        assert_eq!(
+            extract_bytes_from_images(&result, &options).unwrap(),
+            payload
+        );
+    }
+
+    #[test]
+    fn test_split_larger_than_images() {
+        let result = inject_bytes_into_images(
+            &images(),
+            &[0u8; 121],
+            &SteganographyInjectOption::default(),
+        );
+        assert!(matches!(
+            result,
+            Err(SteganographyError::Capacity {
+                required: 121,
+                available: 120
+            })
+        ));
+    }
+
+    #[test]
+    fn test_join_missing_images() {
+        let payload = b"Split across three images".to_vec();
+        let result =
+            inject_bytes_into_images(&images(), &payload, &SteganographyInjectOption::default())
+                .unwrap();
+        let options = SteganographyExtractOption::default();
+        let error = extract_bytes_from_images(&result[1..2], &options).unwrap_err();
+        assert!(matches!(
+            error,
+            SteganographyError::MissingShards { missing, total: 3 } if missing == vec![1, 3]
+        ));
+    }
+
+    #[test]
+    fn test_join_different_messages() {
+        let options = SteganographyInjectOption::default();
+        let first = inject_bytes_into_images(&images(), b"First", &options).unwrap();
+        let second = inject_bytes_into_images(&images(), b"Second", &options).unwrap();
+        let result = extract_bytes_from_images(
+            &[first[0].clone(), second[1].clone()],
+            &SteganographyExtractOption::default(),
+        );
+        assert!(matches!(result, Err(SteganographyError::InvalidOption(_))));
+    }
+
+    #[test]
+    fn test_split_join_files() {
+        let options = SteganographyInjectOption {
+            input_file_path: Some("testAssets/message1.txt".to_string()),
+            output_image_path: "target/delete_me_shards".to_string(),
+            shard_image_paths: vec![
+                "testAssets/prestine.png".to_string(),
+                "testAssets/out_message_Bye.png".to_string(),
+            ],
+            password: Some("secret".to_string()),
+            compression: Compression::Deflate,
+            ..Default::default()
+        };
+        inject_shards(options).unwrap();
+        let options = SteganographyExtractOption {
+            shard_image_paths: vec!["target/delete_me_shards".to_string()],
+            password: Some("secret".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            extract_shards(options).unwrap(),
+            fs::read("testAssets/message1.txt").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_split_join_palette_files() {
+        fs::create_dir_all("target/delete_me_palette_sources").unwrap();
+        fs::write("target/delete_me_palette_sources/first.png", palette_png()).unwrap();
+        fs::write("target/delete_me_palette_sources/second.png", palette_png()).unwrap();
+        let options = SteganographyInjectOption {
+            message: "Hi".to_string(),
+            output_image_path: "target/delete_me_palette_shards".to_string(),
+            shard_image_paths: vec!["target/delete_me_palette_sources".to_string()],
+            verify: true,
+            ..Default::default()
+        };
+        inject_shards(options).unwrap();
+        let saved = fs::read("target/delete_me_palette_shards/first.png").unwrap();
+        assert_eq!(palette_format(&saved), Some(image::ImageFormat::Png));
+        let options = SteganographyExtractOption {
+            shard_image_paths: vec!["target/delete_me_palette_shards".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(get_message_from_shards(options).unwrap(), "Hi");
+    }
+
+    #[test]
+    fn test_split_join_jpeg_files() {
+        let img = image::open("testAssets/prestine.png").unwrap();
+        let jpeg = encode_jpeg(&img, DEFAULT_JPEG_QUALITY).unwrap();
+        fs::create_dir_all("target/delete_me_jpeg_sources").unwrap();
+        fs::write("target/delete_me_jpeg_sources/first.jpg", &jpeg).unwrap();
+        fs::write("target/delete_me_jpeg_sources/second.jpg", &jpeg).unwrap();
+        let options = SteganographyInjectOption {
+            message: "Split across two JPEG images".to_string(),
+            output_image_path: "target/delete_me_jpeg_shards".to_string(),
+            shard_image_paths: vec!["target/delete_me_jpeg_sources".to_string()],
+            verify: true,
+            ..Default::default()
+        };
+        inject_shards(options).unwrap();
+        assert!(is_jpeg(
+            &fs::read("target/delete_me_jpeg_shards/first.jpg").unwrap()
+        ));
+        let options = SteganographyExtractOption {
+            shard_image_paths: vec!["target/delete_me_jpeg_shards".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(
+            get_message_from_shards(options).unwrap(),
+            "Split across two JPEG images"
+        );
+    }
+}
@@ -0,0 +1,480 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{Cursor, Read};
+
+use image::DynamicImage;
+use rand::rngs::OsRng;
+use rand::RngCore;
+
+use super::compression::{compress, compression_from_id, compression_id, decompress_reader};
+use super::error::SteganographyError;
+use super::function::{bytes_to_message, saved_extract_options, verify_payload, PayloadCarrier};
+use super::header::PayloadKind;
+use super::options::{Compression, SteganographyExtractOption, SteganographyInjectOption};
+use super::shard::{
+    image_paths, open_images, open_images_for_output, output_image_paths, save_images,
+};
+
+/// Number of bytes the share header takes in front of each share: payload identifier (8),
+/// threshold (1), coordinate of the share (1) and compression (1)
+pub const SHARE_HEADER_LENGTH: usize = 11;
+
+/// Number of random bytes that identify the payload shared across the images
+const PAYLOAD_ID_LENGTH: usize = 8;
+
+/// Reduction polynomial of GF(2^8), the same as AES: x^8 + x^4 + x^3 + x + 1
+const GF_REDUCTION: u8 = 0x1B;
+
+/// Information written in front of each share
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct ShareHeader {
+    /// Random identifier shared by all the shares of the payload
+    payload_id: [u8; PAYLOAD_ID_LENGTH],
+    /// Number of shares required to recover the payload
+    threshold: u8,
+    /// Point where the polynomials were evaluated for this share, never 0
+    x: u8,
+    /// Compression of the payload, reversed once it is recovered
+    compression: Compression,
+}
+
+impl ShareHeader {
+    fn to_bytes(self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(SHARE_HEADER_LENGTH);
+        bytes.extend(self.payload_id);
+        bytes.push(self.threshold);
+        bytes.push(self.x);
+        bytes.push(compression_id(self.compression));
+        bytes
+    }
+
+    /// Split a share read from an image into its header and its bytes
+    ///
+    /// # Errors
+    /// Returns `CorruptPayload` if the share is too short or its header is not valid
+    fn split(share: &[u8]) -> Result<(Self, &[u8]), SteganographyError> {
+        let corrupt = || {
+            SteganographyError::CorruptPayload("The header of the share is not valid".to_string())
+        };
+        if share.len() < SHARE_HEADER_LENGTH {
+            return Err(corrupt());
+        }
+        let (bytes, content) = share.split_at(SHARE_HEADER_LENGTH);
+        let mut payload_id = [0u8; PAYLOAD_ID_LENGTH];
+        payload_id.copy_from_slice(&bytes[..PAYLOAD_ID_LENGTH]);
+        let header = ShareHeader {
+            payload_id,
+            threshold: bytes[8],
+            x: bytes[9],
+            compression: compression_from_id(bytes[10]).ok_or_else(corrupt)?,
+        };
+        if header.threshold == 0 || header.x == 0 {
+            return Err(corrupt());
+        }
+        Ok((header, content))
+    }
+}
+
+/// Multiplication in GF(2^8)
+fn gf_mul(mut a: u8, mut b: u8) -> u8 {
+    let mut result = 0;
+    while b != 0 {
+        if b & 1 != 0 {
+            result ^= a;
+        }
+        let carry = a & 0x80 != 0;
+        a <<= 1;
+        if carry {
+            a ^= GF_REDUCTION;
+        }
+        b >>= 1;
+    }
+    result
+}
+
+/// Inverse in GF(2^8), a^254 since a^255 = 1. `a` must not be 0
+fn gf_inv(a: u8) -> u8 {
+    let mut result = 1;
+    let mut base = a;
+    let mut exponent = 254u8;
+    while exponent != 0 {
+        if exponent & 1 != 0 {
+            result = gf_mul(result, base);
+        }
+        base = gf_mul(base, base);
+        exponent >>= 1;
+    }
+    result
+}
+
+/// Evaluate at `x` the polynomial whose constant is `constant` followed by `coefficients`, from
+/// the highest degree down with Horner's method
+fn evaluate(constant: u8, coefficients: &[u8], x: u8) -> u8 {
+    coefficients
+        .iter()
+        .rev()
+        .chain(std::iter::once(&constant))
+        .fold(0, |acc, c| gf_mul(acc, x) ^ c)
+}
+
+/// Split a secret into `count` shares with Shamir's scheme: each byte is the constant of a
+/// random polynomial of degree `threshold - 1` evaluated at the points 1 to `count`. Any
+/// `threshold` shares recover the secret, fewer shares reveal nothing about it
+///
+/// # Returns
+/// The shares with their point, each one as long as the secret
+fn split_secret(secret: &[u8], threshold: u8, count: u8) -> Vec<(u8, Vec<u8>)> {
+    let degree = threshold as usize - 1;
+    let mut coefficients = vec![0u8; secret.len() * degree];
+    OsRng.fill_bytes(&mut coefficients);
+    (1..=count)
+        .map(|x| {
+            let share = secret
+                .iter()
+                .enumerate()
+                .map(|(index, byte)| {
+                    evaluate(
+                        *byte,
+                        &coefficients[index * degree..(index + 1) * degree],
+                        x,
+                    )
+                })
+                .collect();
+            (x, share)
+        })
+        .collect()
+}
+
+/// Recover the secret from shares of the same length with distinct points, with the Lagrange
+/// interpolation of the polynomials at 0
+fn combine_shares(shares: &[(u8, &[u8])]) -> Vec<u8> {
+    let weights: Vec<u8> = shares
+        .iter()
+        .map(|(xi, _)| {
+            shares
+                .iter()
+                .filter(|(xj, _)| xj != xi)
+                .fold(1, |acc, (xj, _)| gf_mul(acc, gf_mul(*xj, gf_inv(xi ^ xj))))
+        })
+        .collect();
+    let length = shares.first().map_or(0, |(_, s)| s.len());
+    (0..length)
+        .map(|index| {
+            shares
+                .iter()
+                .zip(&weights)
+                .fold(0, |acc, ((_, share), weight)| {
+                    acc ^ gf_mul(share[index], *weight)
+                })
+        })
+        .collect()
+}
+
+/// Share bytes across several images already in memory so that any `threshold` of them
+/// recover the bytes while fewer reveal nothing. Every image carries a share as long as the
+/// whole payload
+///
+/// # Arguments
+///
+/// * `images` - The images to carry the shares, between `threshold` and 255. They are not
+///   altered, copies are returned in the same order
+/// * `payload` - The bytes to share
+/// * `threshold` - Number of images required to recover the bytes
+/// * `options` - The encoding to use for every image, see `inject_bytes_into_image`
+///
+/// # Errors
+///
+/// Returns `InvalidOption` if the threshold is 0 or larger than the number of images,
+/// `Capacity` if an image cannot hold a share
+pub fn share_bytes_into_images(
+    images: &[DynamicImage],
+    payload: &[u8],
+    threshold: u8,
+    options: &SteganographyInjectOption,
+) -> Result<Vec<DynamicImage>, SteganographyError> {
+    share_into_carriers(images.to_vec(), payload, threshold, options)
+}
+
+/// Share the bytes across any carriers, see `share_bytes_into_images`
+pub(crate) fn share_into_carriers<C: PayloadCarrier>(
+    mut images: Vec<C>,
+    payload: &[u8],
+    threshold: u8,
+    options: &SteganographyInjectOption,
+) -> Result<Vec<C>, SteganographyError> {
+    let count = u8::try_from(images.len()).map_err(|_| {
+        SteganographyError::InvalidOption("At most 255 images can share a message".to_string())
+    })?;
+    if threshold == 0 || threshold > count {
+        return Err(SteganographyError::InvalidOption(format!(
+            "The threshold must be between 1 and the number of images ({})",
+            count
+        )));
+    }
+    let payload = compress(payload, options.compression)?;
+    let payload_id: [u8; PAYLOAD_ID_LENGTH] = rand::random();
+    // The payload is compressed before it is shared, the shares look random and do not compress
+    let share_options = SteganographyInjectOption {
+        compression: Compression::None,
+        ..options.clone()
+    };
+    for (img, (x, share)) in images
+        .iter_mut()
+        .zip(split_secret(&payload, threshold, count))
+    {
+        let header = ShareHeader {
+            payload_id,
+            threshold,
+            x,
+            compression: options.compression,
+        };
+        let mut bytes = header.to_bytes();
+        bytes.extend(share);
+        img.inject(&bytes, PayloadKind::Share, &share_options)?;
+    }
+    Ok(images)
+}
+
+/// Recover the bytes shared with `share_bytes_into_images`
+///
+/// # Arguments
+///
+/// * `images` - At least `threshold` images that carry the shares, in any order
+/// * `options` - The password used for every image (optional). The paths of the options are not
+///   used
+///
+/// # Errors
+///
+/// Returns `NotEnoughShares` if fewer images than the threshold are given, `InvalidOption` if an
+/// image does not carry a share or if the images carry shares of different payloads
+pub fn recover_bytes_from_images(
+    images: &[DynamicImage],
+    options: &SteganographyExtractOption,
+) -> Result<Vec<u8>, SteganographyError> {
+    recover_from_carriers(images, options)
+}
+
+/// Recover the bytes shared across any carriers, see `recover_bytes_from_images`
+pub(crate) fn recover_from_carriers<C: PayloadCarrier>(
+    images: &[C],
+    options: &SteganographyExtractOption,
+) -> Result<Vec<u8>, SteganographyError> {
+    let mut first: Option<ShareHeader> = None;
+    let mut shares = BTreeMap::new();
+    for (index, img) in images.iter().enumerate() {
+        let (kind, mut reader) = img.open_framed_payload(&options.password)?;
+        if kind != PayloadKind::Share {
+            return Err(SteganographyError::InvalidOption(format!(
+                "The image {} does not carry a share of a message",
+                index + 1
+            )));
+        }
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        let (header, share) = ShareHeader::split(&bytes)?;
+        let first = *first.get_or_insert(header);
+        if (header.payload_id, header.threshold, header.compression)
+            != (first.payload_id, first.threshold, first.compression)
+        {
+            return Err(SteganographyError::InvalidOption(
+                "The images carry shares of different messages".to_string(),
+            ));
+        }
+        shares.insert(header.x, share.to_vec());
+    }
+    let header = first.ok_or_else(|| {
+        SteganographyError::InvalidOption("At least one image is required".to_string())
+    })?;
+    if shares.len() < header.threshold as usize {
+        return Err(SteganographyError::NotEnoughShares {
+            required: header.threshold as usize,
+            available: shares.len(),
+        });
+    }
+    let shares: Vec<(u8, &[u8])> = shares
+        .iter()
+        .take(header.threshold as usize)
+        .map(|(x, share)| (*x, share.as_slice()))
+        .collect();
+    if shares.iter().any(|(_, s)| s.len() != shares[0].1.len()) {
+        return Err(SteganographyError::CorruptPayload(
+            "The shares do not have the same length".to_string(),
+        ));
+    }
+    let payload = combine_shares(&shares);
+    let length = payload.len();
+    let mut result = Vec::new();
+    decompress_reader(Box::new(Cursor::new(payload)), header.compression, length)?
+        .read_to_end(&mut result)?;
+    Ok(result)
+}
+
+/// Share the message (or the content of the file referenced by `input_file_path`) across the
+/// images referenced by `shard_image_paths` so that `threshold` of them recover it, and save them
+/// with the same names into the directory referenced by `output_image_path`
+///
+/// # Errors
+///
+/// Returns an error if an image cannot be read or written, if an image cannot hold a share or
+/// if the threshold is larger than the number of images
+pub fn share_message(options: SteganographyInjectOption) -> Result<(), SteganographyError> {
+    let paths = image_paths(&options.shard_image_paths)?;
+    let payload = match &options.input_file_path {
+        Some(path) => fs::read(path)?,
+        None => options.message.clone().into_bytes(),
+    };
+    let output_paths = output_image_paths(&paths, &options.output_image_path)?;
+    let images = open_images_for_output(&paths, &output_paths)?;
+    let result = share_into_carriers(images, &payload, options.threshold, &options)?;
+    save_images(&output_paths, result)?;
+    if options.verify {
+        let extracted = open_images(&output_paths)
+            .and_then(|images| recover_from_carriers(&images, &saved_extract_options(&options)));
+        verify_payload(&payload, extracted)?;
+    }
+    Ok(())
+}
+
+/// Recover the message shared across the images referenced by `shard_image_paths`, given in any
+/// order or as directories
+///
+/// # Errors
+///
+/// Returns an error if an image cannot be read, if there are fewer images than the threshold or
+/// if the password cannot decrypt them
+pub fn recover_message(options: SteganographyExtractOption) -> Result<Vec<u8>, SteganographyError> {
+    let images = open_images(&image_paths(&options.shard_image_paths)?)?;
+    recover_from_carriers(&images, &options)
+}
+
+/// Recover the message shared across the images referenced by `shard_image_paths` as a UTF-8
+/// message
+///
+/// # Errors
+///
+/// Returns the errors of `recover_message` and `CorruptPayload` if the bytes are not valid UTF-8
+pub fn get_message_from_shares(
+    options: SteganographyExtractOption,
+) -> Result<String, SteganographyError> {
+    bytes_to_message(recover_message(options)?)
+}
+
+/// Write the message shared across the images referenced by `shard_image_paths` into the file
+/// referenced by `output_file_path`
+///
+/// # Errors
+///
+/// Returns the errors of `recover_message` and an error if the file cannot be written
+pub fn recover_message_to_file(
+    options: SteganographyExtractOption,
+) -> Result<(), SteganographyError> {
+    let output_file_path = options.output_file_path.clone().ok_or_else(|| {
+        SteganographyError::InvalidOption("The file to create is required".to_string())
+    })?;
+    fs::write(output_file_path, recover_message(options)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test_sharing {
+    use super::*;
+
+    #[test]
+    fn test_gf_inverse() {
+        for a in 1..=255u8 {
+            assert_eq!(gf_mul(a, gf_inv(a)), 1);
+        }
+    }
+
+    #[test]
+    fn test_split_combine_any_subset() {
+        let secret = b"Dead drop at the old oak".to_vec();
+        let shares = split_secret(&secret, 3, 5);
+        for subset in [[0, 1, 2], [4, 2, 0], [1, 3, 4]] {
+            let selected: Vec<(u8, &[u8])> = subset
+                .iter()
+                .map(|i| (shares[*i].0, shares[*i].1.as_slice()))
+                .collect();
+            assert_eq!(combine_shares(&selected), secret);
+        }
+    }
+
+    #[test]
+    fn test_combine_fewer_shares() {
+        let secret = b"Dead drop at the old oak".to_vec();
+        let shares = split_secret(&secret, 3, 5);
+        let selected: Vec<(u8, &[u8])> = shares[..2]
+            .iter()
+            .map(|(x, s)| (*x, s.as_slice()))
+            .collect();
+        assert_ne!(combine_shares(&selected), secret);
+    }
+
+    fn images() -> Vec<DynamicImage> {
+        (0..4).map(|_| DynamicImage::new_rgba8(16, 16)).collect()
+    }
+
+    #[test]
+    fn test_share_recover_images() {
+        let options = SteganographyInjectOption {
+            password: Some("secret".to_string()),
+            ..Default::default()
+        };
+        let result = share_bytes_into_images(&images(), b"Dead drop", 2, &options).unwrap();
+        let options = SteganographyExtractOption {
+            password: Some("secret".to_string()),
+            ..Default::default()
+        };
+        let recovered =
+            recover_bytes_from_images(&[result[3].clone(), result[1].clone()], &options).unwrap();
+        assert_eq!(recovered, b"Dead drop");
+    }
+
+    #[test]
+    fn test_recover_not_enough_images() {
+        let options = SteganographyInjectOption::default();
+        let result = share_bytes_into_images(&images(), b"Dead drop", 3, &options).unwrap();
+        let error = recover_bytes_from_images(&result[..2], &SteganographyExtractOption::default());
+        assert!(matches!(
+            error,
+            Err(SteganographyError::NotEnoughShares {
+                required: 3,
+                available: 2
+            })
+        ));
+    }
+
+    #[test]
+    fn test_share_threshold_larger_than_images() {
+        let options = SteganographyInjectOption::default();
+        let result = share_bytes_into_images(&images(), b"Dead drop", 5, &options);
+        assert!(matches!(result, Err(SteganographyError::InvalidOption(_))));
+    }
+
+    #[test]
+    fn test_share_recover_files() {
+        let options = SteganographyInjectOption {
+            message: "Dead drop at the old oak".to_string(),
+            output_image_path: "target/delete_me_shares".to_string(),
+            shard_image_paths: vec![
+                "testAssets/prestine.png".to_string(),
+                "testAssets/out_message_Bye.png".to_string(),
+                "testAssets/out_message_Bye_2.png".to_string(),
+            ],
+            threshold: 2,
+            ..Default::default()
+        };
+        share_message(options).unwrap();
+        let options = SteganographyExtractOption {
+            shard_image_paths: vec![
+                "target/delete_me_shares/out_message_Bye_2.png".to_string(),
+                "target/delete_me_shares/prestine.png".to_string(),
+            ],
+            ..Default::default()
+        };
+        assert_eq!(
+            get_message_from_shares(options).unwrap(),
+            "Dead drop at the old oak"
+        );
+    }
+}
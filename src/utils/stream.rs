@@ -0,0 +1,486 @@
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use image::DynamicImage;
+use sha2::{Digest, Sha256};
+
+use super::compression::decompressed_limit;
+use super::envelope::{envelope_bytes, envelope_overhead, file_metadata};
+use super::error::SteganographyError;
+use super::function::{
+    extract_file_from_carrier, open_payload, verify_payload, CarrierImage, PayloadCarrier,
+};
+use super::header::PayloadKind;
+use super::options::{
+    Compression, SteganographyCapacityOption, SteganographyExtractOption, SteganographyInjectOption,
+};
+
+/// Embeds a payload written in chunks (E.g. copied from a file or a socket with `io::copy`)
+/// into an image. The bytes are kept until `finish` because the header records the length of
+/// the payload and the encryption authenticates the whole payload. Writing more bytes than the
+/// image can hide fails right away with a `Capacity` error. A compressed payload is only measured
+/// by `finish`, the bytes written are then limited to what the capacity of the image can
+/// decompress to (see `decompressed_limit`)
+pub struct PayloadWriter {
+    carrier: CarrierImage,
+    options: SteganographyInjectOption,
+    payload: Vec<u8>,
+    capacity: usize,
+    /// Name and last modification of the file the bytes are the content of, if any
+    file: Option<(String, u64)>,
+}
+
+impl PayloadWriter {
+    /// # Arguments
+    ///
+    /// * `image` - The image that carries the payload
+    /// * `options` - The encoding to use, see `inject_bytes_into_image`. The paths and the
+    ///   message of the options are not used
+    pub fn new(image: DynamicImage, options: SteganographyInjectOption) -> Self {
+        PayloadWriter::with_carrier(CarrierImage::Pixels(image), options)
+    }
+
+    /// A writer into any image opened from a file, see `CarrierImage`
+    fn with_carrier(carrier: CarrierImage, options: SteganographyInjectOption) -> Self {
+        let capacity = carrier.capacity(&SteganographyCapacityOption {
+            password: options.password.clone(),
+            input_image_path: String::new(),
+            bits_per_channel: options.bits_per_channel,
+            channels: options.channels,
+        });
+        let capacity = if options.compression != Compression::None {
+            usize::try_from(decompressed_limit(capacity)).unwrap_or(usize::MAX)
+        } else {
+            capacity
+        };
+        PayloadWriter {
+            carrier,
+            options,
+            payload: Vec::new(),
+            capacity,
+            file: None,
+        }
+    }
+
+    /// Embeds the bytes written as the content of a file with its name and last modification,
+    /// extracted back with `extract_file_from_image`
+    ///
+    /// # Arguments
+    ///
+    /// * `carrier` - The image that carries the file, see `CarrierImage`
+    /// * `options` - The encoding to use, see `PayloadWriter::new`
+    /// * `name` - Name of the file, without any directory
+    /// * `modified` - Last modification in seconds since the Unix epoch, 0 when unknown
+    pub(crate) fn for_file(
+        carrier: CarrierImage,
+        options: SteganographyInjectOption,
+        name: String,
+        modified: u64,
+    ) -> Self {
+        let mut writer = PayloadWriter::with_carrier(carrier, options);
+        writer.capacity = writer.capacity.saturating_sub(envelope_overhead(&name));
+        writer.file = Some((name, modified));
+        writer
+    }
+
+    /// Embed the bytes written so far into the image
+    ///
+    /// # Returns
+    /// The image with the payload, see `inject_bytes_into_image`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the options cannot be used together or with the image
+    pub fn finish(mut self) -> Result<DynamicImage, SteganographyError> {
+        self.embed()?;
+        self.carrier.into_pixels()
+    }
+
+    /// Embed the bytes written so far into the carrier, the payload is kept to verify the saved
+    /// image
+    fn embed(&mut self) -> Result<(), SteganographyError> {
+        match &self.file {
+            Some((name, modified)) => {
+                let hash = Sha256::digest(&self.payload).into();
+                let envelope = envelope_bytes(name, &self.payload, *modified, &hash)?;
+                self.carrier
+                    .inject(&envelope, PayloadKind::File, &self.options)
+            }
+            None => self
+                .carrier
+                .inject(&self.payload, PayloadKind::Bytes, &self.options),
+        }
+    }
+}
+
+impl Write for PayloadWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let required = self.payload.len() + buf.len();
+        if required > self.capacity {
+            return Err(SteganographyError::Capacity {
+                required,
+                available: self.capacity,
+            }
+            .into());
+        }
+        self.payload.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Reads the payload of an image in chunks (E.g. copied into a file with `io::copy`). A payload
+/// that is not encrypted is read from the pixels as the chunks are requested, an encrypted one
+/// is decrypted when the reader is created
+pub struct PayloadReader<'a> {
+    reader: Box<dyn Read + 'a>,
+}
+
+impl<'a> PayloadReader<'a> {
+    /// # Arguments
+    ///
+    /// * `image` - The image that carries the payload
+    /// * `options` - The password to decrypt the payload (optional). The paths of the options
+    ///   are not used
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no payload is found or if the password cannot decrypt the payload
+    pub fn new(
+        image: &'a DynamicImage,
+        options: &SteganographyExtractOption,
+    ) -> Result<Self, SteganographyError> {
+        PayloadReader::from_carrier(image, options)
+    }
+
+    /// A reader over the payload of any image, see `CarrierImage`
+    fn from_carrier<C: PayloadCarrier>(
+        carrier: &'a C,
+        options: &SteganographyExtractOption,
+    ) -> Result<Self, SteganographyError> {
+        Ok(PayloadReader {
+            reader: open_payload(carrier, &options.password)?,
+        })
+    }
+}
+
+impl Read for PayloadReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.reader.read(buf)
+    }
+}
+
+/// Hide the file referenced by `input_file_path`, with its name and metadata, into the image
+/// referenced by `input_image_path` and save the result at `output_image_path`. The image is
+/// opened as `inject_bytes` does, a JPEG output keeps the file in its coefficients
+///
+/// # Arguments
+///
+/// * `options` - Structure with the file to hide, the images and the encoding to use
+///
+/// # Errors
+///
+/// Returns an error if a file cannot be read or written, if the content does not fit into the
+/// image or if the options cannot be used together
+pub fn inject_file(options: SteganographyInjectOption) -> Result<(), SteganographyError> {
+    let input_file_path = options.input_file_path.clone().ok_or_else(|| {
+        SteganographyError::InvalidOption("The file to hide is required".to_string())
+    })?;
+    let (name, modified) = file_metadata(Path::new(&input_file_path))?;
+    let carrier =
+        CarrierImage::open_for_output(&options.input_image_path, &options.output_image_path)?;
+    let mut writer = PayloadWriter::for_file(carrier, options.clone(), name, modified);
+    io::copy(
+        &mut BufReader::new(File::open(input_file_path)?),
+        &mut writer,
+    )?;
+    writer.embed()?;
+    writer.carrier.save(&options.output_image_path)?;
+    if options.verify {
+        let extracted = CarrierImage::open(&options.output_image_path)
+            .and_then(|carrier| extract_file_from_carrier(&carrier, &options.password))
+            .map(|envelope| envelope.content);
+        verify_payload(&writer.payload, extracted)?;
+    }
+    Ok(())
+}
+
+/// Write the payload of the image referenced by `input_image_path` into the file referenced by
+/// `output_file_path`. The content of a hidden file is written, whatever its original name
+///
+/// # Arguments
+///
+/// * `options` - Structure with the image, the file to create and the password (optional)
+///
+/// # Errors
+///
+/// Returns an error if a file cannot be read or written, if no payload is found or if the
+/// password cannot decrypt the payload
+pub fn extract_file(options: SteganographyExtractOption) -> Result<(), SteganographyError> {
+    let output_file_path = options.output_file_path.clone().ok_or_else(|| {
+        SteganographyError::InvalidOption("The file to create is required".to_string())
+    })?;
+    let carrier = CarrierImage::open(&options.input_image_path)?;
+    let mut reader = PayloadReader::from_carrier(&carrier, &options)?;
+    let mut output = BufWriter::new(File::create(output_file_path)?);
+    io::copy(&mut reader, &mut output)?;
+    output.flush()?;
+    Ok(())
+}
+
+/// Recreate the file hidden into the image referenced by `input_image_path`, with its original
+/// name and last modification, into the directory referenced by `output_dir_path`
+///
+/// # Arguments
+///
+/// * `options` - Structure with the image, the directory and the password (optional)
+///
+/// # Returns
+/// The path of the recreated file
+///
+/// # Errors
+///
+/// Returns an error if the image does not carry a file, if the file cannot be written or if the
+/// content of the file does not match its hash
+pub fn extract_file_to_dir(
+    options: SteganographyExtractOption,
+) -> Result<PathBuf, SteganographyError> {
+    let output_dir_path = options.output_dir_path.clone().ok_or_else(|| {
+        SteganographyError::InvalidOption(
+            "The directory to create the file is required".to_string(),
+        )
+    })?;
+    let carrier = CarrierImage::open(&options.input_image_path)?;
+    extract_file_from_carrier(&carrier, &options.password)?.write_to_dir(output_dir_path)
+}
+
+#[cfg(test)]
+mod test_stream {
+    use super::*;
+    use crate::utils::function::extract_file_from_image;
+    use crate::utils::options::EmbeddingOrder;
+    use crate::utils::palette::palette_format;
+    use crate::utils::palette::test_palette::palette_gif;
+    use std::fs;
+
+    #[test]
+    fn test_write_read_in_chunks() {
+        let payload: Vec<u8> = (0..5000u32).map(|i| (i % 251) as u8).collect();
+        let img = image::open("testAssets/prestine.png").unwrap();
+        let mut writer = PayloadWriter::new(img, SteganographyInjectOption::default());
+        for chunk in payload.chunks(777) {
+            writer.write_all(chunk).unwrap();
+        }
+        let new_img = writer.finish().unwrap();
+        let options = SteganographyExtractOption::default();
+        let mut reader = PayloadReader::new(&new_img, &options).unwrap();
+        let mut result = Vec::new();
+        let mut chunk = [0u8; 100];
+        loop {
+            let count = reader.read(&mut chunk).unwrap();
+            if count == 0 {
+                break;
+            }
+            result.extend_from_slice(&chunk[..count]);
+        }
+        assert_eq!(result, payload);
+    }
+
+    #[test]
+    fn test_write_read_encrypted() {
+        let img = image::open("testAssets/prestine.png").unwrap();
+        let options = SteganographyInjectOption {
+            password: Some("secret".to_string()),
+            order: EmbeddingOrder::Scattered,
+            ..Default::default()
+        };
+        let mut writer = PayloadWriter::new(img, options);
+        io::copy(&mut &b"Streamed secret"[..], &mut writer).unwrap();
+        let new_img = writer.finish().unwrap();
+        let options = SteganographyExtractOption {
+            password: Some("secret".to_string()),
+            ..Default::default()
+        };
+        let mut result = String::new();
+        PayloadReader::new(&new_img, &options)
+            .unwrap()
+            .read_to_string(&mut result)
+            .unwrap();
+        assert_eq!(result, "Streamed secret");
+    }
+
+    #[test]
+    fn test_write_more_than_capacity() {
+        let mut writer = PayloadWriter::new(
+            DynamicImage::new_rgba8(8, 8),
+            SteganographyInjectOption::default(),
+        );
+        writer.write_all(&[0u8; 21]).unwrap();
+        let error: SteganographyError = writer.write_all(&[0u8]).unwrap_err().into();
+        assert!(matches!(
+            error,
+            SteganographyError::Capacity {
+                required: 22,
+                available: 21
+            }
+        ));
+    }
+
+    #[test]
+    fn test_write_compressed_beyond_capacity() {
+        let options = SteganographyInjectOption {
+            compression: Compression::Zstd,
+            ..Default::default()
+        };
+        let mut writer = PayloadWriter::new(DynamicImage::new_rgba8(8, 8), options);
+        writer.write_all(&[0u8; 100]).unwrap();
+        let new_img = writer.finish().unwrap();
+        let mut result = Vec::new();
+        PayloadReader::new(&new_img, &SteganographyExtractOption::default())
+            .unwrap()
+            .read_to_end(&mut result)
+            .unwrap();
+        assert_eq!(result, vec![0u8; 100]);
+    }
+
+    #[test]
+    fn test_write_file_in_chunks() {
+        let image = DynamicImage::new_rgba8(32, 32);
+        let options = SteganographyInjectOption::default();
+        let available = PayloadWriter::new(image.clone(), options.clone()).capacity;
+        let mut writer = PayloadWriter::for_file(
+            CarrierImage::Pixels(image),
+            options,
+            "notes.txt".to_string(),
+            42,
+        );
+        assert_eq!(writer.capacity, available - envelope_overhead("notes.txt"));
+        for chunk in b"Streamed file content".chunks(4) {
+            writer.write_all(chunk).unwrap();
+        }
+        let new_img = writer.finish().unwrap();
+        let envelope =
+            extract_file_from_image(&new_img, &SteganographyExtractOption::default()).unwrap();
+        assert_eq!(envelope.name, "notes.txt");
+        assert_eq!(envelope.modified, 42);
+        assert_eq!(envelope.content, b"Streamed file content");
+    }
+
+    #[test]
+    fn test_write_compressed_above_limit() {
+        let options = SteganographyInjectOption {
+            compression: Compression::Zstd,
+            ..Default::default()
+        };
+        let mut writer = PayloadWriter::new(DynamicImage::new_rgba8(8, 8), options);
+        let limit = decompressed_limit(21) as usize;
+        writer.write_all(&vec![0u8; limit]).unwrap();
+        let error: SteganographyError = writer.write_all(&[0u8]).unwrap_err().into();
+        assert!(matches!(
+            error,
+            SteganographyError::Capacity { required, available }
+                if required == limit + 1 && available == limit
+        ));
+    }
+
+    #[test]
+    fn test_inject_extract_file() {
+        let options = SteganographyInjectOption {
+            input_image_path: "testAssets/prestine.png".to_string(),
+            output_image_path: "testAssets/delete_me_file.png".to_string(),
+            input_file_path: Some("testAssets/message1.txt".to_string()),
+            verify: true,
+            ..Default::default()
+        };
+        inject_file(options).unwrap();
+        let options = SteganographyExtractOption {
+            input_image_path: "testAssets/delete_me_file.png".to_string(),
+            output_file_path: Some("target/delete_me_message1.txt".to_string()),
+            ..Default::default()
+        };
+        extract_file(options).unwrap();
+        assert_eq!(
+            std::fs::read("target/delete_me_message1.txt").unwrap(),
+            std::fs::read("testAssets/message1.txt").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_inject_extract_file_to_dir() {
+        let options = SteganographyInjectOption {
+            input_image_path: "testAssets/prestine.png".to_string(),
+            output_image_path: "testAssets/delete_me_file_to_dir.png".to_string(),
+            input_file_path: Some("testAssets/message1.txt".to_string()),
+            password: Some("secret".to_string()),
+            ..Default::default()
+        };
+        inject_file(options).unwrap();
+        let options = SteganographyExtractOption {
+            input_image_path: "testAssets/delete_me_file_to_dir.png".to_string(),
+            output_dir_path: Some("target/delete_me_to_dir".to_string()),
+            password: Some("secret".to_string()),
+            ..Default::default()
+        };
+        let path = extract_file_to_dir(options).unwrap();
+        assert_eq!(path, PathBuf::from("target/delete_me_to_dir/message1.txt"));
+        assert_eq!(
+            std::fs::read(&path).unwrap(),
+            std::fs::read("testAssets/message1.txt").unwrap()
+        );
+        assert_eq!(
+            std::fs::metadata(&path)
+                .unwrap()
+                .modified()
+                .unwrap()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_secs(),
+            std::fs::metadata("testAssets/message1.txt")
+                .unwrap()
+                .modified()
+                .unwrap()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_secs()
+        );
+    }
+
+    #[test]
+    fn test_inject_extract_file_keeps_palette() {
+        fs::write("target/delete_me_palette_source.gif", palette_gif()).unwrap();
+        let options = SteganographyInjectOption {
+            input_image_path: "target/delete_me_palette_source.gif".to_string(),
+            output_image_path: "target/delete_me_palette_file.gif".to_string(),
+            input_file_path: Some("testAssets/message1.txt".to_string()),
+            verify: true,
+            ..Default::default()
+        };
+        inject_file(options).unwrap();
+        let saved = fs::read("target/delete_me_palette_file.gif").unwrap();
+        assert_eq!(palette_format(&saved), Some(image::ImageFormat::Gif));
+        let options = SteganographyExtractOption {
+            input_image_path: "target/delete_me_palette_file.gif".to_string(),
+            output_dir_path: Some("target/delete_me_palette_to_dir".to_string()),
+            ..Default::default()
+        };
+        let path = extract_file_to_dir(options).unwrap();
+        assert_eq!(
+            fs::read(path).unwrap(),
+            fs::read("testAssets/message1.txt").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_extract_message_to_dir() {
+        let options = SteganographyExtractOption {
+            input_image_path: "testAssets/prestine.png".to_string(),
+            output_dir_path: Some("target/delete_me_to_dir".to_string()),
+            ..Default::default()
+        };
+        assert!(extract_file_to_dir(options).is_err());
+    }
+}